@@ -1,64 +1,319 @@
 pub mod audio_conversion {
-    use std::io::{Cursor, BufReader};
-    use hound::{WavReader, SampleFormat};
-    use std::error::Error;
+    use bytes::Bytes;
+    use std::path::PathBuf;
+    use rubato::{FftFixedIn, Resampler};
+    use std::time::Duration;
+    use thiserror::Error;
+    use crate::levels::levels::Levels;
+    use crate::wav::wav::read_wav;
 
+    /// Input frames the resampler works on at a time.
+    const RESAMPLE_CHUNK: usize = 1024;
+
+    /// Errors from turning an input file into samples.
+    #[derive(Debug, Error)]
+    pub enum ConversionError {
+        #[error("I/O error during conversion: {0}")]
+        Io(#[from] std::io::Error),
+        #[error("failed to start {}: {source}", binary.display())]
+        Spawn {
+            binary: PathBuf,
+            source: std::io::Error,
+        },
+        /// The converter ran but failed; `stderr` holds the end of its output.
+        #[error("ffmpeg exited with {}: {stderr}", code.map_or("a signal".to_string(), |c| format!("code {}", c)))]
+        FFmpeg { code: Option<i32>, stderr: String },
+        /// The converter was killed for taking too long.
+        #[error("ffmpeg did not finish within {} seconds", .0.as_secs())]
+        Timeout(Duration),
+        #[error("invalid WAV data: {0}")]
+        Wav(String),
+        #[error("unsupported audio format: {0}")]
+        UnsupportedFormat(String),
+        /// A native decoder found the input malformed.
+        #[error("failed to decode {format}: {reason}")]
+        Decode { format: &'static str, reason: String },
+    }
+
+    /// Decoded audio and what is known about where it came from.
+    #[derive(Debug, Clone)]
     pub struct AudioData {
+        /// Interleaved samples in [-1, 1]
         pub samples: Vec<f32>,
         pub duration: f64, // Duration in seconds
+        pub sample_rate: u32,
+        pub channels: u16,
+        /// Container of the original file, e.g. "ogg" or "wav", when known
+        pub container: Option<String>,
+        /// Codec of the original audio stream, e.g. "opus" or "pcm_s16le", when known
+        pub codec: Option<String>,
+        pub levels: Levels,
     }
 
-    // AudioConverter trait with a single method to convert audio data to WAV format.
-    // It returns bytes of the converted WAV file (pcm,_s16le, 44100 Hz), not the samples.
-    pub trait AudioConverter {
-        fn convert_audio_to_wav(&self, input_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
+    impl AudioData {
+        /// Wraps interleaved `samples` and measures their duration and levels.
+        pub fn new(samples: Vec<f32>, sample_rate: u32, channels: u16) -> Self {
+            let frames = samples.len() / channels.max(1) as usize;
+            let levels = Levels::measure(&samples, sample_rate, channels);
+            AudioData {
+                duration: frames as f64 / sample_rate as f64,
+                samples,
+                sample_rate,
+                channels,
+                container: None,
+                codec: None,
+                levels,
+            }
+        }
+
+        /// Records the container and codec of the original file.
+        pub fn with_source(mut self, container: Option<String>, codec: Option<String>) -> Self {
+            self.container = container;
+            self.codec = codec;
+            self
+        }
+
+        /// "ogg/opus, 48000 Hz, 1 ch" or as much of it as is known.
+        pub fn describe_format(&self) -> String {
+            let source = match (&self.container, &self.codec) {
+                (Some(container), Some(codec)) => format!("{}/{}, ", container, codec),
+                (Some(known), None) | (None, Some(known)) => format!("{}, ", known),
+                (None, None) => String::new(),
+            };
+            format!("{}{} Hz, {} ch", source, self.sample_rate, self.channels)
+        }
+    }
+
+    /// How samples are stored in converted audio.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SampleFormat {
+        /// Signed 16-bit integers
+        I16,
+        /// 32-bit floats in the range [-1, 1]
+        F32,
+    }
+
+    impl SampleFormat {
+        /// Bytes of one sample.
+        pub fn bytes(self) -> usize {
+            match self {
+                SampleFormat::I16 => 2,
+                SampleFormat::F32 => 4,
+            }
+        }
     }
 
-    pub fn convert_wav_to_samples(wav_bytes: &[u8]) -> Result<AudioData, Box<dyn Error>> {
-        // Create a cursor for the input bytes
-        let cursor = Cursor::new(wav_bytes);
-
-        // Create a buffered reader for the cursor
-        let reader = BufReader::new(cursor);
-
-        // Initialize the WavReader
-        let wav_reader = WavReader::new(reader)?;
-        let mut wr = wav_reader;
-
-        // Convert the WAV data to a Vec<f32> based on the sample format
-        let wav_data: Vec<f32> = match wr.spec().sample_format {
-            SampleFormat::Float => wr.samples::<f32>().map(|s| s.unwrap()).collect(),
-            SampleFormat::Int => {
-                let bits_per_sample = wr.spec().bits_per_sample;
-                match bits_per_sample {
-                    8 => wr.samples::<i8>()
-                        .map(|s| (s.unwrap() as f32) / 128.0)
-                        .collect(),
-                    16 => wr.samples::<i16>()
-                        .map(|s| (s.unwrap() as f32) / 32768.0)
-                        .collect(),
-                    24 => wr.samples::<i32>()
-                        .map(|s| (s.unwrap() as f32) / 8388608.0)
-                        .collect(),
-                    32 => wr.samples::<i32>()
-                        .map(|s| (s.unwrap() as f32) / 2147483648.0)
-                        .collect(),
-                    _ => return Err("Unsupported bit depth".into()),
-                }
+    /// The audio a converter has to produce. Speech recognition engines state
+    /// the spec they need (`SpeechToText::input_spec`), so that converters never
+    /// have to guess it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TargetSpec {
+        pub sample_rate: u32,
+        pub channels: u16,
+        pub sample_format: SampleFormat,
+        /// Seconds of audio after which decoding stops, so that a small file
+        /// can't unpack into more samples than memory holds. `None` decodes
+        /// all of it.
+        pub max_duration_secs: Option<u32>,
+    }
+
+    impl TargetSpec {
+        /// Duration in seconds of `samples` interleaved samples in this spec.
+        pub fn duration(&self, samples: usize) -> f64 {
+            samples as f64 / (self.sample_rate as f64 * self.channels as f64)
+        }
+
+        /// The same spec, decoding no more than `secs` seconds.
+        pub fn with_max_duration(self, secs: u32) -> Self {
+            TargetSpec { max_duration_secs: Some(secs), ..self }
+        }
+
+        /// Interleaved samples at `sample_rate` with `channels` channels that
+        /// `max_duration_secs` allows, or `usize::MAX` without a limit.
+        pub fn max_samples(&self, sample_rate: u32, channels: u16) -> usize {
+            self.max_duration_secs
+                .map_or(usize::MAX, |secs| secs as usize * sample_rate as usize * channels as usize)
+        }
+    }
+
+    /// Kinds of input files, as far as converters need to tell them apart.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum InputFormat {
+        Wav,
+        OggOpus,
+        OggVorbis,
+        /// Ogg with another or an unknown codec
+        Ogg,
+        Mp3,
+        Flac,
+        /// MP4 or M4A, usually with AAC audio
+        Mp4,
+        /// Raw AAC in ADTS frames
+        Aac,
+        Unknown,
+    }
+
+    impl InputFormat {
+        /// The format of a file starting with `head`, or the one its MIME type
+        /// names if the content doesn't tell. Either can be missing or empty.
+        pub fn detect(mime_type: Option<&str>, head: &[u8]) -> InputFormat {
+            match InputFormat::from_magic(head) {
+                InputFormat::Unknown => mime_type.map_or(InputFormat::Unknown, InputFormat::from_mime),
+                format => format,
+            }
+        }
+
+        /// The format from the magic bytes at the start of a file.
+        pub fn from_magic(head: &[u8]) -> InputFormat {
+            if head.len() >= 12 && &head[0..4] == b"RIFF" && &head[8..12] == b"WAVE" {
+                return InputFormat::Wav;
+            }
+            if head.starts_with(b"OggS") {
+                // The first packet follows the page header and its segment table
+                let first_packet = head.get(26).and_then(|&segments| head.get(27 + segments as usize..));
+                return match first_packet {
+                    Some(packet) if packet.starts_with(b"OpusHead") => InputFormat::OggOpus,
+                    Some(packet) if packet.starts_with(b"\x01vorbis") => InputFormat::OggVorbis,
+                    _ => InputFormat::Ogg,
+                };
+            }
+            if head.starts_with(b"fLaC") {
+                return InputFormat::Flac;
+            }
+            if head.len() >= 8 && &head[4..8] == b"ftyp" {
+                return InputFormat::Mp4;
+            }
+            // The sync word of an ADTS frame, which has layer 0
+            if head.len() >= 2 && head[0] == 0xFF && head[1] & 0xF6 == 0xF0 {
+                return InputFormat::Aac;
+            }
+            // An ID3 tag, or the sync word of a frame of MPEG audio layer III
+            if head.starts_with(b"ID3") || (head.len() >= 2 && head[0] == 0xFF && head[1] & 0xE6 == 0xE2) {
+                return InputFormat::Mp3;
+            }
+            InputFormat::Unknown
+        }
+
+        /// The format a MIME type such as `audio/ogg; codecs=opus` stands for.
+        pub fn from_mime(mime_type: &str) -> InputFormat {
+            let mime_type = mime_type.to_ascii_lowercase();
+            let mut parts = mime_type.split(';').map(str::trim);
+            let essence = parts.next().unwrap_or_default();
+            let codecs = parts.find_map(|param| param.strip_prefix("codecs=")).map(|codecs| codecs.trim_matches('"'));
+
+            match (essence, codecs) {
+                ("audio/wav" | "audio/x-wav" | "audio/wave" | "audio/vnd.wave", _) => InputFormat::Wav,
+                ("audio/opus", _) | ("audio/ogg", Some("opus")) => InputFormat::OggOpus,
+                ("audio/vorbis", _) | ("audio/ogg", Some("vorbis")) => InputFormat::OggVorbis,
+                ("audio/ogg" | "application/ogg", _) => InputFormat::Ogg,
+                ("audio/mpeg" | "audio/mp3" | "audio/mpeg3" | "audio/x-mpeg-3", _) => InputFormat::Mp3,
+                ("audio/flac" | "audio/x-flac", _) => InputFormat::Flac,
+                ("audio/mp4" | "audio/m4a" | "audio/x-m4a" | "video/mp4", _) => InputFormat::Mp4,
+                ("audio/aac" | "audio/x-aac" | "audio/aacp", _) => InputFormat::Aac,
+                _ => InputFormat::Unknown,
+            }
+        }
+
+        /// Name of the container in `AudioData::container`.
+        pub fn container(self) -> Option<&'static str> {
+            match self {
+                InputFormat::Wav => Some("wav"),
+                InputFormat::OggOpus | InputFormat::OggVorbis | InputFormat::Ogg => Some("ogg"),
+                InputFormat::Mp3 => Some("mp3"),
+                InputFormat::Flac => Some("flac"),
+                InputFormat::Mp4 => Some("mp4"),
+                InputFormat::Aac => Some("aac"),
+                InputFormat::Unknown => None,
             }
+        }
+    }
+
+    // AudioConverter trait with a single method to convert audio data to WAV format.
+    // It returns bytes of the converted WAV file in the `target` format, not the samples.
+    pub trait AudioConverter: Send + Sync {
+        fn convert_audio_to_wav(&self, input_data: &[u8], target: &TargetSpec) -> Result<Vec<u8>, ConversionError>;
+
+        /// Name of the converter in logs.
+        fn name(&self) -> &'static str;
+
+        /// Whether the converter is meant for files in `format`. Converters that
+        /// read about anything, like ffmpeg, support every format.
+        fn supports(&self, format: InputFormat) -> bool;
+
+        /// Decodes `input_data` into samples in the `target` spec. Goes through
+        /// WAV unless the converter has a more direct way.
+        fn decode_audio(&self, input_data: &[u8], target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            convert_wav_to_samples(&self.convert_audio_to_wav(input_data, target)?)
+        }
+
+        /// Same as `decode_audio` for input handed over to the converter, which
+        /// converters that need to own it can keep without a copy.
+        fn decode_bytes(&self, input_data: Bytes, target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            self.decode_audio(&input_data, target)
+        }
+    }
+
+    /// Interleaved `samples` with `from` channels changed to `to` channels:
+    /// channels averaged into mono, or mono copied to every channel. Other
+    /// layouts can't be mapped without knowing the speaker positions.
+    pub fn remix(samples: &[f32], from: u16, to: u16) -> Option<Vec<f32>> {
+        match (from as usize, to as usize) {
+            (from, to) if from == to => Some(samples.to_vec()),
+            (0, _) | (_, 0) => None,
+            (from, 1) => Some(samples.chunks_exact(from).map(|frame| frame.iter().sum::<f32>() / from as f32).collect()),
+            (1, to) => Some(samples.iter().flat_map(|&sample| std::iter::repeat_n(sample, to)).collect()),
+            _ => None,
+        }
+    }
+
+    /// Interleaved `samples` resampled from `from` Hz to `to` Hz. The result
+    /// is aligned with the input and has as many frames as the same duration
+    /// takes at the new rate.
+    pub fn resample(samples: &[f32], channels: u16, from: u32, to: u32) -> Result<Vec<f32>, ConversionError> {
+        let channels = channels as usize;
+        if from == to || channels == 0 || samples.is_empty() {
+            return Ok(samples.to_vec());
+        }
+        let failed = |e: &dyn std::fmt::Display| {
+            ConversionError::UnsupportedFormat(format!("resampling {} Hz to {} Hz: {}", from, to, e))
         };
 
-        // Calculate the duration of the audio clip
-        let sample_rate = wr.spec().sample_rate as usize;
-        let num_channels = wr.spec().channels as usize;
-        let num_samples = wav_data.len();
+        let mut resampler = FftFixedIn::<f32>::new(from as usize, to as usize, RESAMPLE_CHUNK, 2, channels)
+            .map_err(|e| failed(&e))?;
+        let frames = samples.len() / channels;
+        let wanted = (frames as u64 * to as u64).div_ceil(from as u64) as usize;
+        let delay = resampler.output_delay();
 
-        let duration_seconds = num_samples as f64 / (sample_rate * num_channels) as f64;
+        let input: Vec<Vec<f32>> =
+            (0..channels).map(|channel| samples.iter().skip(channel).step_by(channels).copied().collect()).collect();
+        let mut output = vec![Vec::with_capacity(wanted + delay); channels];
+        let mut position = 0;
+        // Past the end of the input the resampler is fed silence until the
+        // delayed output has come out
+        while output[0].len() < wanted + delay {
+            let needed = resampler.input_frames_next();
+            let chunk = if position + needed <= frames {
+                resampler.process(&input.iter().map(|channel| &channel[position..position + needed]).collect::<Vec<_>>(), None)
+            } else if position < frames {
+                let rest: Vec<&[f32]> = input.iter().map(|channel| &channel[position..]).collect();
+                resampler.process_partial(Some(&rest), None)
+            } else {
+                resampler.process_partial(None::<&[&[f32]]>, None)
+            }
+            .map_err(|e| failed(&e))?;
+            position += needed;
+            output.iter_mut().zip(chunk).for_each(|(channel, chunk)| channel.extend(chunk));
+        }
+
+        let mut resampled = Vec::with_capacity(wanted * channels);
+        for frame in delay..delay + wanted {
+            resampled.extend(output.iter().map(|channel| channel[frame]));
+        }
+        Ok(resampled)
+    }
 
-        // Return an AudioData struct with the samples and duration
-        Ok(AudioData {
-            samples: wav_data,
-            duration: duration_seconds,
-        })
+    /// Reads the samples of a complete WAV file, see `read_wav`.
+    pub fn convert_wav_to_samples(wav_bytes: &[u8]) -> Result<AudioData, ConversionError> {
+        read_wav(wav_bytes)
     }
 }
\ No newline at end of file
@@ -1,38 +1,68 @@
 use std::env;
 use std::error::Error;
+use std::fs;
 use std::time::Instant;
 use log::info;
-use voicebot::audio_conversion::audio_conversion::{convert_wav_to_samples, AudioConverter};
+use voicebot::audio_conversion::audio_conversion::AudioData;
+use voicebot::config::config::{Config, PreprocessStage};
 use voicebot::ffmpeg_converter::audio_conversion::FFMpegAudioConverter;
+use voicebot::hallucination::hallucination::HallucinationFilter;
+use voicebot::preprocess::preprocess::{canonical, format_stages, Pipeline};
 use voicebot::speech_to_text::speech_to_text::{SpeechToText, WhisperSTT};
+use voicebot::subtitles::subtitles::to_paragraphs;
+use voicebot::wer::wer::word_error_rate;
+
+/// Runs the recognition with every preprocessing variant instead of only the
+/// configured stages.
+const COMPARE_FLAG: &str = "--compare-preprocessing";
 
 fn main() {
     pretty_env_logger::init();
 
     let args: Vec<String> = env::args().collect();
+    let compare = args.iter().any(|arg| arg == COMPARE_FLAG);
+    let positional: Vec<&String> = args.iter().skip(1).filter(|arg| *arg != COMPARE_FLAG).collect();
 
     // Check if we have enough arguments
-    if args.len() < 3 {
-        eprintln!("Usage: {} <model> <input>", args[0]);
+    if positional.len() < 2 {
+        eprintln!("Usage: {} <model> <input> [reference.txt] [{}]", args[0], COMPARE_FLAG);
         std::process::exit(1);
     }
 
-    let model = &args[1];
-    let input = &args[2];
+    let model = positional[0];
+    let input = positional[1];
+    let reference = positional.get(2).map(|path| path.as_str());
 
-    run_benchmark(input, model).unwrap()
+    run_benchmark(input, model, reference, compare).unwrap()
 }
 
-fn run_benchmark(input: &str, model: &str) -> Result<(), Box<dyn Error>> {
+fn run_benchmark(input: &str, model: &str, reference: Option<&str>, compare: bool) -> Result<(), Box<dyn Error>> {
     info!("Starting benchmark run");
     info!("Model: {}", model);
     info!("Input: {}", input);
 
-    let conv = FFMpegAudioConverter;
-    let bytes = FFMpegAudioConverter::convert_file_to_wav(input)?;
+    let mut config = Config::read()?;
+    config.whisper.model_path = model.into();
+    config.validate()?;
+
+    let reference = reference.map(fs::read_to_string).transpose()?;
 
-    let audio_data = convert_wav_to_samples(bytes.as_slice())?;
-    let samples = audio_data.samples;
+    let filter = HallucinationFilter::new(&config.hallucinations, &config.vad);
+    let stt = WhisperSTT::new(&config.whisper)?.with_filter(filter);
+    let converter = FFMpegAudioConverter::new(&config.ffmpeg);
+    // Decoding straight from the file lets ffmpeg report the original format
+    let runtime = tokio::runtime::Runtime::new()?;
+    let audio_data = runtime.block_on(converter.decode_file(input, &stt.input_spec()))?;
+
+    let levels = &audio_data.levels;
+    info!("Input format: {}", audio_data.describe_format());
+    info!(
+        "Input levels: peak {:.1} dBFS, RMS {:.1} dBFS, loudness {:.1} LUFS, {:.3}% clipped",
+        levels.peak_db,
+        levels.rms_db,
+        levels.loudness_lufs,
+        levels.clipping_ratio * 100.0
+    );
 
     let total_seconds = audio_data.duration.round() as u32; // Round to nearest second and convert to u32
     let minutes = total_seconds / 60;
@@ -41,20 +71,65 @@ fn run_benchmark(input: &str, model: &str) -> Result<(), Box<dyn Error>> {
     info!("Audio duration: {} minutes, {} seconds ({} samples)",
         minutes,
         seconds,
-        samples.len());
+        audio_data.samples.len());
+
+    let variants = if compare {
+        // No preprocessing, every stage on its own and all of them
+        let mut variants = vec![Vec::new()];
+        variants.extend(PreprocessStage::ALL.iter().map(|&stage| vec![stage]));
+        variants.push(PreprocessStage::ALL.to_vec());
+        variants
+    } else {
+        vec![canonical(&config.preprocess.stages)]
+    };
+
+    let mut results = Vec::new();
+    for stages in &variants {
+        let error_rate = run_variant(&stt, &config, &audio_data, stages, reference.as_deref())?;
+        results.push((format_stages(stages), error_rate));
+    }
+
+    if reference.is_some() && results.len() > 1 {
+        info!("Word error rate by preprocessing:");
+        for (stages, error_rate) in results {
+            info!("  {:<32} {:.2}%", stages, error_rate.unwrap_or_default() * 100.0);
+        }
+    }
+
+    Ok(())
+}
+
+/// Recognises `audio_data` preprocessed with `stages` and returns the word
+/// error rate if there is a reference transcript.
+fn run_variant(
+    stt: &WhisperSTT,
+    config: &Config,
+    audio_data: &AudioData,
+    stages: &[PreprocessStage],
+    reference: Option<&str>,
+) -> Result<Option<f64>, Box<dyn Error>> {
+    let name = format_stages(stages);
+    let pipeline = Pipeline::from_stages(stages, &config.preprocess);
+    let audio = pipeline.apply(audio_data.clone());
+    info!("[{}] Loudness after preprocessing: {:.1} LUFS", name, audio.levels.loudness_lufs);
 
-    let stt = WhisperSTT::new(Some(model))?;
     let start_time = Instant::now();
-    let recognized_text = stt.recognize(&samples);
+    let transcript = stt.transcribe(&audio.samples)?;
     let recognition_duration = start_time.elapsed().as_secs_f64();
+    let recognized_text = transcript.text();
 
-    info!("Recognized text: {}", recognized_text);
+    let paragraphs = to_paragraphs(&transcript.segments, &config.paragraphs, |speaker| format!("Speaker {}", speaker));
+    info!("[{}] Recognized text:\n{}", name, paragraphs);
     // Let's say 100 seconds for 200 seconds of recording
     // then we can say we recognise 2 seconds of recording in one second
     // i.e. 2 seconds of recording in 1 second of real time
-    let real_time_duration = total_seconds as f64 / recognition_duration;
+    let real_time_duration = audio.duration / recognition_duration;
     // send log message with this information
-    info!("Recognition speed: {} seconds of audio in second", real_time_duration);
+    info!("[{}] Recognition speed: {} seconds of audio in second", name, real_time_duration);
 
-    Ok(())
+    let error_rate = reference.map(|reference| word_error_rate(reference, &recognized_text));
+    if let Some(error_rate) = error_rate {
+        info!("[{}] Word error rate: {:.2}%", name, error_rate * 100.0);
+    }
+    Ok(error_rate)
 }
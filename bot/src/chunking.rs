@@ -0,0 +1,143 @@
+pub mod chunking {
+    use crate::config::config::ChunkingConfig;
+    use crate::speech_to_text::speech_to_text::{RecognitionError, RecognitionOptions, Transcript, WhisperSTT};
+    use crate::vad::vad::SpeechRegion;
+
+    /// Most words looked at when removing text repeated by two neighbouring chunks.
+    const MAX_REPEATED_WORDS: usize = 8;
+
+    /// A part of a long recording that is recognised on its own.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Chunk {
+        /// First sample of the chunk, including the overlap with the previous one
+        pub start: usize,
+        /// End of the chunk in samples (exclusive), including the overlap with
+        /// the next one
+        pub end: usize,
+        /// Start of the part of the recording the chunk's text is taken from, in
+        /// seconds. Text from the overlaps belongs to the neighbours.
+        pub keep_from: f64,
+        /// End of that part in seconds
+        pub keep_until: f64,
+    }
+
+    /// Splits `total_samples` of audio into chunks of at most `max_chunk_secs`
+    /// (plus the overlaps). Cuts go into the longest pause between `regions` in
+    /// the second half of each chunk, and only fall inside speech when there is
+    /// no such pause.
+    pub fn plan(total_samples: usize, regions: &[SpeechRegion], sample_rate: u32, config: &ChunkingConfig) -> Vec<Chunk> {
+        let rate = sample_rate as f64;
+        let total = total_samples as f64 / rate;
+        let max = config.max_chunk_secs;
+
+        let mut cuts = vec![0.0];
+        let mut from = 0.0;
+        while total - from > max {
+            let limit = from + max;
+            let pause = regions
+                .windows(2)
+                .filter(|pair| pair[1].start > pair[0].end)
+                .map(|pair| (pair[1].start - pair[0].end, (pair[0].end + pair[1].start) / 2.0))
+                .filter(|&(_, middle)| middle > from + max / 2.0 && middle <= limit)
+                .max_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, middle)| middle);
+            from = pause.unwrap_or(limit);
+            cuts.push(from);
+        }
+        cuts.push(total);
+
+        let to_samples = |secs: f64| ((secs.max(0.0) * rate).round() as usize).min(total_samples);
+        cuts.windows(2)
+            .map(|pair| Chunk {
+                start: to_samples(pair[0] - config.overlap_secs),
+                end: to_samples(pair[1] + config.overlap_secs),
+                keep_from: pair[0],
+                keep_until: pair[1],
+            })
+            .collect()
+    }
+
+    /// Joins the transcripts of `chunks` into one with timestamps from the start
+    /// of the recording.
+    ///
+    /// Segments are kept by the chunk their middle falls into. Words a segment
+    /// repeats from the end of the previous chunk's text, as happens when a
+    /// word in the overlap was heard by both chunks, are removed.
+    pub fn stitch(chunks: &[Chunk], transcripts: Vec<Transcript>, sample_rate: u32) -> Transcript {
+        let mut result = Transcript::default();
+        let last = chunks.len().saturating_sub(1);
+
+        for (i, (chunk, mut transcript)) in chunks.iter().zip(transcripts).enumerate() {
+            transcript.shift(chunk.start as f64 / sample_rate as f64);
+            if result.language.is_none() {
+                result.language = transcript.language;
+            }
+
+            let mut first_in_chunk = true;
+            for mut segment in transcript.segments {
+                let middle = (segment.start + segment.end) / 2.0;
+                if middle < chunk.keep_from || (middle >= chunk.keep_until && i != last) {
+                    continue;
+                }
+
+                if first_in_chunk {
+                    first_in_chunk = false;
+                    if let Some(previous) = result.segments.last() {
+                        let repeated = repeated_words(&previous.text, &segment.text);
+                        if repeated > 0 {
+                            let rest: Vec<&str> = segment.text.split_whitespace().skip(repeated).collect();
+                            if rest.is_empty() {
+                                continue;
+                            }
+                            segment.text = format!(" {}", rest.join(" "));
+                        }
+                    }
+                }
+                result.segments.push(segment);
+            }
+        }
+
+        result
+    }
+
+    /// Number of words at the start of `next` that repeat the end of `previous`,
+    /// ignoring case and punctuation.
+    pub fn repeated_words(previous: &str, next: &str) -> usize {
+        let previous = words(previous);
+        let next = words(next);
+        let longest = MAX_REPEATED_WORDS.min(previous.len()).min(next.len());
+        (1..=longest)
+            .rev()
+            .find(|&n| previous[previous.len() - n..] == next[..n])
+            .unwrap_or(0)
+    }
+
+    /// Recognises a long recording in chunks, in parallel, and stitches the
+    /// result together. `regions` are the speech regions of `samples`.
+    pub fn transcribe_chunked(
+        stt: &WhisperSTT,
+        samples: &[f32],
+        regions: &[SpeechRegion],
+        sample_rate: u32,
+        options: RecognitionOptions,
+        config: &ChunkingConfig,
+    ) -> Result<Transcript, RecognitionError> {
+        let chunks = plan(samples.len(), regions, sample_rate, config);
+        log::info!(
+            "Recognising {:.1}s of audio in {} chunks, {} at a time",
+            samples.len() as f64 / sample_rate as f64,
+            chunks.len(),
+            config.parallel
+        );
+
+        let parts: Vec<&[f32]> = chunks.iter().map(|chunk| &samples[chunk.start..chunk.end]).collect();
+        let transcripts = stt.transcribe_parts(&parts, options, config.parallel)?;
+        Ok(stt.collapse_loops(stitch(&chunks, transcripts, sample_rate)))
+    }
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .map(|word| word.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect())
+            .collect()
+    }
+}
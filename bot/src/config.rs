@@ -0,0 +1,886 @@
+pub mod config {
+    use serde::Deserialize;
+    use std::env;
+    use std::path::{Path, PathBuf};
+    use thiserror::Error;
+    use url::Url;
+
+    /// Environment variable pointing at the TOML configuration file.
+    pub const CONFIG_PATH_VAR: &str = "VOICEBOT_CONFIG";
+
+    /// Configuration file used when `VOICEBOT_CONFIG` is not set. It is optional:
+    /// if it does not exist, defaults and environment variables are used.
+    pub const DEFAULT_CONFIG_PATH: &str = "voicebot.toml";
+
+    /// Longest message Telegram accepts, in UTF-16 code units.
+    pub const TELEGRAM_MESSAGE_LIMIT: usize = 4096;
+
+    #[derive(Debug, Error)]
+    pub enum ConfigError {
+        #[error("failed to read config file {path}: {source}")]
+        Io {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+        #[error("failed to parse config file {path}: {source}")]
+        Parse {
+            path: PathBuf,
+            source: toml::de::Error,
+        },
+        #[error("environment variable {var}={value:?} is invalid: {reason}")]
+        InvalidEnv {
+            var: &'static str,
+            value: String,
+            reason: String,
+        },
+        #[error("invalid configuration value `{field}`: {reason}")]
+        Invalid { field: &'static str, reason: String },
+    }
+
+    /// Top level bot configuration, loaded once at startup.
+    #[derive(Debug, Clone, Default, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        pub telegram: TelegramConfig,
+        pub whisper: WhisperConfig,
+        pub ffmpeg: FFmpegConfig,
+        pub bot: BotConfig,
+        pub eta: EtaConfig,
+        pub storage: StorageConfig,
+        pub export: ExportConfig,
+        pub limits: LimitsConfig,
+        pub followup: FollowUpConfig,
+        pub messages: MessagesConfig,
+        pub vad: VadConfig,
+        pub chunking: ChunkingConfig,
+        pub quality: QualityConfig,
+        pub preprocess: PreprocessConfig,
+        pub diarization: DiarizationConfig,
+        pub hallucinations: HallucinationConfig,
+        pub paragraphs: ParagraphConfig,
+        pub vocabulary: VocabularyConfig,
+    }
+
+    #[derive(Debug, Clone, Default, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct TelegramConfig {
+        /// Bot token. Overridden by `TELOXIDE_TOKEN`.
+        pub token: Option<String>,
+        /// Base URL of a custom Bot API server, e.g. `http://localhost:8081`.
+        /// Overridden by `TELOXIDE_API_URL`.
+        pub api_url: Option<String>,
+        /// Set when the custom server runs with `--local`: files are then read from
+        /// the paths it returns instead of being downloaded, and the 20 MB download
+        /// limit does not apply. The bot must see the server's working directory.
+        pub local_mode: bool,
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct WhisperConfig {
+        /// Path to the GGML model file. Overridden by `GGML`.
+        pub model_path: PathBuf,
+        /// Number of threads whisper uses. Overridden by `WHISPER_THREADS`.
+        pub threads: u16,
+        /// A bigger, slower model offered as "Retry with larger model" under
+        /// transcripts. Overridden by `GGML_LARGER`.
+        pub larger_model_path: Option<PathBuf>,
+    }
+
+    impl Default for WhisperConfig {
+        fn default() -> Self {
+            WhisperConfig {
+                model_path: PathBuf::new(),
+                threads: 4,
+                larger_model_path: None,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct FFmpegConfig {
+        /// Name or path of the ffmpeg binary. Overridden by `FFMPEG`.
+        pub binary: PathBuf,
+        /// ffmpeg is killed when it takes longer than this to decode a file,
+        /// counted from its start, so time spent waiting for input counts too.
+        pub timeout_secs: u64,
+        /// Address space ffmpeg may use, in megabytes. 0 for no limit.
+        pub max_memory_mb: u64,
+        /// CPU time ffmpeg may use, in seconds. 0 for no limit.
+        pub max_cpu_secs: u64,
+        /// Demuxers ffmpeg may use for the input (`-format_whitelist`). Leaves
+        /// out playlists and other formats that refer to further files.
+        pub allowed_formats: Vec<String>,
+    }
+
+    impl Default for FFmpegConfig {
+        fn default() -> Self {
+            FFmpegConfig {
+                binary: PathBuf::from("ffmpeg"),
+                timeout_secs: 300,
+                max_memory_mb: 2048,
+                max_cpu_secs: 600,
+                allowed_formats: [
+                    "aac", "aiff", "amr", "ape", "asf", "au", "caf", "flac", "flv", "matroska,webm", "mov,mp4,m4a,3gp,3g2,mj2",
+                    "mp3", "mpeg", "mpegts", "ogg", "wav", "wv",
+                ]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct BotConfig {
+        /// How many seconds of audio are recognised in one second of wall time,
+        /// used for the expected recognition time. Overridden by `RECORDING_TO_WALL_RATIO`.
+        pub recording_to_wall_ratio: f64,
+        /// Telegram user ids allowed to run admin commands such as /purgecache.
+        /// Overridden by `BOT_ADMINS`, a comma-separated list.
+        pub admins: Vec<u64>,
+    }
+
+    impl Default for BotConfig {
+        fn default() -> Self {
+            BotConfig {
+                recording_to_wall_ratio: 10.0,
+                admins: Vec::new(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct EtaConfig {
+        /// Where the learned recognition speeds are kept between restarts.
+        pub state_path: PathBuf,
+        /// Weight of the newest measurement in the rolling speed estimate, in (0, 1].
+        pub smoothing: f64,
+    }
+
+    impl Default for EtaConfig {
+        fn default() -> Self {
+            EtaConfig {
+                state_path: PathBuf::from("eta.json"),
+                smoothing: 0.3,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct StorageConfig {
+        /// SQLite database with the transcript history.
+        pub database_path: PathBuf,
+    }
+
+    impl Default for StorageConfig {
+        fn default() -> Self {
+            StorageConfig {
+                database_path: PathBuf::from("voicebot.db"),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct ExportConfig {
+        /// Upper bound of one /export archive; larger exports are split into
+        /// several archives. Telegram bots can upload files of up to 50 MB.
+        pub max_archive_bytes: usize,
+    }
+
+    impl Default for ExportConfig {
+        fn default() -> Self {
+            ExportConfig {
+                max_archive_bytes: 45 * 1024 * 1024,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct LimitsConfig {
+        /// Largest file the bot downloads, in bytes.
+        pub max_file_size: u64,
+        /// Longest recording the bot recognises, in seconds.
+        pub max_duration_secs: u32,
+        /// Largest file decoded in memory by the bot itself, in bytes. Larger
+        /// files and videos are streamed through ffmpeg, under its time and
+        /// memory limits. 0 always uses ffmpeg.
+        pub max_native_file_size: u64,
+    }
+
+    impl Default for LimitsConfig {
+        fn default() -> Self {
+            LimitsConfig {
+                max_file_size: 200 * 1024 * 1024,
+                max_duration_secs: 2 * 60 * 60,
+                max_native_file_size: 20 * 1024 * 1024,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct FollowUpConfig {
+        /// Memory kept for decoded recordings, so that the buttons under a
+        /// transcript can recognise it again. The oldest are dropped first.
+        pub max_cached_audio_bytes: usize,
+    }
+
+    impl Default for FollowUpConfig {
+        fn default() -> Self {
+            FollowUpConfig {
+                max_cached_audio_bytes: 256 * 1024 * 1024,
+            }
+        }
+    }
+
+    /// What to do with transcripts that don't fit into one Telegram message.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum LongTextMode {
+        /// Send several messages, up to `messages.split_max_chars`
+        Split,
+        /// Always send a text file
+        Document,
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct MessagesConfig {
+        pub long_text: LongTextMode,
+        /// Length of one message, at most Telegram's limit of 4096.
+        pub max_message_chars: usize,
+        /// Longer texts are sent as a file even in split mode.
+        pub split_max_chars: usize,
+    }
+
+    impl Default for MessagesConfig {
+        fn default() -> Self {
+            MessagesConfig {
+                long_text: LongTextMode::Split,
+                max_message_chars: TELEGRAM_MESSAGE_LIMIT,
+                split_max_chars: 4 * TELEGRAM_MESSAGE_LIMIT,
+            }
+        }
+    }
+
+    /// Tuning of the voice activity detector. The defaults suit voice notes
+    /// recorded on phones.
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct VadConfig {
+        /// Run the detector at all. When disabled, the whole file counts as speech.
+        pub enabled: bool,
+        /// Length of one analysis frame in milliseconds.
+        pub frame_ms: u32,
+        /// How far above the background noise a frame has to be to count as
+        /// speech, in dB.
+        pub noise_margin_db: f64,
+        /// Frames quieter than this (dBFS) are never speech, however quiet the
+        /// background is.
+        pub min_energy_db: f64,
+        /// Frames with a higher zero-crossing rate are treated as noise. White
+        /// noise crosses zero at about every other sample (0.5), voiced speech far
+        /// less often.
+        pub max_zero_crossing_rate: f64,
+        /// How long a region stays open after the last speech frame, which bridges
+        /// short pauses and quiet word endings.
+        pub hangover_ms: u32,
+        /// Shorter regions are dropped as clicks and bumps.
+        pub min_speech_ms: u32,
+        /// Silence kept around the speech when trimming.
+        pub padding_ms: u32,
+    }
+
+    impl Default for VadConfig {
+        fn default() -> Self {
+            VadConfig {
+                enabled: true,
+                frame_ms: 30,
+                noise_margin_db: 10.0,
+                min_energy_db: -50.0,
+                max_zero_crossing_rate: 0.4,
+                hangover_ms: 300,
+                min_speech_ms: 150,
+                padding_ms: 200,
+            }
+        }
+    }
+
+    /// Splitting of long recordings into chunks that are recognised in parallel.
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct ChunkingConfig {
+        pub enabled: bool,
+        /// Recordings with less speech than this (in seconds) are recognised in one go.
+        pub min_duration_secs: f64,
+        /// Longest chunk in seconds. Chunks end in a pause where possible.
+        pub max_chunk_secs: f64,
+        /// Audio each chunk shares with its neighbours on both sides, in seconds,
+        /// so that a word cut at the chunk boundary is heard whole by one of them.
+        pub overlap_secs: f64,
+        /// Chunks recognised at the same time. The whisper threads are shared
+        /// between them.
+        pub parallel: usize,
+    }
+
+    impl Default for ChunkingConfig {
+        fn default() -> Self {
+            ChunkingConfig {
+                enabled: true,
+                min_duration_secs: 120.0,
+                max_chunk_secs: 60.0,
+                overlap_secs: 1.0,
+                parallel: 2,
+            }
+        }
+    }
+
+    /// When users are told that their recording may not be recognised well.
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct QualityConfig {
+        /// Recordings with a lower loudness (LUFS) are reported as very quiet.
+        pub quiet_lufs: f64,
+        /// Recordings with a larger fraction of clipped samples are reported as
+        /// distorted.
+        pub max_clipping_ratio: f64,
+    }
+
+    impl Default for QualityConfig {
+        fn default() -> Self {
+            QualityConfig {
+                quiet_lufs: -40.0,
+                max_clipping_ratio: 0.01,
+            }
+        }
+    }
+
+    /// Steps of the audio preprocessing done before recognition. They always
+    /// run in the order listed here.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum PreprocessStage {
+        /// Removes the DC offset and rumble below `preprocess.high_pass_hz`
+        HighPass,
+        /// Spectral gating noise reduction
+        Denoise,
+        /// Brings the loudness to `preprocess.target_lufs`
+        Normalize,
+    }
+
+    impl PreprocessStage {
+        pub const ALL: [PreprocessStage; 3] = [PreprocessStage::HighPass, PreprocessStage::Denoise, PreprocessStage::Normalize];
+
+        /// Name of the stage in the config file and in /preprocess.
+        pub fn name(self) -> &'static str {
+            match self {
+                PreprocessStage::HighPass => "high_pass",
+                PreprocessStage::Denoise => "denoise",
+                PreprocessStage::Normalize => "normalize",
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<PreprocessStage> {
+            PreprocessStage::ALL.into_iter().find(|stage| stage.name() == name)
+        }
+    }
+
+    /// Cleaning up of the decoded audio before recognition.
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct PreprocessConfig {
+        /// Stages used in chats that haven't chosen their own with /preprocess.
+        pub stages: Vec<PreprocessStage>,
+        /// Cutoff of the high-pass filter in Hz. Speech has little below 100 Hz.
+        pub high_pass_hz: f64,
+        /// Loudness (LUFS) recordings are normalised to.
+        pub target_lufs: f64,
+        /// Most gain normalisation applies, in dB, so that near-silent recordings
+        /// don't turn into loud noise.
+        pub max_gain_db: f64,
+        /// Level (dBFS) the peaks are kept below when normalising.
+        pub peak_limit_db: f64,
+        /// How much the noise reducer attenuates noise, in dB.
+        pub denoise_reduction_db: f64,
+        /// Spectral components less than this many standard deviations above the
+        /// noise level are treated as noise.
+        pub denoise_threshold: f64,
+    }
+
+    impl Default for PreprocessConfig {
+        fn default() -> Self {
+            PreprocessConfig {
+                stages: vec![PreprocessStage::HighPass, PreprocessStage::Normalize],
+                high_pass_hz: 80.0,
+                target_lufs: -23.0,
+                max_gain_db: 30.0,
+                peak_limit_db: -1.0,
+                denoise_reduction_db: 12.0,
+                denoise_threshold: 1.5,
+            }
+        }
+    }
+
+    /// How many speakers diarization tells apart in a recording. Written as
+    /// "off", "auto" or a number in the config file and in /speakers.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(try_from = "String")]
+    pub enum Speakers {
+        /// No diarization
+        Off,
+        /// As many as the recording seems to have, up to `diarization.max_speakers`
+        Auto,
+        /// Exactly this many
+        Count(usize),
+    }
+
+    impl Speakers {
+        pub fn parse(value: &str) -> Option<Speakers> {
+            match value.trim().to_ascii_lowercase().as_str() {
+                "off" => Some(Speakers::Off),
+                "auto" => Some(Speakers::Auto),
+                count => count.parse().ok().filter(|&count| count > 0).map(Speakers::Count),
+            }
+        }
+
+        /// Inverse of `parse`.
+        pub fn name(self) -> String {
+            match self {
+                Speakers::Off => "off".to_string(),
+                Speakers::Auto => "auto".to_string(),
+                Speakers::Count(count) => count.to_string(),
+            }
+        }
+    }
+
+    impl TryFrom<String> for Speakers {
+        type Error = String;
+
+        fn try_from(value: String) -> Result<Self, Self::Error> {
+            Speakers::parse(&value).ok_or_else(|| format!("expected off, auto or a number of speakers, got {:?}", value))
+        }
+    }
+
+    /// Telling speakers apart in recordings of conversations.
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct DiarizationConfig {
+        /// Used in chats that haven't chosen their own with /speakers.
+        pub speakers: Speakers,
+        /// Most speakers told apart, also when a chat asks for more.
+        pub max_speakers: usize,
+        /// Length of the stretches of speech compared with each other, in
+        /// seconds. Each is taken to have a single speaker.
+        pub window_secs: f64,
+        /// Step between the starts of the windows, in seconds.
+        pub hop_secs: f64,
+        /// When estimating the number of speakers, how much evidence it takes
+        /// to tell two voices apart. Higher values find fewer speakers.
+        pub bic_penalty: f64,
+    }
+
+    impl Default for DiarizationConfig {
+        fn default() -> Self {
+            DiarizationConfig {
+                speakers: Speakers::Off,
+                max_speakers: 6,
+                window_secs: 1.5,
+                hop_secs: 0.75,
+                bic_penalty: 2.0,
+            }
+        }
+    }
+
+    /// Clean-up of text whisper makes up, such as phrases repeated in a loop
+    /// or stock outros heard in silence and music.
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct HallucinationConfig {
+        pub enabled: bool,
+        /// Segments are dropped when more than this share of their time has no
+        /// speech according to the voice activity detector.
+        pub max_no_speech: f64,
+        /// A phrase said this many times in a row or more is a loop and is kept
+        /// only once.
+        pub min_repeats: usize,
+        /// Longest phrase, in words, looked for in loops.
+        pub max_phrase_words: usize,
+        /// Segments consisting of only one of these phrases are dropped. Case
+        /// and punctuation are ignored.
+        pub blocklist: Vec<String>,
+    }
+
+    impl Default for HallucinationConfig {
+        fn default() -> Self {
+            HallucinationConfig {
+                enabled: true,
+                max_no_speech: 0.8,
+                min_repeats: 3,
+                max_phrase_words: 8,
+                blocklist: [
+                    "Thank you for watching",
+                    "Thanks for watching",
+                    "Please subscribe to my channel",
+                    "Subtitles by the Amara.org community",
+                    "Продолжение следует",
+                    "Субтитры сделал DimaTorzok",
+                    "Спасибо за просмотр",
+                ]
+                .map(String::from)
+                .to_vec(),
+            }
+        }
+    }
+
+    /// How the text of transcripts is split into paragraphs.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ParagraphMode {
+        /// One block of text, apart from changes of speaker
+        Off,
+        /// A new paragraph after each long pause
+        Pauses,
+        /// Like `Pauses`, with the start time in front of each paragraph
+        Timestamps,
+    }
+
+    impl ParagraphMode {
+        pub const ALL: [ParagraphMode; 3] = [ParagraphMode::Off, ParagraphMode::Pauses, ParagraphMode::Timestamps];
+
+        /// Name of the mode in the config file and in /paragraphs.
+        pub fn name(self) -> &'static str {
+            match self {
+                ParagraphMode::Off => "off",
+                ParagraphMode::Pauses => "pauses",
+                ParagraphMode::Timestamps => "timestamps",
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<ParagraphMode> {
+            ParagraphMode::ALL.into_iter().find(|mode| mode.name() == name)
+        }
+    }
+
+    /// Layout of transcripts sent to chats, exported and printed by the
+    /// benchmark.
+    #[derive(Debug, Clone, Copy, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct ParagraphConfig {
+        /// Used in chats that haven't chosen their own with /paragraphs.
+        pub mode: ParagraphMode,
+        /// A pause of at least this many seconds between segments starts a new
+        /// paragraph.
+        pub pause_secs: f64,
+        /// Paragraphs longer than this many seconds end with the next sentence,
+        /// even without a pause.
+        pub max_paragraph_secs: f64,
+    }
+
+    impl Default for ParagraphConfig {
+        fn default() -> Self {
+            ParagraphConfig {
+                mode: ParagraphMode::Pauses,
+                pause_secs: 1.5,
+                max_paragraph_secs: 60.0,
+            }
+        }
+    }
+
+    impl ParagraphConfig {
+        /// The same layout in `mode`, e.g. the one chosen for a chat.
+        pub fn with_mode(self, mode: ParagraphMode) -> ParagraphConfig {
+            ParagraphConfig { mode, ..self }
+        }
+    }
+
+    /// Limits of the vocabularies chats keep with /vocab.
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct VocabularyConfig {
+        /// Most terms in the vocabulary of a chat. Whisper only sees as many of
+        /// them as fit into half of its context, about 220 tokens.
+        pub max_terms: usize,
+        /// Longest term in characters.
+        pub max_term_chars: usize,
+    }
+
+    impl Default for VocabularyConfig {
+        fn default() -> Self {
+            VocabularyConfig {
+                max_terms: 50,
+                max_term_chars: 50,
+            }
+        }
+    }
+
+    impl Config {
+        /// Loads the configuration the way the bot does: the file named by
+        /// `VOICEBOT_CONFIG` (or `voicebot.toml` if present), then environment
+        /// overrides, then validation.
+        pub fn load() -> Result<Config, ConfigError> {
+            let config = Config::read()?;
+            config.validate()?;
+
+            Ok(config)
+        }
+
+        /// Same as `load`, but without validation, for callers that override some
+        /// values (e.g. from the command line) before calling `validate` themselves.
+        pub fn read() -> Result<Config, ConfigError> {
+            let mut config = match env::var(CONFIG_PATH_VAR) {
+                Ok(path) => Config::from_file(path)?,
+                Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                    Config::from_file(DEFAULT_CONFIG_PATH)?
+                }
+                Err(_) => Config::default(),
+            };
+
+            config.apply_env_overrides(|var| env::var(var).ok())?;
+
+            Ok(config)
+        }
+
+        pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+            let path = path.as_ref();
+            let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+                path: path.to_owned(),
+                source,
+            })?;
+
+            toml::from_str(&contents).map_err(|source| ConfigError::Parse {
+                path: path.to_owned(),
+                source,
+            })
+        }
+
+        /// Applies environment variable overrides. `lookup` is normally `env::var`,
+        /// but can be replaced, e.g. in tests.
+        pub fn apply_env_overrides<F>(&mut self, lookup: F) -> Result<(), ConfigError>
+        where
+            F: Fn(&str) -> Option<String>,
+        {
+            if let Some(token) = lookup("TELOXIDE_TOKEN") {
+                self.telegram.token = Some(token);
+            }
+            if let Some(api_url) = lookup("TELOXIDE_API_URL") {
+                self.telegram.api_url = Some(api_url);
+            }
+            if let Some(model_path) = lookup("GGML") {
+                self.whisper.model_path = PathBuf::from(model_path);
+            }
+            if let Some(model_path) = lookup("GGML_LARGER") {
+                self.whisper.larger_model_path = Some(PathBuf::from(model_path));
+            }
+            if let Some(binary) = lookup("FFMPEG") {
+                self.ffmpeg.binary = PathBuf::from(binary);
+            }
+            if let Some(threads) = lookup("WHISPER_THREADS") {
+                self.whisper.threads = parse_env("WHISPER_THREADS", threads)?;
+            }
+            if let Some(ratio) = lookup("RECORDING_TO_WALL_RATIO") {
+                self.bot.recording_to_wall_ratio = parse_env("RECORDING_TO_WALL_RATIO", ratio)?;
+            }
+            if let Some(admins) = lookup("BOT_ADMINS") {
+                self.bot.admins = admins
+                    .split(',')
+                    .filter(|id| !id.trim().is_empty())
+                    .map(|id| parse_env("BOT_ADMINS", id.to_string()))
+                    .collect::<Result<_, _>>()?;
+            }
+
+            Ok(())
+        }
+
+        /// Checks the values that can be checked without talking to Telegram or
+        /// loading the model.
+        pub fn validate(&self) -> Result<(), ConfigError> {
+            if self.whisper.model_path.as_os_str().is_empty() {
+                return Err(invalid(
+                    "whisper.model_path",
+                    "not set; set it in the config file or via the GGML environment variable",
+                ));
+            }
+            if !self.whisper.model_path.is_file() {
+                return Err(invalid(
+                    "whisper.model_path",
+                    format!("{} does not exist or is not a file", self.whisper.model_path.display()),
+                ));
+            }
+            if let Some(path) = &self.whisper.larger_model_path {
+                if !path.is_file() {
+                    return Err(invalid(
+                        "whisper.larger_model_path",
+                        format!("{} does not exist or is not a file", path.display()),
+                    ));
+                }
+            }
+            self.api_url()?;
+            if self.telegram.local_mode && self.telegram.api_url.is_none() {
+                return Err(invalid(
+                    "telegram.local_mode",
+                    "requires telegram.api_url pointing at a local Bot API server",
+                ));
+            }
+            if self.whisper.threads == 0 {
+                return Err(invalid("whisper.threads", "must be at least 1"));
+            }
+            if !(self.bot.recording_to_wall_ratio.is_finite() && self.bot.recording_to_wall_ratio > 0.0) {
+                return Err(invalid("bot.recording_to_wall_ratio", "must be a positive number"));
+            }
+            if !(self.eta.smoothing > 0.0 && self.eta.smoothing <= 1.0) {
+                return Err(invalid("eta.smoothing", "must be in the range (0, 1]"));
+            }
+            if self.limits.max_file_size == 0 {
+                return Err(invalid("limits.max_file_size", "must be positive"));
+            }
+            if self.limits.max_duration_secs == 0 {
+                return Err(invalid("limits.max_duration_secs", "must be positive"));
+            }
+            if self.export.max_archive_bytes == 0 {
+                return Err(invalid("export.max_archive_bytes", "must be positive"));
+            }
+            if !(1..=TELEGRAM_MESSAGE_LIMIT).contains(&self.messages.max_message_chars) {
+                return Err(invalid(
+                    "messages.max_message_chars",
+                    format!("must be between 1 and {}", TELEGRAM_MESSAGE_LIMIT),
+                ));
+            }
+            if self.messages.split_max_chars < self.messages.max_message_chars {
+                return Err(invalid(
+                    "messages.split_max_chars",
+                    "must not be less than messages.max_message_chars",
+                ));
+            }
+            if self.vad.frame_ms == 0 {
+                return Err(invalid("vad.frame_ms", "must be positive"));
+            }
+            if !(self.vad.max_zero_crossing_rate > 0.0 && self.vad.max_zero_crossing_rate <= 1.0) {
+                return Err(invalid("vad.max_zero_crossing_rate", "must be in the range (0, 1]"));
+            }
+            if !(self.chunking.overlap_secs >= 0.0 && self.chunking.max_chunk_secs > 2.0 * self.chunking.overlap_secs) {
+                return Err(invalid(
+                    "chunking.max_chunk_secs",
+                    "must be positive and more than twice chunking.overlap_secs",
+                ));
+            }
+            if self.chunking.parallel == 0 {
+                return Err(invalid("chunking.parallel", "must be at least 1"));
+            }
+            if !(0.0..=1.0).contains(&self.quality.max_clipping_ratio) {
+                return Err(invalid("quality.max_clipping_ratio", "must be in the range [0, 1]"));
+            }
+            if !(self.preprocess.high_pass_hz > 0.0 && self.preprocess.high_pass_hz < 1000.0) {
+                return Err(invalid("preprocess.high_pass_hz", "must be in the range (0, 1000)"));
+            }
+            if !(0.0..).contains(&self.preprocess.max_gain_db) {
+                return Err(invalid("preprocess.max_gain_db", "must not be negative"));
+            }
+            if !(..=0.0).contains(&self.preprocess.peak_limit_db) {
+                return Err(invalid("preprocess.peak_limit_db", "must not be above 0 dBFS"));
+            }
+            if !(0.0..).contains(&self.preprocess.denoise_reduction_db) {
+                return Err(invalid("preprocess.denoise_reduction_db", "must not be negative"));
+            }
+            if !(2..=20).contains(&self.diarization.max_speakers) {
+                return Err(invalid("diarization.max_speakers", "must be in the range [2, 20]"));
+            }
+            if let Speakers::Count(count) = self.diarization.speakers {
+                if count > self.diarization.max_speakers {
+                    return Err(invalid("diarization.speakers", "must not be above diarization.max_speakers"));
+                }
+            }
+            if !(0.5..=10.0).contains(&self.diarization.window_secs) {
+                return Err(invalid("diarization.window_secs", "must be in the range [0.5, 10]"));
+            }
+            if !(self.diarization.hop_secs > 0.0 && self.diarization.hop_secs <= self.diarization.window_secs) {
+                return Err(invalid("diarization.hop_secs", "must be positive and at most diarization.window_secs"));
+            }
+            if !(self.diarization.bic_penalty.is_finite() && self.diarization.bic_penalty > 0.0) {
+                return Err(invalid("diarization.bic_penalty", "must be positive"));
+            }
+            if !(0.0..=1.0).contains(&self.hallucinations.max_no_speech) {
+                return Err(invalid("hallucinations.max_no_speech", "must be between 0 and 1"));
+            }
+            if self.hallucinations.min_repeats < 2 {
+                return Err(invalid("hallucinations.min_repeats", "must be at least 2"));
+            }
+            if self.hallucinations.max_phrase_words == 0 {
+                return Err(invalid("hallucinations.max_phrase_words", "must be positive"));
+            }
+            let paragraphs = &self.paragraphs;
+            if !(paragraphs.pause_secs.is_finite() && paragraphs.pause_secs > 0.0) {
+                return Err(invalid("paragraphs.pause_secs", "must be positive"));
+            }
+            if !(paragraphs.max_paragraph_secs.is_finite() && paragraphs.max_paragraph_secs > paragraphs.pause_secs) {
+                return Err(invalid("paragraphs.max_paragraph_secs", "must be longer than paragraphs.pause_secs"));
+            }
+            if self.vocabulary.max_terms == 0 {
+                return Err(invalid("vocabulary.max_terms", "must be positive"));
+            }
+            if self.vocabulary.max_term_chars == 0 {
+                return Err(invalid("vocabulary.max_term_chars", "must be positive"));
+            }
+            if self.ffmpeg.binary.as_os_str().is_empty() {
+                return Err(invalid("ffmpeg.binary", "must not be empty"));
+            }
+            if self.ffmpeg.timeout_secs == 0 {
+                return Err(invalid("ffmpeg.timeout_secs", "must be positive"));
+            }
+            if self.ffmpeg.allowed_formats.is_empty() {
+                return Err(invalid("ffmpeg.allowed_formats", "must not be empty"));
+            }
+
+            Ok(())
+        }
+
+        /// Parsed `telegram.api_url`, `None` for the default cloud Bot API.
+        pub fn api_url(&self) -> Result<Option<Url>, ConfigError> {
+            self.telegram
+                .api_url
+                .as_deref()
+                .map(|url| Url::parse(url).map_err(|e| invalid("telegram.api_url", e.to_string())))
+                .transpose()
+        }
+
+        pub fn is_admin(&self, user_id: u64) -> bool {
+            self.bot.admins.contains(&user_id)
+        }
+
+        /// The bot token is only required by the bot itself, not e.g. by the benchmark.
+        pub fn telegram_token(&self) -> Result<&str, ConfigError> {
+            match self.telegram.token.as_deref() {
+                Some(token) if !token.trim().is_empty() => Ok(token),
+                _ => Err(invalid(
+                    "telegram.token",
+                    "not set; set it in the config file or via the TELOXIDE_TOKEN environment variable",
+                )),
+            }
+        }
+    }
+
+    fn invalid<S: Into<String>>(field: &'static str, reason: S) -> ConfigError {
+        ConfigError::Invalid {
+            field,
+            reason: reason.into(),
+        }
+    }
+
+    fn parse_env<T>(var: &'static str, value: String) -> Result<T, ConfigError>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        value.trim().parse().map_err(|e: T::Err| ConfigError::InvalidEnv {
+            var,
+            reason: e.to_string(),
+            value,
+        })
+    }
+}
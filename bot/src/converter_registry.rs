@@ -0,0 +1,129 @@
+pub mod converter_registry {
+    use bytes::Bytes;
+    use crate::audio_conversion::audio_conversion::{
+        AudioConverter, AudioData, ConversionError, InputFormat, TargetSpec,
+    };
+    use crate::config::config::FFmpegConfig;
+    use crate::ffmpeg_converter::audio_conversion::FFMpegAudioConverter;
+    use crate::opus_converter::opus_converter::OpusConverter;
+    use crate::symphonia_converter::symphonia_converter::SymphoniaConverter;
+    use crate::wav::wav::{write_wav, WavConverter};
+
+    /// Converters in order of preference. Each file goes to the first
+    /// converter that supports its format, and on to the next one if that
+    /// fails, so ffmpeg only runs for what the native decoders can't read.
+    #[derive(Default)]
+    pub struct ConverterRegistry {
+        converters: Vec<Box<dyn AudioConverter>>,
+    }
+
+    impl ConverterRegistry {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Adds `converter` after the ones already registered.
+        pub fn then<C: AudioConverter + 'static>(mut self, converter: C) -> Self {
+            self.converters.push(Box::new(converter));
+            self
+        }
+
+        /// WAV, Opus, MP3, FLAC, AAC and Vorbis natively, with ffmpeg for
+        /// everything else.
+        pub fn standard(ffmpeg: &FFmpegConfig) -> Self {
+            ConverterRegistry::new()
+                .then(WavConverter)
+                .then(OpusConverter)
+                .then(SymphoniaConverter)
+                .then(FFMpegAudioConverter::new(ffmpeg))
+        }
+
+        /// Names of the converters that would be tried for `format`, in order.
+        pub fn chain(&self, format: InputFormat) -> Vec<&'static str> {
+            self.supporting(format).map(|converter| converter.name()).collect()
+        }
+
+        /// Whether a converter other than ffmpeg gets the first try at
+        /// `format`. Such files are decoded in memory instead of streamed
+        /// through ffmpeg.
+        pub fn native_first(&self, format: InputFormat) -> bool {
+            self.supporting(format).next().is_some_and(|converter| converter.name() != FFMpegAudioConverter::NAME)
+        }
+
+        /// Decodes `input` with the first converter for its format that
+        /// succeeds. The format is told from the content, or from `mime_type`
+        /// if the content doesn't say.
+        pub fn decode(
+            &self,
+            input: &[u8],
+            mime_type: Option<&str>,
+            target: &TargetSpec,
+        ) -> Result<AudioData, ConversionError> {
+            let format = InputFormat::detect(mime_type, input);
+            self.first_success(format, |converter| converter.decode_audio(input, target))
+        }
+
+        /// Same as `decode` for input handed over to the registry, which spares
+        /// converters that need to own it a copy.
+        pub fn decode_bytes(
+            &self,
+            input: Bytes,
+            mime_type: Option<&str>,
+            target: &TargetSpec,
+        ) -> Result<AudioData, ConversionError> {
+            let format = InputFormat::detect(mime_type, &input);
+            self.first_success(format, |converter| converter.decode_bytes(input.clone(), target))
+        }
+
+        /// The audio of the first converter for `format` that `decode` succeeds
+        /// with, or the last error.
+        fn first_success<F>(&self, format: InputFormat, decode: F) -> Result<AudioData, ConversionError>
+        where
+            F: Fn(&dyn AudioConverter) -> Result<AudioData, ConversionError>,
+        {
+            let mut last_error = None;
+            for converter in self.supporting(format) {
+                match decode(converter) {
+                    Ok(audio) => {
+                        log::info!("Decoded {:?} input with {}", format, converter.name());
+                        return Ok(audio);
+                    }
+                    Err(e) => {
+                        log::warn!("Converter {} failed on {:?} input: {}", converter.name(), format, e);
+                        last_error = Some(e);
+                    }
+                }
+            }
+            Err(last_error.unwrap_or_else(|| {
+                ConversionError::UnsupportedFormat(format!("no converter for {:?} input", format))
+            }))
+        }
+
+        fn supporting(&self, format: InputFormat) -> impl Iterator<Item = &dyn AudioConverter> {
+            self.converters.iter().map(|converter| converter.as_ref()).filter(move |converter| converter.supports(format))
+        }
+    }
+
+    impl AudioConverter for ConverterRegistry {
+        fn convert_audio_to_wav(&self, input_data: &[u8], target: &TargetSpec) -> Result<Vec<u8>, ConversionError> {
+            let audio = self.decode(input_data, None, target)?;
+            Ok(write_wav(&audio.samples, target))
+        }
+
+        fn name(&self) -> &'static str {
+            "registry"
+        }
+
+        fn supports(&self, format: InputFormat) -> bool {
+            self.supporting(format).next().is_some()
+        }
+
+        fn decode_audio(&self, input_data: &[u8], target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            self.decode(input_data, None, target)
+        }
+
+        fn decode_bytes(&self, input_data: Bytes, target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            self.decode_bytes(input_data, None, target)
+        }
+    }
+}
@@ -0,0 +1,531 @@
+pub mod diarization {
+    use std::f32::consts::PI;
+    use std::sync::Arc;
+    use rustfft::num_complex::Complex;
+    use rustfft::{Fft, FftPlanner};
+    use crate::config::config::{DiarizationConfig, Speakers};
+    use crate::speech_to_text::speech_to_text::Segment;
+    use crate::vad::vad::SpeechRegion;
+
+    /// Length of the frames the spectrum is measured on, in seconds.
+    const FRAME_SECS: f64 = 0.025;
+    /// Step between frames, in seconds.
+    const FRAME_HOP_SECS: f64 = 0.01;
+    /// Mel bands of the filterbank, spanning `MIN_HZ` to `MAX_HZ`.
+    const MEL_BANDS: usize = 26;
+    const MIN_HZ: f64 = 60.0;
+    const MAX_HZ: f64 = 7600.0;
+    /// Cepstral coefficients kept. The first one, the overall level, is left
+    /// out so that loudness doesn't tell speakers apart.
+    const CEPSTRA: usize = 12;
+
+    /// Most windows clustered. Longer recordings are sampled with a larger
+    /// hop, since the clustering takes memory quadratic in the windows.
+    const MAX_WINDOWS: usize = 2000;
+
+    /// When estimating the number of speakers, groups with fewer windows than
+    /// this share of all, or than `MIN_SPEAKER_WINDOWS`, are noise rather than
+    /// a speaker of their own. So are groups that never hold two windows in a
+    /// row, which are the windows spanning changes of speaker.
+    const MIN_SPEAKER_SHARE: f64 = 0.05;
+    const MIN_SPEAKER_WINDOWS: usize = 2;
+
+    /// A stretch of the recording attributed to one speaker.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct SpeakerTurn {
+        pub start: f64,
+        pub end: f64,
+        /// Speakers are numbered from 1 in the order they first speak
+        pub speaker: u32,
+    }
+
+    /// A window of speech and the voice characteristics measured in it.
+    #[derive(Debug, Clone)]
+    pub struct Embedding {
+        pub start: f64,
+        pub end: f64,
+        /// Mean and standard deviation of the cepstral coefficients, scaled to
+        /// unit length
+        pub vector: Vec<f32>,
+        stats: FrameStats,
+    }
+
+    /// Sums over the cepstra of a set of frames, enough to fit a Gaussian to
+    /// them. Sums of windows add up to the sums of a group of windows.
+    #[derive(Debug, Clone)]
+    struct FrameStats {
+        count: f64,
+        sum: [f64; CEPSTRA],
+        products: [[f64; CEPSTRA]; CEPSTRA],
+    }
+
+    impl FrameStats {
+        fn new(frames: &[[f32; CEPSTRA]]) -> Self {
+            let mut stats = FrameStats { count: 0.0, sum: [0.0; CEPSTRA], products: [[0.0; CEPSTRA]; CEPSTRA] };
+            for frame in frames {
+                stats.count += 1.0;
+                for i in 0..CEPSTRA {
+                    stats.sum[i] += frame[i] as f64;
+                    for j in 0..CEPSTRA {
+                        stats.products[i][j] += frame[i] as f64 * frame[j] as f64;
+                    }
+                }
+            }
+            stats
+        }
+
+        fn add(&mut self, other: &FrameStats) {
+            self.count += other.count;
+            for i in 0..CEPSTRA {
+                self.sum[i] += other.sum[i];
+                for j in 0..CEPSTRA {
+                    self.products[i][j] += other.products[i][j];
+                }
+            }
+        }
+
+        /// Log determinant of the covariance of the frames, or `None` if it is
+        /// singular.
+        fn log_det_covariance(&self) -> Option<f64> {
+            let mut covariance = [[0.0; CEPSTRA]; CEPSTRA];
+            for (i, row) in covariance.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    let mean_product = self.sum[i] * self.sum[j] / (self.count * self.count);
+                    *value = self.products[i][j] / self.count - mean_product;
+                }
+            }
+            // Cholesky decomposition, the determinant is the squared product
+            // of the diagonal
+            let mut lower = [[0.0; CEPSTRA]; CEPSTRA];
+            let mut log_det = 0.0;
+            for i in 0..CEPSTRA {
+                for j in 0..=i {
+                    let sum: f64 = (0..j).map(|k| lower[i][k] * lower[j][k]).sum();
+                    if i == j {
+                        let diagonal = covariance[i][i] - sum;
+                        if diagonal <= 0.0 {
+                            return None;
+                        }
+                        lower[i][i] = diagonal.sqrt();
+                        log_det += 2.0 * lower[i][i].ln();
+                    } else {
+                        lower[i][j] = (covariance[i][j] - sum) / lower[j][j];
+                    }
+                }
+            }
+            Some(log_det)
+        }
+    }
+
+    /// Bayesian information criterion for two groups of frames having
+    /// different speakers: positive when modelling them with a Gaussian each
+    /// fits better than one for both, by more than the extra parameters cost
+    /// times `penalty`.
+    fn delta_bic(a: &FrameStats, b: &FrameStats, penalty: f64) -> f64 {
+        let mut both = a.clone();
+        both.add(b);
+        let (Some(log_det), Some(log_det_a), Some(log_det_b)) =
+            (both.log_det_covariance(), a.log_det_covariance(), b.log_det_covariance())
+        else {
+            // Too few frames to tell
+            return f64::NEG_INFINITY;
+        };
+        let dimensions = CEPSTRA as f64;
+        let parameters = dimensions + dimensions * (dimensions + 1.0) / 2.0;
+        0.5 * (both.count * log_det - a.count * log_det_a - b.count * log_det_b)
+            - penalty * 0.5 * parameters * both.count.ln()
+    }
+
+    /// Finds who speaks when in mono audio: windows of speech are described by
+    /// their average spectral envelope (MFCCs) and grouped by agglomerative
+    /// clustering, so that windows of the same voice end up together.
+    pub struct Diarizer {
+        config: DiarizationConfig,
+        sample_rate: u32,
+        frame_len: usize,
+        frame_hop: usize,
+        fft: Arc<dyn Fft<f32>>,
+        window: Vec<f32>,
+        filterbank: Vec<Vec<(usize, f32)>>,
+    }
+
+    impl Diarizer {
+        pub fn new(config: &DiarizationConfig, sample_rate: u32) -> Self {
+            let frame_len = (FRAME_SECS * sample_rate as f64).round() as usize;
+            let fft_len = frame_len.next_power_of_two();
+            let window = (0..frame_len).map(|i| 0.54 - 0.46 * (2.0 * PI * i as f32 / (frame_len - 1) as f32).cos()).collect();
+            Diarizer {
+                config: config.clone(),
+                sample_rate,
+                frame_len,
+                frame_hop: (FRAME_HOP_SECS * sample_rate as f64).round() as usize,
+                fft: FftPlanner::new().plan_fft_forward(fft_len),
+                window,
+                filterbank: mel_filterbank(fft_len, sample_rate),
+            }
+        }
+
+        /// Speaker turns of `samples`, looking only at the speech `regions`.
+        /// Returns nothing when diarization is off or there is no speech.
+        pub fn diarize(&self, samples: &[f32], regions: &[SpeechRegion], speakers: Speakers) -> Vec<SpeakerTurn> {
+            let wanted = match speakers {
+                Speakers::Off => return Vec::new(),
+                Speakers::Auto => None,
+                Speakers::Count(count) => Some(count.min(self.config.max_speakers)),
+            };
+            let embeddings = self.embeddings(samples, regions);
+            if embeddings.is_empty() {
+                return Vec::new();
+            }
+
+            let labels = cluster(&embeddings, wanted, &self.config);
+            turns(&embeddings, &smooth(&labels))
+        }
+
+        /// Embeddings of windows of `window_secs` covering the speech regions.
+        /// Regions shorter than a window get one window of their own length.
+        pub fn embeddings(&self, samples: &[f32], regions: &[SpeechRegion]) -> Vec<Embedding> {
+            let speech: f64 = regions.iter().map(SpeechRegion::duration).sum();
+            let window = self.config.window_secs;
+            let hop = self.config.hop_secs.max(speech / MAX_WINDOWS as f64);
+
+            let mut embeddings = Vec::new();
+            for region in regions {
+                let mut start = region.start;
+                loop {
+                    let end = (start + window).min(region.end);
+                    // The tail of a region is covered by the previous window
+                    // unless it is most of a window long
+                    let is_tail = start > region.start && end - start < window / 2.0;
+                    if !is_tail {
+                        let from = (start * self.sample_rate as f64) as usize;
+                        let to = ((end * self.sample_rate as f64) as usize).min(samples.len());
+                        if let Some((vector, stats)) = self.embed(samples.get(from..to).unwrap_or_default()) {
+                            embeddings.push(Embedding { start, end, vector, stats });
+                        }
+                    }
+                    if end >= region.end {
+                        break;
+                    }
+                    start += hop;
+                }
+            }
+            embeddings
+        }
+
+        /// Mean and standard deviation of the cepstrum over the frames of
+        /// `samples`, or `None` if they don't fill a frame.
+        fn embed(&self, samples: &[f32]) -> Option<(Vec<f32>, FrameStats)> {
+            if samples.len() < self.frame_len {
+                return None;
+            }
+            let frames: Vec<[f32; CEPSTRA]> =
+                samples.windows(self.frame_len).step_by(self.frame_hop).map(|frame| self.cepstrum(frame)).collect();
+
+            let count = frames.len() as f32;
+            let mut vector = [0.0; 2 * CEPSTRA];
+            for frame in &frames {
+                for (sum, &value) in vector.iter_mut().zip(frame) {
+                    *sum += value / count;
+                }
+            }
+            for frame in &frames {
+                for i in 0..CEPSTRA {
+                    vector[CEPSTRA + i] += (frame[i] - vector[i]).powi(2) / count;
+                }
+            }
+            vector[CEPSTRA..].iter_mut().for_each(|variance| *variance = variance.sqrt());
+
+            let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+            (norm > 0.0).then(|| (vector.iter().map(|value| value / norm).collect(), FrameStats::new(&frames)))
+        }
+
+        /// Mel-frequency cepstral coefficients 1 to `CEPSTRA` of one frame.
+        fn cepstrum(&self, frame: &[f32]) -> [f32; CEPSTRA] {
+            let mut spectrum = vec![Complex::new(0.0, 0.0); self.fft.len()];
+            for ((bin, &sample), &weight) in spectrum.iter_mut().zip(frame).zip(&self.window) {
+                bin.re = sample * weight;
+            }
+            self.fft.process(&mut spectrum);
+
+            let energies: Vec<f32> = self
+                .filterbank
+                .iter()
+                .map(|band| {
+                    let energy: f32 = band.iter().map(|&(bin, weight)| spectrum[bin].norm_sqr() * weight).sum();
+                    (energy + 1e-10).ln()
+                })
+                .collect();
+
+            // DCT-II of the log energies
+            let mut cepstrum = [0.0; CEPSTRA];
+            for (k, coefficient) in cepstrum.iter_mut().enumerate() {
+                let k = k + 1;
+                *coefficient = energies
+                    .iter()
+                    .enumerate()
+                    .map(|(n, energy)| energy * (PI * k as f32 * (n as f32 + 0.5) / MEL_BANDS as f32).cos())
+                    .sum();
+            }
+            cepstrum
+        }
+    }
+
+    /// Triangular mel filters over the bins of an FFT of `fft_len`, each as
+    /// the bins it covers and their weights.
+    fn mel_filterbank(fft_len: usize, sample_rate: u32) -> Vec<Vec<(usize, f32)>> {
+        let mel = |hz: f64| 2595.0 * (1.0 + hz / 700.0).log10();
+        let hz = |mel: f64| 700.0 * (10f64.powf(mel / 2595.0) - 1.0);
+        let max_hz = MAX_HZ.min(sample_rate as f64 / 2.0);
+        let (low, high) = (mel(MIN_HZ), mel(max_hz));
+        let edges: Vec<f64> = (0..MEL_BANDS + 2)
+            .map(|i| hz(low + (high - low) * i as f64 / (MEL_BANDS + 1) as f64) * fft_len as f64 / sample_rate as f64)
+            .collect();
+
+        edges
+            .windows(3)
+            .map(|edge| {
+                let (left, centre, right) = (edge[0], edge[1], edge[2]);
+                (left.ceil() as usize..=right.floor() as usize)
+                    .filter_map(|bin| {
+                        let position = bin as f64;
+                        let weight = if position <= centre {
+                            (position - left) / (centre - left)
+                        } else {
+                            (right - position) / (right - centre)
+                        };
+                        (weight > 0.0).then_some((bin, weight as f32))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+        1.0 - a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>()
+    }
+
+    /// Groups windows by average-linkage agglomerative clustering of their
+    /// embeddings and returns the group of each, numbered from 0 in order of
+    /// their first window.
+    ///
+    /// With `speakers` given the groups are merged down to that many.
+    /// Otherwise merging goes on down to `max_speakers` groups, and beyond
+    /// that for as long as the information criterion doesn't tell the two
+    /// groups being merged apart.
+    pub fn cluster(embeddings: &[Embedding], speakers: Option<usize>, config: &DiarizationConfig) -> Vec<usize> {
+        let n = embeddings.len();
+        if n == 0 {
+            return Vec::new();
+        }
+
+        let vectors: Vec<&[f32]> = embeddings.iter().map(|embedding| embedding.vector.as_slice()).collect();
+        let mut parents: Vec<usize> = (0..n).collect();
+        let mut stats: Vec<FrameStats> = embeddings.iter().map(|embedding| embedding.stats.clone()).collect();
+        let mut groups = n;
+        for (a, b, _) in linkage(&vectors) {
+            let (a, b) = (find(&mut parents, a), find(&mut parents, b));
+            let merge = match speakers {
+                Some(speakers) => groups > speakers.max(1),
+                None => groups > config.max_speakers || delta_bic(&stats[a], &stats[b], config.bic_penalty) <= 0.0,
+            };
+            if !merge {
+                break;
+            }
+            parents[b] = a;
+            let merged = stats[b].clone();
+            stats[a].add(&merged);
+            groups -= 1;
+        }
+
+        let mut labels: Vec<usize> = (0..n).map(|i| find(&mut parents, i)).collect();
+        if speakers.is_none() {
+            absorb_small_groups(&vectors, &mut labels);
+        }
+
+        let mut numbers: Vec<usize> = Vec::new();
+        labels
+            .iter()
+            .map(|label| match numbers.iter().position(|known| known == label) {
+                Some(number) => number,
+                None => {
+                    numbers.push(*label);
+                    numbers.len() - 1
+                }
+            })
+            .collect()
+    }
+
+    /// The merges of average-linkage clustering as (cluster, cluster,
+    /// distance), closest first. Clusters are named after one of their
+    /// members. Uses the nearest-neighbour chain algorithm, which needs time
+    /// and memory quadratic in the number of vectors.
+    fn linkage(vectors: &[&[f32]]) -> Vec<(usize, usize, f32)> {
+        let n = vectors.len();
+        let mut distances = vec![0f32; n * n];
+        for i in 0..n {
+            for j in i + 1..n {
+                let distance = cosine_distance(vectors[i], vectors[j]);
+                distances[i * n + j] = distance;
+                distances[j * n + i] = distance;
+            }
+        }
+
+        let mut active = vec![true; n];
+        let mut sizes = vec![1usize; n];
+        let mut merges = Vec::with_capacity(n - 1);
+        let mut chain: Vec<usize> = Vec::new();
+        while merges.len() + 1 < n {
+            if chain.is_empty() {
+                chain.push(active.iter().position(|&active| active).unwrap());
+            }
+            let a = chain[chain.len() - 1];
+            let previous = (chain.len() >= 2).then(|| chain[chain.len() - 2]);
+
+            // The nearest active cluster, preferring the previous link on ties
+            let mut nearest = previous.unwrap_or(usize::MAX);
+            let mut nearest_distance = previous.map_or(f32::INFINITY, |previous| distances[a * n + previous]);
+            for c in (0..n).filter(|&c| active[c] && c != a) {
+                if distances[a * n + c] < nearest_distance {
+                    nearest = c;
+                    nearest_distance = distances[a * n + c];
+                }
+            }
+
+            if Some(nearest) != previous {
+                chain.push(nearest);
+                continue;
+            }
+            chain.truncate(chain.len() - 2);
+            let b = nearest;
+            merges.push((a, b, nearest_distance));
+
+            // `a` stands for the merged cluster from now on
+            let (size_a, size_b) = (sizes[a] as f32, sizes[b] as f32);
+            for c in (0..n).filter(|&c| active[c] && c != a && c != b) {
+                let distance = (size_a * distances[a * n + c] + size_b * distances[b * n + c]) / (size_a + size_b);
+                distances[a * n + c] = distance;
+                distances[c * n + a] = distance;
+            }
+            active[b] = false;
+            sizes[a] += sizes[b];
+        }
+
+        merges.sort_by(|x, y| x.2.total_cmp(&y.2));
+        merges
+    }
+
+    fn find(parents: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parents[root] != root {
+            root = parents[root];
+        }
+        parents[i] = root;
+        root
+    }
+
+    /// Moves the windows of groups too small to be a speaker to the group
+    /// whose centre is nearest.
+    fn absorb_small_groups(vectors: &[&[f32]], labels: &mut [usize]) {
+        let n = vectors.len();
+        let min_size = ((n as f64 * MIN_SPEAKER_SHARE).ceil() as usize).max(MIN_SPEAKER_WINDOWS);
+        let mut sizes = std::collections::HashMap::new();
+        labels.iter().for_each(|&label| *sizes.entry(label).or_insert(0usize) += 1);
+        let consecutive = |label: usize| labels.windows(2).any(|pair| pair[0] == label && pair[1] == label);
+        let large: Vec<usize> = sizes
+            .iter()
+            .filter(|(&label, &size)| size >= min_size && consecutive(label))
+            .map(|(&label, _)| label)
+            .collect();
+        if large.is_empty() || large.len() == sizes.len() {
+            return;
+        }
+
+        let centres: Vec<(usize, Vec<f32>)> = large
+            .iter()
+            .map(|&label| {
+                let mut centre = vec![0f32; vectors[0].len()];
+                for (vector, _) in vectors.iter().zip(labels.iter()).filter(|(_, &l)| l == label) {
+                    centre.iter_mut().zip(vector.iter()).for_each(|(sum, value)| *sum += value);
+                }
+                let norm = centre.iter().map(|value| value * value).sum::<f32>().sqrt().max(f32::MIN_POSITIVE);
+                (label, centre.iter().map(|value| value / norm).collect())
+            })
+            .collect();
+
+        for (vector, label) in vectors.iter().zip(labels.iter_mut()) {
+            if large.contains(label) {
+                continue;
+            }
+            *label = centres
+                .iter()
+                .min_by(|x, y| cosine_distance(vector, &x.1).total_cmp(&cosine_distance(vector, &y.1)))
+                .map(|(label, _)| *label)
+                .unwrap();
+        }
+    }
+
+    /// Removes single windows that disagree with both neighbours, which are
+    /// more likely a cough or a noise than a change of speaker.
+    fn smooth(labels: &[usize]) -> Vec<usize> {
+        let mut smoothed = labels.to_vec();
+        for i in 1..labels.len().saturating_sub(1) {
+            if labels[i - 1] == labels[i + 1] && labels[i] != labels[i - 1] {
+                smoothed[i] = labels[i - 1];
+            }
+        }
+        smoothed
+    }
+
+    /// Joins labelled windows into turns. Where windows overlap, the boundary
+    /// is halfway between their centres.
+    fn turns(embeddings: &[Embedding], labels: &[usize]) -> Vec<SpeakerTurn> {
+        let mut turns: Vec<SpeakerTurn> = Vec::new();
+        for (i, (embedding, &label)) in embeddings.iter().zip(labels).enumerate() {
+            let centre = |e: &Embedding| (e.start + e.end) / 2.0;
+            let mut start = embedding.start;
+            if let Some(previous) = i.checked_sub(1).map(|i| &embeddings[i]) {
+                if previous.end > embedding.start {
+                    start = (centre(previous) + centre(embedding)) / 2.0;
+                }
+            }
+            let speaker = label as u32 + 1;
+            match turns.last_mut() {
+                Some(last) if last.speaker == speaker => last.end = embedding.end,
+                Some(last) => {
+                    last.end = last.end.min(start);
+                    turns.push(SpeakerTurn { start, end: embedding.end, speaker });
+                }
+                None => turns.push(SpeakerTurn { start, end: embedding.end, speaker }),
+            }
+        }
+        turns
+    }
+
+    /// Gives each segment the speaker it overlaps most, or the nearest one if
+    /// it overlaps none.
+    pub fn assign_speakers(segments: &mut [Segment], turns: &[SpeakerTurn]) {
+        if turns.is_empty() {
+            return;
+        }
+        for segment in segments {
+            let mut overlaps: Vec<(u32, f64)> = Vec::new();
+            for turn in turns {
+                let overlap = segment.end.min(turn.end) - segment.start.max(turn.start);
+                if overlap > 0.0 {
+                    match overlaps.iter_mut().find(|(speaker, _)| *speaker == turn.speaker) {
+                        Some((_, total)) => *total += overlap,
+                        None => overlaps.push((turn.speaker, overlap)),
+                    }
+                }
+            }
+
+            let middle = (segment.start + segment.end) / 2.0;
+            let distance = |turn: &SpeakerTurn| (turn.start - middle).max(middle - turn.end).max(0.0);
+            segment.speaker = match overlaps.iter().max_by(|x, y| x.1.total_cmp(&y.1)) {
+                Some(&(speaker, _)) => Some(speaker),
+                None => turns.iter().min_by(|x, y| distance(x).total_cmp(&distance(y))).map(|turn| turn.speaker),
+            };
+        }
+    }
+}
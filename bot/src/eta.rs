@@ -0,0 +1,125 @@
+pub mod eta {
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Learned recognition speed for one model and thread count.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+    pub struct SpeedStats {
+        /// Seconds of audio recognised per second of wall time, as if the job
+        /// had the machine to itself.
+        pub speed: f64,
+        /// How many jobs contributed to the estimate.
+        pub samples: u64,
+    }
+
+    /// Keeps a rolling (exponentially weighted) estimate of the recognition speed
+    /// per model and thread count, optionally persisted as JSON so that it
+    /// survives restarts.
+    pub struct SpeedEstimator {
+        path: Option<PathBuf>,
+        smoothing: f64,
+        fallback_ratio: f64,
+        speeds: HashMap<String, SpeedStats>,
+    }
+
+    impl SpeedEstimator {
+        /// Creates an estimator without any history.
+        ///
+        /// # Arguments
+        /// * `fallback_ratio` - speed used while there is no history for a key.
+        /// * `smoothing` - weight of the newest measurement, in (0, 1].
+        pub fn new(fallback_ratio: f64, smoothing: f64) -> Self {
+            SpeedEstimator {
+                path: None,
+                smoothing,
+                fallback_ratio,
+                speeds: HashMap::new(),
+            }
+        }
+
+        /// Creates an estimator backed by `path`. A missing file is not an error,
+        /// the history just starts empty.
+        pub fn load(path: PathBuf, fallback_ratio: f64, smoothing: f64) -> Result<Self, Box<dyn Error>> {
+            let speeds = if path.exists() {
+                serde_json::from_str(&fs::read_to_string(&path)?)?
+            } else {
+                HashMap::new()
+            };
+
+            Ok(SpeedEstimator {
+                path: Some(path),
+                smoothing,
+                fallback_ratio,
+                speeds,
+            })
+        }
+
+        pub fn key(model: &str, threads: u16) -> String {
+            format!("{}@{}", model, threads)
+        }
+
+        pub fn stats(&self, key: &str) -> Option<SpeedStats> {
+            self.speeds.get(key).copied()
+        }
+
+        /// Seconds of audio per second of wall time for a single job.
+        pub fn speed(&self, key: &str) -> f64 {
+            self.speeds
+                .get(key)
+                .map(|s| s.speed)
+                .unwrap_or(self.fallback_ratio)
+        }
+
+        /// Expected wall time in seconds to recognise `audio_seconds` of audio
+        /// while `concurrent_jobs` jobs (including this one) share the machine.
+        pub fn estimate(&self, key: &str, audio_seconds: f64, concurrent_jobs: usize) -> f64 {
+            audio_seconds / self.speed(key) * concurrent_jobs.max(1) as f64
+        }
+
+        /// Records a finished job and persists the updated history.
+        ///
+        /// `concurrent_jobs` is the number of jobs that were running when this one
+        /// started; the measured speed is scaled by it so that the stored value
+        /// describes a job running alone.
+        pub fn record(
+            &mut self,
+            key: &str,
+            audio_seconds: f64,
+            wall_seconds: f64,
+            concurrent_jobs: usize,
+        ) -> Result<(), Box<dyn Error>> {
+            if audio_seconds <= 0.0 || wall_seconds <= 0.0 {
+                return Ok(());
+            }
+
+            let measured = audio_seconds / wall_seconds * concurrent_jobs.max(1) as f64;
+            let smoothing = self.smoothing;
+            self.speeds
+                .entry(key.to_owned())
+                .and_modify(|s| {
+                    s.speed = smoothing * measured + (1.0 - smoothing) * s.speed;
+                    s.samples += 1;
+                })
+                .or_insert(SpeedStats {
+                    speed: measured,
+                    samples: 1,
+                });
+
+            self.save()
+        }
+
+        fn save(&self) -> Result<(), Box<dyn Error>> {
+            if let Some(path) = &self.path {
+                // Write to a temporary file first so a crash never leaves a truncated file
+                let tmp_path = path.with_extension("tmp");
+                fs::write(&tmp_path, serde_json::to_string_pretty(&self.speeds)?)?;
+                fs::rename(&tmp_path, path)?;
+            }
+
+            Ok(())
+        }
+    }
+}
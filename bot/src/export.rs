@@ -0,0 +1,120 @@
+pub mod export {
+    use crate::config::config::ParagraphConfig;
+    use crate::storage::storage::StoredTranscript;
+    use crate::subtitles::subtitles::{to_paragraphs, to_srt, with_speaker_labels};
+    use serde::Serialize;
+    use std::error::Error;
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    /// Rough upper bound of the zip headers written for one file.
+    const ENTRY_OVERHEAD: usize = 256;
+
+    /// File name and contents of one archive entry.
+    type ArchiveFile = (String, Vec<u8>);
+
+    /// Metadata written next to each transcript as JSON.
+    #[derive(Serialize)]
+    struct TranscriptMetadata<'a> {
+        id: i64,
+        chat_id: i64,
+        message_id: i32,
+        sender_id: Option<u64>,
+        sender_name: Option<&'a str>,
+        /// RFC 3339 date of the original message
+        date: String,
+        duration: f64,
+        language: Option<&'a str>,
+        model: &'a str,
+    }
+
+    /// Packs transcripts into zip archives, one `.txt` and one `.json` file per
+    /// transcript plus an `.srt` file when segment timestamps are known.
+    ///
+    /// A new archive is started whenever the next transcript could push the
+    /// current one over `max_archive_bytes`, so every archive fits into an upload
+    /// limit (unless a single transcript is larger than that on its own).
+    ///
+    /// The text of transcripts with segments is laid out in paragraphs as in
+    /// `layout`. Turns and subtitles of segments with a known speaker start
+    /// with `speaker_label(speaker)`.
+    pub fn build_archives(
+        transcripts: &[StoredTranscript],
+        max_archive_bytes: usize,
+        layout: &ParagraphConfig,
+        speaker_label: &dyn Fn(u32) -> String,
+    ) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let mut archives = Vec::new();
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        // Uncompressed size plus headers: an upper bound of the archive size
+        let mut size = 0;
+        let mut entries = 0;
+
+        for transcript in transcripts {
+            let files = transcript_files(transcript, layout, speaker_label)?;
+            let files_size: usize = files
+                .iter()
+                .map(|(name, contents)| name.len() * 2 + contents.len() + ENTRY_OVERHEAD)
+                .sum();
+
+            if entries > 0 && size + files_size > max_archive_bytes {
+                archives.push(writer.finish()?.into_inner());
+                writer = ZipWriter::new(Cursor::new(Vec::new()));
+                size = 0;
+                entries = 0;
+            }
+
+            let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+            for (name, contents) in files {
+                writer.start_file(name, options)?;
+                writer.write_all(&contents)?;
+            }
+            size += files_size;
+            entries += 1;
+        }
+
+        if entries > 0 {
+            archives.push(writer.finish()?.into_inner());
+        }
+
+        Ok(archives)
+    }
+
+    fn transcript_files(
+        transcript: &StoredTranscript,
+        layout: &ParagraphConfig,
+        speaker_label: &dyn Fn(u32) -> String,
+    ) -> Result<Vec<ArchiveFile>, Box<dyn Error>> {
+        let date = chrono::DateTime::from_timestamp(transcript.created_at, 0).unwrap_or_default();
+        let base_name = format!("{}_{}", date.format("%Y-%m-%d_%H-%M-%S"), transcript.id);
+
+        let metadata = TranscriptMetadata {
+            id: transcript.id,
+            chat_id: transcript.chat_id,
+            message_id: transcript.message_id,
+            sender_id: transcript.sender_id,
+            sender_name: transcript.sender_name.as_deref(),
+            date: date.to_rfc3339(),
+            duration: transcript.duration,
+            language: transcript.language.as_deref(),
+            model: &transcript.model,
+        };
+
+        let segments = transcript.segments.as_deref().filter(|s| !s.is_empty());
+        let text = match segments {
+            Some(segments) => to_paragraphs(segments, layout, speaker_label),
+            None => transcript.text.clone(),
+        };
+        let mut files = vec![
+            (format!("{}.txt", base_name), text.into_bytes()),
+            (format!("{}.json", base_name), serde_json::to_vec_pretty(&metadata)?),
+        ];
+        if let Some(segments) = segments {
+            let segments = with_speaker_labels(segments, speaker_label);
+            files.push((format!("{}.srt", base_name), to_srt(&segments).into_bytes()));
+        }
+
+        Ok(files)
+    }
+}
@@ -1,54 +1,105 @@
 pub mod audio_conversion {
-    use std::error::Error;
-    use std::process::{Command, Stdio};
-    use std::io::{Write, Read};
+    use std::ffi::OsStr;
+    use std::path::{Path, PathBuf};
+    use std::pin::Pin;
+    use std::process::{Child as StdChild, Command, ExitStatus, Stdio};
+    use std::io::{self, Write, Read};
     use std::fs::File;
+    use std::task::{Context, Poll};
+    use std::time::{Duration, Instant};
     use tempfile::NamedTempFile;
-    use crate::audio_conversion::audio_conversion::AudioConverter;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::process::{Child, ChildStdin};
+    use tokio::task::JoinHandle;
+    use crate::audio_conversion::audio_conversion::{AudioConverter, AudioData, ConversionError, InputFormat, SampleFormat, TargetSpec};
+    use crate::config::config::FFmpegConfig;
 
-    pub struct FFMpegAudioConverter;
+    /// How much of ffmpeg's stderr is kept in errors, from the end.
+    const STDERR_TAIL_LINES: usize = 20;
+
+    /// How often a blocking conversion checks whether ffmpeg has finished.
+    const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+    /// Runs ffmpeg with a time limit, resource limits (on Unix) and only the
+    /// input formats and protocols it needs for the file at hand.
+    pub struct FFMpegAudioConverter {
+        binary: PathBuf,
+        timeout: Duration,
+        max_memory_bytes: u64,
+        max_cpu_secs: u64,
+        format_whitelist: String,
+    }
 
     impl AudioConverter for FFMpegAudioConverter {
-        fn convert_audio_to_wav(&self, input_data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        fn convert_audio_to_wav(&self, input_data: &[u8], target: &TargetSpec) -> Result<Vec<u8>, ConversionError> {
             // Create a temporary file to store the input data
             let mut input_file = NamedTempFile::new()?;
             input_file.write_all(input_data)?;
-            let input_path = input_file
-                .path()
-                .to_str()
-                .ok_or_else(|| "Invalid input file path")?;
 
-            Self::convert_file_to_wav(input_path)
+            self.convert_file_to_wav(input_file.path(), target)
+        }
+
+        fn name(&self) -> &'static str {
+            Self::NAME
+        }
+
+        fn supports(&self, _format: InputFormat) -> bool {
+            true
         }
     }
 
     impl FFMpegAudioConverter {
-        pub fn convert_file_to_wav(input_path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        /// Name of the converter in logs and converter chains.
+        pub const NAME: &'static str = "ffmpeg";
+
+        pub fn new(config: &FFmpegConfig) -> Self {
+            FFMpegAudioConverter {
+                binary: config.binary.clone(),
+                timeout: Duration::from_secs(config.timeout_secs),
+                max_memory_bytes: config.max_memory_mb.saturating_mul(1024 * 1024),
+                max_cpu_secs: config.max_cpu_secs,
+                format_whitelist: config.allowed_formats.join(","),
+            }
+        }
+
+        pub fn convert_file_to_wav<P: AsRef<Path>>(&self, input_path: P, target: &TargetSpec) -> Result<Vec<u8>, ConversionError> {
             // Create another temporary file to store the output WAV data
             let output_file = NamedTempFile::new()?;
-            let output_path = output_file.path().to_str().ok_or("Invalid output file path")?;
+            let output_path = output_file.path();
 
             // Run FFmpeg command to convert input file to WAV
-            let status = Command::new("ffmpeg")
+            let mut child = self
+                .command(input_path.as_ref().as_os_str(), "file", target)
                 .arg("-y")  // Overwrite output file if it exists
-                .arg("-i")
-                .arg(input_path) // Input file path
-                .arg("-ar")
-                .arg("16000") // Sample rate 16 kHz
-                .arg("-ac")
-                .arg("1") // 1 channels (mono)
                 .arg("-f")
                 .arg("wav") // Output format
-                .arg("-acodec")
-                .arg("pcm_s16le") // PCM signed 16-bit little-endian
                 .arg(output_path) // Output file path
-                .stderr(Stdio::null()) // Suppress FFmpeg output
+                .stdin(Stdio::null())
+                .stderr(Stdio::piped()) // Kept for error reports
                 .stdout(Stdio::null())
-                .status()?;
+                .spawn()
+                .map_err(|source| ConversionError::Spawn {
+                    binary: self.binary.clone(),
+                    source,
+                })?;
+
+            // Read on the side, ffmpeg stalls when the pipe is full
+            let stderr = child.stderr.take().map(|mut pipe| {
+                std::thread::spawn(move || {
+                    let mut data = Vec::new();
+                    let _ = pipe.read_to_end(&mut data);
+                    data
+                })
+            });
+            let status = wait_with_timeout(&mut child, self.timeout)?;
+            let stderr = stderr.map(|reader| reader.join().unwrap_or_default()).unwrap_or_default();
 
             // Check if the FFmpeg process completed successfully
             if !status.success() {
-                return Err("FFmpeg conversion failed".into());
+                return Err(ConversionError::FFmpeg {
+                    code: status.code(),
+                    stderr: stderr_tail(&stderr),
+                });
             }
 
             // Read the WAV data from the output file
@@ -57,5 +108,315 @@ pub mod audio_conversion {
 
             Ok(output_wav)
         }
+
+        /// Starts ffmpeg decoding whatever is written to the returned decoder.
+        /// The input can arrive in pieces, e.g. while it is still downloading.
+        pub fn decode_stream(&self, target: &TargetSpec) -> Result<StreamingDecoder, ConversionError> {
+            self.spawn_decoder(OsStr::new("pipe:0"), "pipe", Stdio::piped(), target)
+        }
+
+        /// Decodes `input` without writing it to disk.
+        ///
+        /// Containers that keep their index at the end, such as most MP4 files,
+        /// can't be decoded from a pipe; `decode_buffered` handles those.
+        pub async fn decode(&self, input: &[u8], target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            let mut decoder = self.decode_stream(target)?;
+            // ffmpeg not reading its input counts towards the timeout as well
+            let written = tokio::time::timeout_at(decoder.deadline(), decoder.write_all(input))
+                .await
+                .map_err(|_| ConversionError::Timeout(self.timeout))?;
+            // ffmpeg stops reading when it gives up, the reason is reported by `finish`
+            match written {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+                _ => decoder.finish().await,
+            }
+        }
+
+        /// Decodes the file at `path`. Unlike a pipe, a file can be seeked in,
+        /// so this works for every container ffmpeg supports.
+        pub async fn decode_file<P: AsRef<Path>>(&self, path: P, target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            self.spawn_decoder(path.as_ref().as_os_str(), "file", Stdio::null(), target)?.finish().await
+        }
+
+        /// Decodes `input` through a temporary file, for input `decode` fails on.
+        pub async fn decode_buffered(&self, input: &[u8], target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            let mut input_file = NamedTempFile::new()?;
+            input_file.write_all(input)?;
+            self.decode_file(input_file.path(), target).await
+        }
+
+        /// Finishes `decoder`, which was fed `input`, and decodes `input` again
+        /// from a file if ffmpeg couldn't read it from the pipe, e.g. an MP4 file
+        /// with its index at the end. A timeout or ffmpeg failing to start is
+        /// returned as it is, retrying would only take as long again.
+        pub async fn finish_stream(
+            &self,
+            decoder: StreamingDecoder,
+            input: &[u8],
+            target: &TargetSpec,
+        ) -> Result<AudioData, ConversionError> {
+            match decoder.finish().await {
+                Err(e @ (ConversionError::Timeout(_) | ConversionError::Spawn { .. })) => Err(e),
+                Err(e) => {
+                    log::info!("Decoding from a pipe failed, retrying from a file: {}", e);
+                    self.decode_buffered(input, target).await
+                }
+                decoded => decoded,
+            }
+        }
+
+        /// An ffmpeg command reading `input` through `protocol` and converting it
+        /// to `target`, up to the output container and path.
+        fn command(&self, input: &OsStr, protocol: &str, target: &TargetSpec) -> Command {
+            let mut command = Command::new(&self.binary);
+            command
+                .arg("-nostdin") // Never wait for input on stdin, pipe:0 input excepted
+                .arg("-hide_banner")
+                // Input formats such as playlists can refer to other files and URLs,
+                // which are not allowed to be opened
+                .arg("-protocol_whitelist")
+                .arg(protocol)
+                .arg("-format_whitelist")
+                .arg(&self.format_whitelist)
+                .arg("-i")
+                .arg(input)
+                .arg("-ar")
+                .arg(target.sample_rate.to_string())
+                .arg("-ac")
+                .arg(target.channels.to_string())
+                .arg("-acodec")
+                .arg(format!("pcm_{}", raw_format(target.sample_format)));
+            if let Some(secs) = target.max_duration_secs {
+                command.arg("-t").arg(secs.to_string());
+            }
+            limit_resources(&mut command, self.max_memory_bytes, self.max_cpu_secs);
+            command
+        }
+
+        fn spawn_decoder(
+            &self,
+            input: &OsStr,
+            protocol: &str,
+            stdin: Stdio,
+            target: &TargetSpec,
+        ) -> Result<StreamingDecoder, ConversionError> {
+            let mut child = tokio::process::Command::from(self.command(input, protocol, target))
+                .arg("-f")
+                .arg(raw_format(target.sample_format)) // Raw samples, no container
+                .arg("pipe:1")
+                .stdin(stdin)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|source| ConversionError::Spawn {
+                    binary: self.binary.clone(),
+                    source,
+                })?;
+
+            // Both pipes are drained all the time, ffmpeg stalls when one of them is full
+            let stdout = child.stdout.take().map(read_all);
+            let stderr = child.stderr.take().map(read_all);
+            Ok(StreamingDecoder {
+                stdin: child.stdin.take(),
+                child,
+                stdout,
+                stderr,
+                timeout: self.timeout,
+                deadline: tokio::time::Instant::now() + self.timeout,
+                target: *target,
+            })
+        }
+    }
+
+    /// A running ffmpeg process decoding the bytes written to it into samples
+    /// in the target spec. Write the whole input, then call `finish`. Dropping
+    /// the decoder kills the process.
+    pub struct StreamingDecoder {
+        child: Child,
+        stdin: Option<ChildStdin>,
+        stdout: Option<JoinHandle<io::Result<Vec<u8>>>>,
+        stderr: Option<JoinHandle<io::Result<Vec<u8>>>>,
+        timeout: Duration,
+        /// When the timeout, counted from the start of ffmpeg, runs out
+        deadline: tokio::time::Instant,
+        target: TargetSpec,
+    }
+
+    impl StreamingDecoder {
+        /// When ffmpeg has used up the configured timeout. Writing the input
+        /// takes from the same time, so callers feeding it should stop by then.
+        pub fn deadline(&self) -> tokio::time::Instant {
+            self.deadline
+        }
+
+        /// Ends the input and waits for ffmpeg to decode the rest, until the
+        /// `deadline` at most.
+        pub async fn finish(mut self) -> Result<AudioData, ConversionError> {
+            if let Some(mut stdin) = self.stdin.take() {
+                // Fails if ffmpeg has already exited, which the exit status explains better
+                let _ = stdin.shutdown().await;
+            }
+            let status = match tokio::time::timeout_at(self.deadline, self.child.wait()).await {
+                Ok(status) => status?,
+                Err(_) => {
+                    let _ = self.child.kill().await;
+                    return Err(ConversionError::Timeout(self.timeout));
+                }
+            };
+            let stdout = collect(self.stdout.take()).await?;
+            let stderr = collect(self.stderr.take()).await?;
+
+            if !status.success() {
+                return Err(ConversionError::FFmpeg {
+                    code: status.code(),
+                    stderr: stderr_tail(&stderr),
+                });
+            }
+
+            let samples: Vec<f32> = match self.target.sample_format {
+                SampleFormat::I16 => stdout
+                    .chunks_exact(2)
+                    .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0)
+                    .collect(),
+                SampleFormat::F32 => stdout
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    .collect(),
+            };
+            let (container, codec) = input_format(&String::from_utf8_lossy(&stderr));
+            Ok(AudioData::new(samples, self.target.sample_rate, self.target.channels).with_source(container, codec))
+        }
+
+        fn stdin(&mut self) -> io::Result<Pin<&mut ChildStdin>> {
+            match self.stdin.as_mut() {
+                Some(stdin) => Ok(Pin::new(stdin)),
+                None => Err(io::ErrorKind::BrokenPipe.into()),
+            }
+        }
+    }
+
+    impl AsyncWrite for StreamingDecoder {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            match self.stdin() {
+                Ok(stdin) => stdin.poll_write(cx, buf),
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            match self.stdin() {
+                Ok(stdin) => stdin.poll_flush(cx),
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            match self.stdin() {
+                Ok(stdin) => stdin.poll_shutdown(cx),
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        }
+    }
+
+    /// ffmpeg's name of the raw little-endian format, which with `pcm_` prepended
+    /// is also the name of the codec.
+    fn raw_format(format: SampleFormat) -> &'static str {
+        match format {
+            SampleFormat::I16 => "s16le",
+            SampleFormat::F32 => "f32le",
+        }
+    }
+
+    /// Waits for `child` to exit, killing it once `timeout` has passed.
+    fn wait_with_timeout(child: &mut StdChild, timeout: Duration) -> Result<ExitStatus, ConversionError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status);
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(ConversionError::Timeout(timeout));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Limits the address space and CPU time of the process `command` starts.
+    /// A limit of 0 means none.
+    #[cfg(unix)]
+    fn limit_resources(command: &mut Command, max_memory_bytes: u64, max_cpu_secs: u64) {
+        use std::os::unix::process::CommandExt;
+
+        fn rlimit(soft: u64, hard: u64) -> libc::rlimit {
+            libc::rlimit {
+                rlim_cur: soft as libc::rlim_t,
+                rlim_max: hard as libc::rlim_t,
+            }
+        }
+
+        // SAFETY: only calls setrlimit, which is async-signal-safe, between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                if max_memory_bytes > 0 && libc::setrlimit(libc::RLIMIT_AS, &rlimit(max_memory_bytes, max_memory_bytes)) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // SIGXCPU at the soft limit, SIGKILL a second later if that is ignored
+                if max_cpu_secs > 0 && libc::setrlimit(libc::RLIMIT_CPU, &rlimit(max_cpu_secs, max_cpu_secs + 1)) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn limit_resources(_command: &mut Command, _max_memory_bytes: u64, _max_cpu_secs: u64) {}
+
+    fn read_all<R: AsyncRead + Unpin + Send + 'static>(mut pipe: R) -> JoinHandle<io::Result<Vec<u8>>> {
+        tokio::spawn(async move {
+            let mut data = Vec::new();
+            pipe.read_to_end(&mut data).await?;
+            Ok(data)
+        })
+    }
+
+    async fn collect(reader: Option<JoinHandle<io::Result<Vec<u8>>>>) -> io::Result<Vec<u8>> {
+        match reader {
+            Some(reader) => reader.await.map_err(io::Error::other)?,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Container and audio codec of the input as ffmpeg reports them on stderr,
+    /// from the lines `Input #0, ogg, from 'pipe:0':` and
+    /// `Stream #0:0: Audio: opus, 48000 Hz, mono, fltp`.
+    pub fn input_format(stderr: &str) -> (Option<String>, Option<String>) {
+        let mut container = None;
+        let mut codec = None;
+        for line in stderr.lines() {
+            let line = line.trim();
+            // The output is described the same way after the input
+            if line.starts_with("Output #") {
+                break;
+            }
+            if let Some(rest) = line.strip_prefix("Input #0, ") {
+                container = rest.rsplit_once(", from ").map(|(name, _)| name.to_string());
+            } else if let Some((_, rest)) = line.split_once(": Audio: ") {
+                if codec.is_none() && line.starts_with("Stream #0:") {
+                    codec = rest.split(|c: char| c == ',' || c.is_whitespace()).next().map(str::to_string);
+                }
+            }
+        }
+        (container, codec.filter(|codec| !codec.is_empty()))
+    }
+
+    /// Last `STDERR_TAIL_LINES` lines of ffmpeg's output, where the actual error is.
+    fn stderr_tail(stderr: &[u8]) -> String {
+        let stderr = String::from_utf8_lossy(stderr);
+        let lines: Vec<&str> = stderr.lines().collect();
+        lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n")
     }
 }
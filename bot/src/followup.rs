@@ -0,0 +1,185 @@
+pub mod followup {
+    use crate::config::config::{PreprocessStage, Speakers};
+    use crate::speech_to_text::speech_to_text::Transcript;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::Arc;
+
+    /// Prefix of the callback data of follow-up buttons.
+    const CALLBACK_PREFIX: &str = "fu";
+
+    /// Actions offered as inline buttons under a transcript.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum FollowUp {
+        Summarize,
+        Translate,
+        Subtitles,
+        Timestamps,
+        RetryLarger,
+    }
+
+    impl FollowUp {
+        pub const ALL: [FollowUp; 5] = [
+            FollowUp::Summarize,
+            FollowUp::Translate,
+            FollowUp::Subtitles,
+            FollowUp::Timestamps,
+            FollowUp::RetryLarger,
+        ];
+
+        fn id(self) -> &'static str {
+            match self {
+                FollowUp::Summarize => "sum",
+                FollowUp::Translate => "tr",
+                FollowUp::Subtitles => "srt",
+                FollowUp::Timestamps => "ts",
+                FollowUp::RetryLarger => "retry",
+            }
+        }
+
+        /// Message catalog key of the button label.
+        pub fn label_key(self) -> &'static str {
+            match self {
+                FollowUp::Summarize => "followup.summarize",
+                FollowUp::Translate => "followup.translate",
+                FollowUp::Subtitles => "followup.subtitles",
+                FollowUp::Timestamps => "followup.timestamps",
+                FollowUp::RetryLarger => "followup.retry_larger",
+            }
+        }
+
+        /// Callback data of the button for the recording in message `message_id`.
+        /// The chat is known from the message the button is attached to.
+        pub fn callback_data(self, message_id: i32) -> String {
+            format!("{}:{}:{}", CALLBACK_PREFIX, self.id(), message_id)
+        }
+
+        /// Inverse of `callback_data`.
+        pub fn parse(data: &str) -> Option<(FollowUp, i32)> {
+            let mut parts = data.split(':');
+            if parts.next()? != CALLBACK_PREFIX {
+                return None;
+            }
+            let id = parts.next()?;
+            let action = FollowUp::ALL.into_iter().find(|action| action.id() == id)?;
+            let message_id = parts.next()?.parse().ok()?;
+            if parts.next().is_some() {
+                return None;
+            }
+            Some((action, message_id))
+        }
+    }
+
+    /// A recognised recording, kept so that follow-up actions don't need the
+    /// file to be downloaded and decoded again.
+    #[derive(Debug, Clone)]
+    pub struct CachedRecording {
+        /// Telegram's `file_unique_id` of the recording
+        pub file_unique_id: String,
+        /// Duration of the recording in seconds
+        pub duration: f64,
+        /// Where `samples` start in the recording, in seconds. Silence around
+        /// the speech is not kept.
+        pub offset: f64,
+        pub samples: Arc<[f32]>,
+        /// Preprocessing `samples` went through
+        pub preprocessing: Vec<PreprocessStage>,
+        /// How many speakers the transcript tells apart
+        pub speakers: Speakers,
+        pub transcript: Transcript,
+        /// Model the transcript was made with
+        pub model: String,
+    }
+
+    impl CachedRecording {
+        fn size(&self) -> usize {
+            std::mem::size_of_val(&*self.samples)
+        }
+    }
+
+    /// Recently recognised recordings keyed by chat and message id. Holds at most
+    /// `max_bytes` of samples and drops the least recently used ones first.
+    pub struct RecordingCache {
+        max_bytes: usize,
+        bytes: usize,
+        entries: HashMap<(i64, i32), CachedRecording>,
+        /// Keys from least to most recently used
+        order: VecDeque<(i64, i32)>,
+    }
+
+    impl RecordingCache {
+        pub fn new(max_bytes: usize) -> Self {
+            RecordingCache {
+                max_bytes,
+                bytes: 0,
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+            }
+        }
+
+        /// Adds a recording. Recordings larger than the whole cache are not kept.
+        pub fn insert(&mut self, chat_id: i64, message_id: i32, recording: CachedRecording) {
+            let key = (chat_id, message_id);
+            self.remove(key);
+
+            let size = recording.size();
+            if size > self.max_bytes {
+                return;
+            }
+            while self.bytes + size > self.max_bytes {
+                match self.order.pop_front() {
+                    Some(oldest) => {
+                        if let Some(evicted) = self.entries.remove(&oldest) {
+                            self.bytes -= evicted.size();
+                        }
+                    }
+                    None => break,
+                }
+            }
+
+            self.bytes += size;
+            self.entries.insert(key, recording);
+            self.order.push_back(key);
+        }
+
+        pub fn get(&mut self, chat_id: i64, message_id: i32) -> Option<CachedRecording> {
+            let key = (chat_id, message_id);
+            let recording = self.entries.get(&key)?.clone();
+            self.touch(key);
+            Some(recording)
+        }
+
+        /// Replaces the transcript of a cached recording, e.g. after it was
+        /// recognised again with a better model.
+        pub fn update_transcript(&mut self, chat_id: i64, message_id: i32, transcript: Transcript, model: &str) {
+            if let Some(recording) = self.entries.get_mut(&(chat_id, message_id)) {
+                recording.transcript = transcript;
+                recording.model = model.to_string();
+            }
+        }
+
+        pub fn len(&self) -> usize {
+            self.entries.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.entries.is_empty()
+        }
+
+        /// Bytes of samples currently held.
+        pub fn bytes(&self) -> usize {
+            self.bytes
+        }
+
+        fn touch(&mut self, key: (i64, i32)) {
+            self.order.retain(|k| *k != key);
+            self.order.push_back(key);
+        }
+
+        fn remove(&mut self, key: (i64, i32)) {
+            if let Some(removed) = self.entries.remove(&key) {
+                self.bytes -= removed.size();
+                self.order.retain(|k| *k != key);
+            }
+        }
+    }
+}
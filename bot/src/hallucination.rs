@@ -0,0 +1,189 @@
+pub mod hallucination {
+    use crate::config::config::{HallucinationConfig, VadConfig};
+    use crate::speech_to_text::speech_to_text::Segment;
+    use crate::vad::vad::{SpeechRegion, Vad};
+
+    /// Loops of a single word need this many words in all, so that "no, no,
+    /// no" stays as it was said.
+    const MIN_LOOP_WORDS: usize = 4;
+
+    /// Removes text whisper makes up rather than hears: segments over silence
+    /// or music, stock phrases from its training subtitles and phrases repeated
+    /// in a loop.
+    ///
+    /// whisper-rs doesn't expose whisper's no-speech probability, so it is
+    /// estimated from the voice activity detector instead.
+    pub struct HallucinationFilter {
+        config: HallucinationConfig,
+        vad: VadConfig,
+        /// The blocklist in the form `normalize` gives
+        blocklist: Vec<String>,
+    }
+
+    impl HallucinationFilter {
+        pub fn new(config: &HallucinationConfig, vad: &VadConfig) -> Self {
+            HallucinationFilter {
+                config: config.clone(),
+                vad: vad.clone(),
+                blocklist: config.blocklist.iter().map(|phrase| normalize(phrase)).collect(),
+            }
+        }
+
+        /// Cleans up `segments` recognised from `samples`, whose timestamps are
+        /// relative to the start of the samples.
+        pub fn apply(&self, segments: Vec<Segment>, samples: &[f32], sample_rate: u32) -> Vec<Segment> {
+            self.collapse(self.drop_made_up(segments, samples, sample_rate))
+        }
+
+        /// Same as `apply`, with the speech `regions` already detected.
+        pub fn clean(&self, segments: Vec<Segment>, regions: &[SpeechRegion]) -> Vec<Segment> {
+            self.collapse(self.drop_segments(segments, regions))
+        }
+
+        /// Only the checks of `apply` that look at one segment at a time: speech
+        /// and the blocklist. Parts of a longer recording go through this, and
+        /// through `collapse` once they are put together, so that loops running
+        /// from one part into the next are seen whole.
+        pub fn drop_made_up(&self, segments: Vec<Segment>, samples: &[f32], sample_rate: u32) -> Vec<Segment> {
+            if !self.config.enabled || segments.is_empty() {
+                return segments;
+            }
+            let regions = Vad::new(&self.vad, sample_rate).detect(samples);
+            self.drop_segments(segments, &regions)
+        }
+
+        /// Keeps only the first of a phrase repeated in a loop, see `collapse_loops`.
+        pub fn collapse(&self, segments: Vec<Segment>) -> Vec<Segment> {
+            if !self.config.enabled {
+                return segments;
+            }
+            let count = segments.len();
+            let collapsed = collapse_loops(segments, self.config.min_repeats, self.config.max_phrase_words);
+            if collapsed.len() < count {
+                log::info!("Dropped {} of {} segments as repeated in a loop", count - collapsed.len(), count);
+            }
+            collapsed
+        }
+
+        fn drop_segments(&self, segments: Vec<Segment>, regions: &[SpeechRegion]) -> Vec<Segment> {
+            if !self.config.enabled {
+                return segments;
+            }
+            let count = segments.len();
+            let heard: Vec<Segment> = segments
+                .into_iter()
+                .filter(|segment| {
+                    let no_speech = no_speech_share(segment, regions);
+                    let keep = no_speech <= self.config.max_no_speech;
+                    if !keep {
+                        log::debug!("Dropping {:?}, {:.0}% without speech", segment.text, no_speech * 100.0);
+                    }
+                    keep
+                })
+                .filter(|segment| {
+                    let keep = !self.blocklist.contains(&normalize(&segment.text));
+                    if !keep {
+                        log::debug!("Dropping blocklisted {:?}", segment.text);
+                    }
+                    keep
+                })
+                .collect();
+
+            if heard.len() < count {
+                log::info!("Dropped {} of {} segments as hallucinated", count - heard.len(), count);
+            }
+            heard
+        }
+    }
+
+    /// Share of the segment's time outside of the speech `regions`, from 0 to 1.
+    pub fn no_speech_share(segment: &Segment, regions: &[SpeechRegion]) -> f64 {
+        let duration = segment.end - segment.start;
+        if duration <= 0.0 {
+            let inside = regions.iter().any(|region| region.start <= segment.start && segment.start <= region.end);
+            return if inside { 0.0 } else { 1.0 };
+        }
+        let speech: f64 = regions
+            .iter()
+            .map(|region| (segment.end.min(region.end) - segment.start.max(region.start)).max(0.0))
+            .sum();
+        (1.0 - speech / duration).clamp(0.0, 1.0)
+    }
+
+    /// Keeps only the first of a phrase of up to `max_words` words said
+    /// `min_repeats` times or more in a row, also across segments. Segments left
+    /// without words are dropped.
+    pub fn collapse_loops(segments: Vec<Segment>, min_repeats: usize, max_words: usize) -> Vec<Segment> {
+        // Every word with the segment it belongs to
+        let words: Vec<(usize, &str)> = segments
+            .iter()
+            .enumerate()
+            .flat_map(|(i, segment)| segment.text.split_whitespace().map(move |word| (i, word)))
+            .collect();
+        let keys: Vec<String> = words.iter().map(|(_, word)| normalize(word)).collect();
+
+        let mut removed = vec![false; words.len()];
+        let mut i = 0;
+        while i < keys.len() {
+            // The phrase starting here that repeats over the most words
+            let mut best: Option<(usize, usize)> = None;
+            for length in 1..=max_words.min(keys.len() - i) {
+                let phrase = &keys[i..i + length];
+                let mut repeats = 1;
+                while keys[i + repeats * length..].starts_with(phrase) {
+                    repeats += 1;
+                }
+                let covered = repeats * length;
+                if repeats >= min_repeats
+                    && covered >= MIN_LOOP_WORDS
+                    && best.is_none_or(|(_, best_covered)| covered > best_covered)
+                {
+                    best = Some((length, covered));
+                }
+            }
+            match best {
+                Some((length, covered)) => {
+                    removed[i + length..i + covered].iter_mut().for_each(|removed| *removed = true);
+                    i += covered;
+                }
+                None => i += 1,
+            }
+        }
+        if !removed.contains(&true) {
+            return segments;
+        }
+
+        let mut kept: Vec<Vec<String>> = vec![Vec::new(); segments.len()];
+        let mut changed = vec![false; segments.len()];
+        for (&(segment, word), &removed) in words.iter().zip(&removed) {
+            if removed {
+                changed[segment] = true;
+            } else {
+                kept[segment].push(word.to_string());
+            }
+        }
+
+        let mut collapsed = Vec::with_capacity(segments.len());
+        for (i, mut segment) in segments.into_iter().enumerate() {
+            if !changed[i] {
+                collapsed.push(segment);
+            } else if !kept[i].is_empty() {
+                // Whisper starts segments with a space, which is kept
+                let space = if segment.text.starts_with(char::is_whitespace) { " " } else { "" };
+                segment.text = format!("{}{}", space, kept[i].join(" "));
+                collapsed.push(segment);
+            }
+        }
+        collapsed
+    }
+
+    /// Lower case words without punctuation, separated by single spaces.
+    fn normalize(text: &str) -> String {
+        let words: Vec<String> = text
+            .split_whitespace()
+            .map(|word| word.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect::<String>())
+            .filter(|word| !word.is_empty())
+            .collect();
+        words.join(" ")
+    }
+}
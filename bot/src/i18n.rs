@@ -0,0 +1,110 @@
+pub mod i18n {
+    use std::collections::HashMap;
+    use std::fmt::Display;
+    use std::sync::OnceLock;
+
+    /// Message catalogs, one flat TOML table of `key = "template"` per language.
+    /// Templates refer to their arguments as `{name}`.
+    const CATALOGS: [(Language, &str); 2] = [
+        (Language::English, include_str!("../locales/en.toml")),
+        (Language::Russian, include_str!("../locales/ru.toml")),
+    ];
+
+    /// Languages the bot talks to users in.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum Language {
+        English,
+        Russian,
+    }
+
+    impl Language {
+        pub const ALL: [Language; 2] = [Language::English, Language::Russian];
+
+        /// Used when nothing better is known, and for missing translations.
+        pub const DEFAULT: Language = Language::English;
+
+        /// Two-letter ISO 639-1 code, as used by Telegram.
+        pub fn code(self) -> &'static str {
+            match self {
+                Language::English => "en",
+                Language::Russian => "ru",
+            }
+        }
+
+        /// Matches an IETF language tag such as `ru` or `en-US` by its primary subtag.
+        pub fn from_code(code: &str) -> Option<Language> {
+            let primary = code.split(['-', '_']).next()?.to_ascii_lowercase();
+            Language::ALL.into_iter().find(|lang| lang.code() == primary)
+        }
+
+        /// Picks the language for a chat: the chat's own setting first, then the
+        /// language of the user's Telegram client, then the default.
+        pub fn resolve(chat_setting: Option<&str>, user_code: Option<&str>) -> Language {
+            chat_setting
+                .and_then(Language::from_code)
+                .or_else(|| user_code.and_then(Language::from_code))
+                .unwrap_or(Language::DEFAULT)
+        }
+
+        /// The message template for `key`, falling back to the default language
+        /// and then to the key itself when there is no translation.
+        pub fn tr(self, key: &str) -> &str {
+            let catalogs = catalogs();
+            match [self, Language::DEFAULT].iter().find_map(|lang| catalogs[lang].get(key)) {
+                Some(template) => template,
+                None => {
+                    log::warn!("No message {:?} in any catalog", key);
+                    key
+                }
+            }
+        }
+
+        /// The message for `key` with `{name}` placeholders replaced by `args`.
+        /// The template is read once, so placeholders in the values, which can
+        /// be user text, stay as they are.
+        ///
+        /// Arguments are `Sync` so that a call can be used directly in an `.await`ed
+        /// expression of a `Send` future.
+        pub fn format(self, key: &str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+            let mut template = self.tr(key);
+            let mut text = String::with_capacity(template.len());
+            while let Some(start) = template.find('{') {
+                text.push_str(&template[..start]);
+                let rest = &template[start..];
+                let Some(end) = rest.find('}') else {
+                    template = rest;
+                    break;
+                };
+                let name = &rest[1..end];
+                match args.iter().find(|(arg, _)| *arg == name) {
+                    Some((_, value)) => text.push_str(&value.to_string()),
+                    None => text.push_str(&rest[..=end]),
+                }
+                template = &rest[end + 1..];
+            }
+            text.push_str(template);
+            text
+        }
+    }
+
+    /// All keys of a language's catalog. Used to check that translations are complete.
+    pub fn keys(lang: Language) -> Vec<&'static str> {
+        let mut keys: Vec<&str> = catalogs()[&lang].keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    fn catalogs() -> &'static HashMap<Language, HashMap<String, String>> {
+        static CATALOGS_PARSED: OnceLock<HashMap<Language, HashMap<String, String>>> = OnceLock::new();
+        CATALOGS_PARSED.get_or_init(|| {
+            CATALOGS
+                .iter()
+                .map(|(lang, source)| {
+                    let catalog = toml::from_str(source)
+                        .unwrap_or_else(|e| panic!("Invalid {} message catalog: {}", lang.code(), e));
+                    (*lang, catalog)
+                })
+                .collect()
+        })
+    }
+}
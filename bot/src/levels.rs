@@ -0,0 +1,179 @@
+pub mod levels {
+    use std::f64::consts::PI;
+
+    /// Level reported for silence, in dB, instead of minus infinity.
+    pub const SILENCE_DB: f64 = -100.0;
+
+    /// Samples at or above this magnitude count as clipped.
+    const CLIP_LEVEL: f32 = 0.999;
+
+    /// Length of the loudness measurement blocks in seconds, and how far apart
+    /// they start (75% overlap), as in ITU-R BS.1770.
+    const BLOCK_SECS: f64 = 0.4;
+    const BLOCK_STEP_SECS: f64 = 0.1;
+
+    /// Blocks quieter than this don't count towards the loudness at all.
+    const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+    /// Blocks this far below the ungated loudness are left out as pauses.
+    const RELATIVE_GATE_LU: f64 = -10.0;
+
+    /// Level statistics of a recording.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Levels {
+        /// Loudest sample in dBFS
+        pub peak_db: f64,
+        /// RMS level of all samples in dBFS
+        pub rms_db: f64,
+        /// Gated, K-weighted loudness after ITU-R BS.1770 in LUFS. All channels
+        /// are weighted equally.
+        pub loudness_lufs: f64,
+        /// Fraction of samples at full scale
+        pub clipping_ratio: f64,
+    }
+
+    impl Levels {
+        /// Measures interleaved `samples` with `channels` channels.
+        pub fn measure(samples: &[f32], sample_rate: u32, channels: u16) -> Levels {
+            if samples.is_empty() {
+                return Levels {
+                    peak_db: SILENCE_DB,
+                    rms_db: SILENCE_DB,
+                    loudness_lufs: SILENCE_DB,
+                    clipping_ratio: 0.0,
+                };
+            }
+
+            let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            let mean_square = samples.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / samples.len() as f64;
+            let clipped = samples.iter().filter(|s| s.abs() >= CLIP_LEVEL).count();
+
+            Levels {
+                peak_db: amplitude_db(peak as f64),
+                rms_db: power_db(mean_square),
+                loudness_lufs: loudness(samples, sample_rate, channels.max(1) as usize),
+                clipping_ratio: clipped as f64 / samples.len() as f64,
+            }
+        }
+    }
+
+    /// Integrated loudness in LUFS.
+    fn loudness(samples: &[f32], sample_rate: u32, channels: usize) -> f64 {
+        let frames = samples.len() / channels;
+        let rate = sample_rate as f64;
+
+        // K-weighted power of every frame, summed over the channels
+        let mut power = vec![0.0; frames];
+        for channel in 0..channels {
+            let mut filter = KWeighting::new(rate);
+            for (frame, power) in power.iter_mut().enumerate() {
+                let weighted = filter.process(samples[frame * channels + channel] as f64);
+                *power += weighted * weighted;
+            }
+        }
+
+        let block = ((BLOCK_SECS * rate) as usize).max(1);
+        let step = ((BLOCK_STEP_SECS * rate) as usize).max(1);
+        // Recordings shorter than one block are measured as a single block
+        let starts = if frames <= block { vec![0] } else { (0..=frames - block).step_by(step).collect() };
+
+        // Prefix sums make every block a subtraction
+        let mut cumulative = Vec::with_capacity(frames + 1);
+        cumulative.push(0.0);
+        for p in &power {
+            cumulative.push(cumulative[cumulative.len() - 1] + p);
+        }
+        let blocks: Vec<f64> = starts
+            .iter()
+            .map(|&start| {
+                let end = (start + block).min(frames);
+                (cumulative[end] - cumulative[start]) / (end - start).max(1) as f64
+            })
+            .collect();
+
+        let gated = |threshold: f64| -> Option<f64> {
+            let loud: Vec<f64> = blocks.iter().copied().filter(|&p| block_loudness(p) > threshold).collect();
+            (!loud.is_empty()).then(|| loud.iter().sum::<f64>() / loud.len() as f64)
+        };
+        let Some(ungated) = gated(ABSOLUTE_GATE_LUFS) else {
+            return SILENCE_DB;
+        };
+        let relative = block_loudness(ungated) + RELATIVE_GATE_LU;
+        gated(relative.max(ABSOLUTE_GATE_LUFS)).map_or(SILENCE_DB, block_loudness)
+    }
+
+    fn block_loudness(mean_power: f64) -> f64 {
+        if mean_power <= 0.0 {
+            SILENCE_DB
+        } else {
+            (-0.691 + 10.0 * mean_power.log10()).max(SILENCE_DB)
+        }
+    }
+
+    fn amplitude_db(amplitude: f64) -> f64 {
+        power_db(amplitude * amplitude)
+    }
+
+    fn power_db(power: f64) -> f64 {
+        if power <= 0.0 {
+            SILENCE_DB
+        } else {
+            (10.0 * power.log10()).max(SILENCE_DB)
+        }
+    }
+
+    /// The K-weighting filter of BS.1770: a high shelf modelling the head,
+    /// followed by a high-pass, with coefficients derived for any sample rate.
+    struct KWeighting {
+        shelf: Biquad,
+        high_pass: Biquad,
+    }
+
+    impl KWeighting {
+        fn new(rate: f64) -> Self {
+            let f0 = 1681.974450955533;
+            let gain_db = 3.999843853973347;
+            let q = 0.7071752369554196;
+            let k = (PI * f0 / rate).tan();
+            let vh = 10f64.powf(gain_db / 20.0);
+            let vb = vh.powf(0.4996667741545416);
+            let a0 = 1.0 + k / q + k * k;
+            let shelf = Biquad::new(
+                [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+                [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            );
+
+            let f0 = 38.13547087602444;
+            let q = 0.5003270373238773;
+            let k = (PI * f0 / rate).tan();
+            let a0 = 1.0 + k / q + k * k;
+            let high_pass = Biquad::new([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+
+            KWeighting { shelf, high_pass }
+        }
+
+        fn process(&mut self, x: f64) -> f64 {
+            self.high_pass.process(self.shelf.process(x))
+        }
+    }
+
+    /// Second-order IIR filter in direct form I, with `a0` normalised to 1.
+    pub(crate) struct Biquad {
+        b: [f64; 3],
+        a: [f64; 2],
+        x: [f64; 2],
+        y: [f64; 2],
+    }
+
+    impl Biquad {
+        pub(crate) fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+            Biquad { b, a, x: [0.0; 2], y: [0.0; 2] }
+        }
+
+        pub(crate) fn process(&mut self, x: f64) -> f64 {
+            let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1] - self.a[0] * self.y[0] - self.a[1] * self.y[1];
+            self.x = [x, self.x[0]];
+            self.y = [y, self.y[0]];
+            y
+        }
+    }
+}
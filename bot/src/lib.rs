@@ -1,4 +1,27 @@
+#![allow(clippy::module_inception)]
+
 pub mod audio_conversion;
+pub mod chunking;
+pub mod config;
+pub mod converter_registry;
+pub mod diarization;
+pub mod eta;
+pub mod export;
 pub mod ffmpeg_converter;
+pub mod followup;
+pub mod hallucination;
+pub mod i18n;
+pub mod levels;
+pub mod ogg;
+pub mod opus_converter;
+pub mod preprocess;
 pub mod speech_to_text;
-
+pub mod storage;
+pub mod subtitles;
+pub mod summary;
+pub mod symphonia_converter;
+pub mod text_split;
+pub mod vad;
+pub mod vocabulary;
+pub mod wav;
+pub mod wer;
@@ -1,165 +1,1141 @@
-use std::env;
 use std::error::Error;
-use std::fs::File;
-use std::time::Instant;
-use teloxide::dispatching::dialogue::GetChatId;
-use teloxide::types::Currency::AUD;
-use teloxide::{net::Download, prelude::*, types::BotCommand, utils::command::BotCommands};
-use tempfile::tempdir;
-use voicebot::audio_conversion::audio_conversion::convert_wav_to_samples;
-use voicebot::audio_conversion::audio_conversion::AudioConverter;
-use voicebot::ffmpeg_converter::audio_conversion::FFMpegAudioConverter;
-use voicebot::speech_to_text::speech_to_text::{SpeechToText, WhisperSTT};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use teloxide::types::{
+    BotCommand, CallbackQuery, FileMeta, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, MessageId,
+    ParseMode, User,
+};
+use teloxide::{ApiError, DownloadError, RequestError};
+use thiserror::Error;
+use tokio::io::AsyncWrite;
+use teloxide::utils::html;
+use teloxide::{net::Download, prelude::*, utils::command::BotCommands};
+use voicebot::audio_conversion::audio_conversion::{AudioData, ConversionError, InputFormat, TargetSpec};
+use voicebot::converter_registry::converter_registry::ConverterRegistry;
+use voicebot::chunking::chunking::transcribe_chunked;
+use voicebot::config::config::{
+    Config, LongTextMode, MessagesConfig, ParagraphConfig, ParagraphMode, PreprocessStage, Speakers,
+};
+use voicebot::diarization::diarization::{assign_speakers, Diarizer};
+use voicebot::eta::eta::SpeedEstimator;
+use voicebot::export::export::build_archives;
+use voicebot::hallucination::hallucination::HallucinationFilter;
+use voicebot::i18n::i18n::Language;
+use voicebot::levels::levels::Levels;
+use voicebot::preprocess::preprocess::{canonical, format_stages, parse_stages, Pipeline};
+use voicebot::ffmpeg_converter::audio_conversion::{FFMpegAudioConverter, StreamingDecoder};
+use voicebot::followup::followup::{CachedRecording, FollowUp, RecordingCache};
+use voicebot::speech_to_text::speech_to_text::{RecognitionError, RecognitionOptions, Segment, SpeechToText, Transcript, WhisperSTT};
+use voicebot::storage::storage::{
+    CacheKey, CachedTranscript, NewTranscript, StoredTranscript, TranscriptStore, MATCH_END, MATCH_START,
+};
+use voicebot::subtitles::subtitles::{to_paragraphs, to_srt, to_timestamped_text, with_speaker_labels};
+use voicebot::summary::summary::summarize as summarize_text;
+use voicebot::text_split::text_split::{split_message, utf16_len};
+use voicebot::vad::vad::{speech_duration, SpeechRegion, Vad};
+use voicebot::vocabulary::vocabulary::parse_terms;
+
+/// Maximum size of a file bots can download through the cloud Bot API
+const CLOUD_API_DOWNLOAD_LIMIT: u32 = 20 * 1024 * 1024;
+/// Number of transcripts shown on one /history page
+const HISTORY_PAGE_SIZE: u32 = 5;
+/// Maximum number of /search results
+const SEARCH_LIMIT: u32 = 10;
+/// Transcripts longer than this are shortened in /history
+const PREVIEW_CHARS: usize = 300;
+/// Chat setting holding the language chosen with /language
+const LANGUAGE_SETTING: &str = "language";
+/// Chat setting holding the preprocessing stages chosen with /preprocess
+const PREPROCESS_SETTING: &str = "preprocess";
+/// Chat setting holding the number of speakers chosen with /speakers
+const SPEAKERS_SETTING: &str = "speakers";
+/// Chat setting holding the paragraph mode chosen with /paragraphs
+const PARAGRAPHS_SETTING: &str = "paragraphs";
+/// Chat setting holding the terms added with /vocab, one per line
+const VOCABULARY_SETTING: &str = "vocabulary";
+/// Number of sentences in a summary of a transcript
+const SUMMARY_SENTENCES: usize = 5;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     pretty_env_logger::init();
     log::info!("Starting bot...");
 
-    let bot = Bot::from_env();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Invalid configuration: {}", e);
+            return Err(e.into());
+        }
+    };
+    let mut bot = Bot::new(config.telegram_token()?);
+    if let Some(api_url) = config.api_url()? {
+        log::info!("Using Bot API server at {}", api_url);
+        bot = bot.set_api_url(api_url);
+    }
+    register_commands(&bot).await;
 
-    teloxide::repl(
-        bot,
-        |bot: Bot, msg: Message| async move {
-            recognize(bot, msg).await?;
-            Ok(())
-        },
-    ).await;
+    let converter = Arc::new(FFMpegAudioConverter::new(&config.ffmpeg));
+    let converters = Arc::new(ConverterRegistry::standard(&config.ffmpeg));
+    let filter = || HallucinationFilter::new(&config.hallucinations, &config.vad);
+    let stt = Arc::new(WhisperSTT::new(&config.whisper)?.with_filter(filter()));
+    let larger_stt = match &config.whisper.larger_model_path {
+        Some(path) => Some(Arc::new(WhisperSTT::with_model(path, config.whisper.threads)?.with_filter(filter()))),
+        None => None,
+    };
+    let larger_stt = Arc::new(LargerModel(larger_stt));
+    let cache = Arc::new(Mutex::new(RecordingCache::new(config.followup.max_cached_audio_bytes)));
+    let speed = Arc::new(SpeedTracker {
+        estimator: Mutex::new(SpeedEstimator::load(
+            config.eta.state_path.clone(),
+            config.bot.recording_to_wall_ratio,
+            config.eta.smoothing,
+        )?),
+        active_jobs: AtomicUsize::new(0),
+    });
+    let store = Arc::new(TranscriptStore::open(&config.storage.database_path)?);
+    let config = Arc::new(config);
+
+    let commands = dptree::entry()
+        .filter_command::<Command>()
+        .branch(dptree::case![Command::Help].endpoint(help))
+        .branch(dptree::case![Command::Recognize].endpoint(recognize))
+        .branch(dptree::case![Command::Summarize].endpoint(summarize))
+        .branch(dptree::case![Command::History(page)].endpoint(history))
+        .branch(dptree::case![Command::Search(query)].endpoint(search))
+        .branch(dptree::case![Command::Export(range)].endpoint(export))
+        .branch(dptree::case![Command::Language(code)].endpoint(language))
+        .branch(dptree::case![Command::Preprocess(stages)].endpoint(preprocess))
+        .branch(dptree::case![Command::Speakers(speakers)].endpoint(speakers))
+        .branch(dptree::case![Command::Paragraphs(mode)].endpoint(paragraphs))
+        .branch(dptree::case![Command::Vocab(args)].endpoint(vocab))
+        .branch(dptree::case![Command::PurgeCache].endpoint(purge_cache));
+
+    let messages = Update::filter_message()
+        .branch(commands)
+        .branch(dptree::endpoint(recognize));
+    let handler = dptree::entry()
+        .branch(messages)
+        .branch(Update::filter_callback_query().endpoint(follow_up));
+
+    Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![config, converter, converters, stt, larger_stt, speed, store, cache])
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
 
     Ok(())
 }
 
+/// The model offered for "Retry with larger model", if one is configured.
+struct LargerModel(Option<Arc<WhisperSTT>>);
+
+/// Recognition speed history and the number of jobs currently being recognised,
+/// shared by all handlers to estimate recognition time.
+struct SpeedTracker {
+    estimator: Mutex<SpeedEstimator>,
+    active_jobs: AtomicUsize,
+}
+
+/// Descriptions come from the `command.*` keys of the locales, see `localized_commands`.
 #[derive(BotCommands, Clone)]
-#[command(
-    rename_rule = "lowercase",
-    description = "These commands are supported:"
-)]
+#[command(rename_rule = "lowercase")]
 enum Command {
-    #[command(description = "recognize the attached audio file.")]
     Recognize,
-    #[command(description = "summarize the attached text and/or audio")]
     Summarize,
-    #[command(description = "display this text.")]
     Help,
+    History(String),
+    Search(String),
+    Export(String),
+    Language(String),
+    Preprocess(String),
+    Speakers(String),
+    Paragraphs(String),
+    Vocab(String),
+    // Admin commands are not listed in the menu or in /help
+    #[command(description = "off")]
+    PurgeCache,
+}
+
+/// The commands with descriptions in `lang`, as shown in the Telegram menu and /help.
+fn localized_commands(lang: Language) -> Vec<BotCommand> {
+    Command::bot_commands()
+        .into_iter()
+        .map(|command| {
+            let name = command.command.trim_start_matches('/');
+            let description = lang.tr(&format!("command.{}", name)).to_string();
+            BotCommand::new(command.command, description)
+        })
+        .collect()
+}
+
+/// Registers the command menu for every supported language. Clients with other
+/// languages get the default one.
+async fn register_commands(bot: &Bot) {
+    for lang in Language::ALL {
+        let request = bot.set_my_commands(localized_commands(lang));
+        let result = if lang == Language::DEFAULT {
+            request.await
+        } else {
+            request.language_code(lang.code()).await
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to register {} commands: {}", lang.code(), e);
+        }
+    }
+}
+
+/// The language to answer `msg` in: the one chosen for the chat with /language,
+/// otherwise the language of the sender's Telegram app.
+fn chat_language(store: &TranscriptStore, msg: &Message) -> Language {
+    user_language(store, msg.chat.id, msg.from())
+}
+
+/// Same as `chat_language`, for updates that are not messages.
+fn user_language(store: &TranscriptStore, chat_id: ChatId, user: Option<&User>) -> Language {
+    let setting = store
+        .chat_setting(chat_id.0, LANGUAGE_SETTING)
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to read the language of chat {}: {}", chat_id, e);
+            None
+        });
+    let user_code = user.and_then(|user| user.language_code.as_deref());
+    Language::resolve(setting.as_deref(), user_code)
+}
+
+/// The preprocessing stages chosen for the chat with /preprocess, otherwise
+/// the configured ones.
+fn chat_preprocessing(store: &TranscriptStore, config: &Config, chat_id: ChatId) -> Vec<PreprocessStage> {
+    let setting = store.chat_setting(chat_id.0, PREPROCESS_SETTING).unwrap_or_else(|e| {
+        log::warn!("Failed to read the preprocessing of chat {}: {}", chat_id, e);
+        None
+    });
+    setting
+        .and_then(|setting| parse_stages(&setting))
+        .unwrap_or_else(|| canonical(&config.preprocess.stages))
+}
+
+/// The number of speakers chosen for the chat with /speakers, otherwise the
+/// configured one.
+fn chat_speakers(store: &TranscriptStore, config: &Config, chat_id: ChatId) -> Speakers {
+    let setting = store.chat_setting(chat_id.0, SPEAKERS_SETTING).unwrap_or_else(|e| {
+        log::warn!("Failed to read the speakers of chat {}: {}", chat_id, e);
+        None
+    });
+    setting.and_then(|setting| Speakers::parse(&setting)).unwrap_or(config.diarization.speakers)
+}
+
+/// The paragraph layout with the mode chosen for the chat with /paragraphs,
+/// otherwise the configured one.
+fn chat_paragraphs(store: &TranscriptStore, config: &Config, chat_id: ChatId) -> ParagraphConfig {
+    let setting = store.chat_setting(chat_id.0, PARAGRAPHS_SETTING).unwrap_or_else(|e| {
+        log::warn!("Failed to read the paragraph mode of chat {}: {}", chat_id, e);
+        None
+    });
+    let mode = setting.and_then(|setting| ParagraphMode::from_name(&setting)).unwrap_or(config.paragraphs.mode);
+    config.paragraphs.with_mode(mode)
 }
 
-async fn answer(bot: Bot, msg: Message, cmd: Command) -> ResponseResult<()> {
-    recognize(bot, msg).await?;
-    // match cmd {
-    //     Command::Help => help(bot, msg).await?,
-    //     Command::Recognize => recognize(bot, msg).await?,
-    //     Command::Summarize => summarize(bot, msg).await?,
-    // }
+/// The terms added to the chat's vocabulary with /vocab.
+fn chat_vocabulary(store: &TranscriptStore, chat_id: ChatId) -> Vec<String> {
+    let setting = store.chat_setting(chat_id.0, VOCABULARY_SETTING).unwrap_or_else(|e| {
+        log::warn!("Failed to read the vocabulary of chat {}: {}", chat_id, e);
+        None
+    });
+    setting.map(|setting| parse_terms(&setting)).unwrap_or_default()
+}
+
+async fn help(bot: Bot, msg: Message, store: Arc<TranscriptStore>) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let mut text = format!("{}\n", lang.tr("help.header"));
+    for command in localized_commands(lang) {
+        text.push_str(&format!("\n{} — {}", command.command, command.description));
+    }
 
+    bot.send_message(msg.chat.id, text).await?;
     Ok(())
 }
 
-async fn help(bot: Bot, msg: Message) -> ResponseResult<()> {
-    bot.send_message(msg.chat.id, Command::descriptions().to_string())
-        .await?;
+#[allow(clippy::too_many_arguments)]
+async fn recognize(
+    bot: Bot,
+    msg: Message,
+    converter: Arc<FFMpegAudioConverter>,
+    converters: Arc<ConverterRegistry>,
+    stt: Arc<WhisperSTT>,
+    config: Arc<Config>,
+    speed: Arc<SpeedTracker>,
+    store: Arc<TranscriptStore>,
+    cache: Arc<Mutex<RecordingCache>>,
+) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let result = recognize_message(&bot, &msg, lang, &converter, &converters, &stt, &config, &speed, &store, &cache).await;
+    if let Err(e) = result {
+        log::error!("Failed to recognize message {} in chat {}: {}", msg.id, msg.chat.id, e);
+        bot.send_message(msg.chat.id, e.user_message(&config, lang)).await?;
+    }
+
     Ok(())
 }
 
-async fn recognize(bot: Bot, msg: Message) -> ResponseResult<()> {
-    let mut file_id : Option<String> = None;
+#[allow(clippy::too_many_arguments)]
+async fn recognize_message(
+    bot: &Bot,
+    msg: &Message,
+    lang: Language,
+    converter: &FFMpegAudioConverter,
+    converters: &Arc<ConverterRegistry>,
+    stt: &Arc<WhisperSTT>,
+    config: &Arc<Config>,
+    speed: &SpeedTracker,
+    store: &TranscriptStore,
+    cache: &Mutex<RecordingCache>,
+) -> Result<(), BotError> {
+    // The file, the duration Telegram reports for it and its MIME type
+    let mut attachment: Option<(FileMeta, u32, Option<String>)> = None;
 
     if let Some(voice) = msg.voice() {
         log::info!("This is a voice message");
-        file_id = Some(voice.clone().file.id);
+        // Voice messages are Ogg Opus even when Telegram leaves out the type
+        let mime_type = voice.mime_type.as_ref().map_or("audio/ogg".to_string(), |mime| mime.to_string());
+        attachment = Some((voice.file.clone(), voice.duration, Some(mime_type)));
 
     } else if let Some(audio) = msg.audio() {
         log::info!("Generic audio file attached to the message");
-        file_id = Some(audio.clone().file.id);
+        attachment = Some((audio.file.clone(), audio.duration, audio.mime_type.as_ref().map(|mime| mime.to_string())));
+
+    } else if let Some(video) = msg.video() {
+        log::info!("Video attached to the message");
+        attachment = Some((video.file.clone(), video.duration, video.mime_type.as_ref().map(|mime| mime.to_string())));
     }
 
-    if let Some(fid) = file_id {
-        let file = bot.get_file(fid).await?;
+    let (file_meta, reported_duration, mime_type) = attachment.ok_or(BotError::NoAudio)?;
 
-        let mut buffer: Vec<u8> = Vec::new();
-        bot.download_file(&file.path, &mut buffer).await?;
+    // The same file recognised before, e.g. a forwarded voice note
+    let stages = chat_preprocessing(store, config, msg.chat.id);
+    let speakers = chat_speakers(store, config, msg.chat.id);
+    let recognition = RecognitionOptions { vocabulary: chat_vocabulary(store, msg.chat.id), ..Default::default() };
+    let options = options_key(&recognition, &stages, speakers);
+    let cache_key = CacheKey {
+        file_unique_id: &file_meta.unique_id,
+        model: stt.model_name(),
+        options: &options,
+    };
+    if let Some(cached) = cached_transcript(store, &cache_key) {
+        log::info!("Transcript of file {} found in the cache", file_meta.unique_id);
+        // The audio isn't downloaded, so the actions that need it are left out
+        let model = stt.model_name();
+        return deliver_transcript(bot, msg, lang, config, store, &cached.transcript, cached.duration, model, false)
+            .await;
+    }
 
-        let converter = FFMpegAudioConverter;
+    let limits = &config.limits;
 
-        // FIXME: replace unwrap with better error propagation
-        let wav_bytes = converter
-            .convert_audio_to_wav(buffer.as_slice()).unwrap();
+    // Check what Telegram tells us before downloading anything
+    if u64::from(file_meta.size) > limits.max_file_size {
+        return Err(BotError::FileTooLarge(u64::from(file_meta.size)));
+    }
+    if reported_duration > limits.max_duration_secs {
+        return Err(BotError::TooLong(reported_duration));
+    }
 
-        // FIXME: replace unwrap with better error propagation
-        let audio_data = convert_wav_to_samples(wav_bytes.as_slice()).unwrap();
-        let samples = audio_data.samples;
+    // A second past the limit is enough to tell the decoded audio is too long
+    let spec = stt.input_spec().with_max_duration(limits.max_duration_secs.saturating_add(1));
+    let audio_data = fetch_audio(bot, config, converter, converters, &file_meta, mime_type.as_deref(), &spec).await?;
+    let levels = audio_data.levels;
+    log::info!(
+        "Decoded {} ({}): {:.1}s, peak {:.1} dBFS, RMS {:.1} dBFS, {:.1} LUFS, {:.2}% clipped",
+        file_meta.unique_id,
+        audio_data.describe_format(),
+        audio_data.duration,
+        levels.peak_db,
+        levels.rms_db,
+        levels.loudness_lufs,
+        levels.clipping_ratio * 100.0
+    );
+    let warnings = quality_warnings(lang, config, &levels);
 
-        let total_seconds = audio_data.duration.round() as u32; // Round to nearest second and convert to u32
-        let minutes = total_seconds / 60;
-        let seconds = total_seconds % 60;
+    let pipeline = Pipeline::from_stages(&stages, &config.preprocess);
+    let audio_data = if pipeline.is_empty() {
+        audio_data
+    } else {
+        let started = Instant::now();
+        let processed = pipeline.apply(audio_data);
+        log::info!(
+            "Preprocessed with {} in {:.2}s, now {:.1} LUFS",
+            pipeline.names().join(", "),
+            started.elapsed().as_secs_f64(),
+            processed.levels.loudness_lufs
+        );
+        processed
+    };
+    let samples = audio_data.samples;
 
-        let ratio: f64 = env::var("RECORDING_TO_WALL_RATIO")
-            .unwrap_or("10".to_string())  // Use 10 as a default value if the env variable is not set
-            .parse()                      // Parse the string to a floating-point number
-            .unwrap_or(10.0);             // Use 10 as a default value if parsing fails
+    // The reported duration can be wrong, check the decoded audio as well.
+    // Decoding stopped past the limit, so the real length isn't known.
+    if audio_data.duration > f64::from(limits.max_duration_secs) {
+        return Err(BotError::DecodedTooLong);
+    }
 
-        let expected_time = (total_seconds as f64 / ratio) as u64;
+    // Whisper makes up phrases for silence, so silent recordings are not recognised
+    // at all and silence around the speech is cut off
+    let vad = Vad::new(&config.vad, spec.sample_rate);
+    let regions = vad.detect(&samples);
+    if regions.is_empty() {
+        log::info!("No speech detected in message {} in chat {}", msg.id, msg.chat.id);
+        // A very quiet recording explains why nothing was found
+        let text = std::iter::once(lang.tr("recognize.no_speech").to_string()).chain(warnings).collect::<Vec<_>>().join("\n");
+        bot.send_message(msg.chat.id, text).await?;
+        return Ok(());
+    }
+    let (speech_samples, offset) = vad.trim(&samples, &regions);
+    let speech_samples: Arc<[f32]> = speech_samples.into();
+    let speech_secs = speech_duration(&regions);
+    log::info!(
+        "{} speech regions, {:.1}s of speech in {:.1}s of audio",
+        regions.len(),
+        speech_secs,
+        audio_data.duration
+    );
 
-        let expected_minutes = expected_time / 60;
-        let expected_seconds = expected_time % 60;
+    let total_seconds = audio_data.duration.round() as u32; // Round to nearest second and convert to u32
+    let minutes = total_seconds / 60;
+    let seconds = total_seconds % 60;
+    let duration_str = lang.format("duration.minutes_seconds", &[("minutes", &minutes), ("seconds", &seconds)]);
+    let speech_seconds = speech_secs.round() as u32;
+    let speech_str = lang.format(
+        "duration.minutes_seconds",
+        &[("minutes", &(speech_seconds / 60)), ("seconds", &(speech_seconds % 60))],
+    );
 
-        let mut expected_time_str : String;
-        if expected_minutes > 0 {
-            expected_time_str = format!("{} minutes {} seconds", expected_minutes, expected_seconds);
-        } else {
-            expected_time_str = format!("{} seconds", expected_seconds);
-        }
-
-        bot.send_message(
-            msg.chat.id,
-            format!(
-                "Audio duration: {} minutes {} seconds.\nExpected recognition time: {}",
-                minutes,
-                seconds,
-                expected_time_str),
-
-        )
-            .await?;
-
-        let stt = WhisperSTT::new(Option::None).unwrap();
-
-        let start_time = Instant::now();
-        let recognized_text = stt.recognize(&samples);
-        let recognition_duration = start_time.elapsed().as_secs_f64();
-
-        log::info!("Recognized text: {}", recognized_text);
-        // Let's say 100 seconds for 200 seconds of recording
-        // then we can say we recognise 2 seconds of recording in one second
-        // i.e. 2 seconds of recording in 1 second of real time
-        let real_time_duration = total_seconds as f64 / recognition_duration;
-        // send log message with this information
-        log::info!("Recognition speed: {} seconds of audio in second", real_time_duration);
-
-        // send telegram message with this info
-        bot.send_message(msg.chat.id, format!("Actual recognition speed: {} seconds of audio in second", real_time_duration)).await?;
-
-        if recognized_text.len() > 4096 {
-            let dir  = tempdir()?;
-            let path = dir.path().join("recognized_text.txt");
-            std::fs::write(&path, recognized_text)?;
-
-            // Send the file as an attachment
-            bot.send_document(msg.chat.id,
-                              teloxide::types::InputFile::file(path))
-                .await?;
+    // Jobs already being recognised share the CPU with this one
+    let speed_key = SpeedEstimator::key(stt.model_name(), stt.threads());
+    let concurrent_jobs = speed.active_jobs.fetch_add(1, Ordering::SeqCst) + 1;
+    let expected_time = speed
+        .estimator
+        .lock()
+        .unwrap()
+        .estimate(&speed_key, speech_secs, concurrent_jobs) as u64;
+
+    let expected_minutes = expected_time / 60;
+    let expected_seconds = expected_time % 60;
+
+    let expected_time_str = if expected_minutes > 0 {
+        lang.format("duration.minutes_seconds", &[("minutes", &expected_minutes), ("seconds", &expected_seconds)])
+    } else {
+        lang.format("duration.seconds", &[("seconds", &expected_seconds)])
+    };
+
+    let estimate = lang.format(
+        "recognize.estimate",
+        &[("duration", &duration_str), ("speech", &speech_str), ("expected", &expected_time_str)],
+    );
+    let sent = bot
+        .send_message(msg.chat.id, std::iter::once(estimate).chain(warnings).collect::<Vec<_>>().join("\n"))
+        .await;
+    if let Err(e) = sent {
+        speed.active_jobs.fetch_sub(1, Ordering::SeqCst);
+        return Err(e.into());
+    }
+
+    let start_time = Instant::now();
+    let transcript = {
+        let (stt, config, speech_samples) = (Arc::clone(stt), Arc::clone(config), Arc::clone(&speech_samples));
+        run_blocking(move || transcribe(&stt, &config, &speech_samples, recognition)).await
+    };
+    let recognition_duration = start_time.elapsed().as_secs_f64();
+    speed.active_jobs.fetch_sub(1, Ordering::SeqCst);
+    // A failed run says nothing about the recognition speed
+    let mut transcript = transcript??;
+    // Timestamps are relative to the trimmed audio
+    transcript.shift(offset);
+    let transcript = {
+        let config = Arc::clone(config);
+        run_blocking(move || {
+            label_speakers(&config, speakers, &samples, &regions, spec.sample_rate, &mut transcript);
+            transcript
+        })
+        .await?
+    };
+
+    if let Err(e) = speed.estimator.lock().unwrap().record(
+        &speed_key,
+        speech_secs,
+        recognition_duration,
+        concurrent_jobs,
+    ) {
+        log::warn!("Failed to persist recognition speed: {}", e);
+    }
+
+    // Whatever whisper heard may all have been dropped as made up
+    if transcript.is_empty() {
+        log::info!("Nothing left of the transcript of message {} in chat {}", msg.id, msg.chat.id);
+        bot.send_message(msg.chat.id, lang.tr("recognize.no_speech")).await?;
+        return Ok(());
+    }
+
+    if let Err(e) = store.cache_transcript(&cache_key, audio_data.duration, &transcript, msg.date.timestamp()) {
+        log::warn!("Failed to cache transcript: {}", e);
+    }
+
+    // Let's say 100 seconds for 200 seconds of recording
+    // then we can say we recognise 2 seconds of recording in one second
+    // i.e. 2 seconds of recording in 1 second of real time
+    let real_time_duration = speech_secs / recognition_duration;
+    // send log message with this information
+    log::info!("Recognition speed: {} seconds of audio in second", real_time_duration);
+
+    // send telegram message with this info
+    bot.send_message(msg.chat.id, lang.format("recognize.speed", &[("speed", &real_time_duration)])).await?;
+
+    deliver_transcript(bot, msg, lang, config, store, &transcript, audio_data.duration, stt.model_name(), true).await?;
+
+    cache.lock().unwrap().insert(
+        msg.chat.id.0,
+        msg.id.0,
+        CachedRecording {
+            file_unique_id: file_meta.unique_id.clone(),
+            duration: audio_data.duration,
+            offset,
+            samples: speech_samples,
+            preprocessing: stages,
+            speakers,
+            transcript,
+            model: stt.model_name().to_string(),
+        },
+    );
+
+    Ok(())
+}
+
+/// Adds the transcript of `msg` to the chat history and sends it with the
+/// follow-up buttons. Those that recognise the audio again are only offered
+/// `with_audio`, i.e. when the recording goes into the recording cache.
+#[allow(clippy::too_many_arguments)]
+async fn deliver_transcript(
+    bot: &Bot,
+    msg: &Message,
+    lang: Language,
+    config: &Config,
+    store: &TranscriptStore,
+    transcript: &Transcript,
+    duration: f64,
+    model: &str,
+    with_audio: bool,
+) -> Result<(), BotError> {
+    // Stored as one block, which suits search results; the chat gets paragraphs
+    let stored_text = transcript_text(lang, &config.paragraphs.with_mode(ParagraphMode::Off), transcript);
+    let recognized_text = transcript_text(lang, &chat_paragraphs(store, config, msg.chat.id), transcript);
+
+    let sender_name = msg.from().map(|user| user.full_name());
+    let saved = store.insert(&NewTranscript {
+        chat_id: msg.chat.id.0,
+        chat_username: msg.chat.username(),
+        sender_id: msg.from().map(|user| user.id.0),
+        sender_name: sender_name.as_deref(),
+        message_id: msg.id.0,
+        created_at: msg.date.timestamp(),
+        duration,
+        language: transcript.language.as_deref(),
+        model,
+        text: &stored_text,
+        segments: Some(&transcript.segments),
+    });
+    if let Err(e) = saved {
+        log::warn!("Failed to store transcript: {}", e);
+    }
+
+    log::info!("Recognized text: {}", recognized_text);
+
+    let keyboard = follow_up_keyboard(lang, msg.id.0, with_audio, config.whisper.larger_model_path.is_some());
+    send_text(bot, &config.messages, msg.chat.id, recognized_text, "recognized_text.txt", Some(keyboard)).await?;
+
+    Ok(())
+}
+
+/// Looks up a cached transcript; a broken cache only costs a new recognition.
+fn cached_transcript(store: &TranscriptStore, key: &CacheKey) -> Option<CachedTranscript> {
+    store.cached_transcript(key).unwrap_or_else(|e| {
+        log::warn!("Failed to read the transcript cache: {}", e);
+        None
+    })
+}
+
+/// Buttons with the follow-up actions for the recording in message `message_id`.
+/// Translating and retrying need the audio, so they come only `with_audio`.
+fn follow_up_keyboard(lang: Language, message_id: i32, with_audio: bool, larger_model: bool) -> InlineKeyboardMarkup {
+    let button = |action: FollowUp| {
+        InlineKeyboardButton::callback(lang.tr(action.label_key()).to_string(), action.callback_data(message_id))
+    };
+
+    let mut first = vec![button(FollowUp::Summarize)];
+    if with_audio {
+        first.push(button(FollowUp::Translate));
+    }
+    let mut rows = vec![first, vec![button(FollowUp::Subtitles), button(FollowUp::Timestamps)]];
+    if with_audio && larger_model {
+        rows.push(vec![button(FollowUp::RetryLarger)]);
+    }
+    InlineKeyboardMarkup::new(rows)
+}
+
+/// Handles a press of one of the buttons under a transcript.
+#[allow(clippy::too_many_arguments)]
+async fn follow_up(
+    bot: Bot,
+    query: CallbackQuery,
+    config: Arc<Config>,
+    stt: Arc<WhisperSTT>,
+    larger_stt: Arc<LargerModel>,
+    store: Arc<TranscriptStore>,
+    cache: Arc<Mutex<RecordingCache>>,
+) -> ResponseResult<()> {
+    // Stops the spinner on the button, the result comes as a separate message
+    bot.answer_callback_query(query.id.clone()).await?;
+
+    let (action, message_id) = match query.data.as_deref().and_then(FollowUp::parse) {
+        Some(parsed) => parsed,
+        None => {
+            log::warn!("Unknown callback data {:?}", query.data);
+            return Ok(());
+        }
+    };
+    let chat_id = match &query.message {
+        Some(message) => message.chat.id,
+        None => return Ok(()),
+    };
+    let lang = user_language(&store, chat_id, Some(&query.from));
+    log::info!("Follow-up {:?} for message {} in chat {}", action, message_id, chat_id);
+
+    let result =
+        run_follow_up(&bot, chat_id, message_id, action, lang, &config, &stt, &larger_stt, &store, &cache).await;
+    if let Err(e) = result {
+        log::error!("Follow-up {:?} for message {} in chat {} failed: {}", action, message_id, chat_id, e);
+        bot.send_message(chat_id, e.user_message(&config, lang)).await?;
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_follow_up(
+    bot: &Bot,
+    chat_id: ChatId,
+    message_id: i32,
+    action: FollowUp,
+    lang: Language,
+    config: &Arc<Config>,
+    stt: &Arc<WhisperSTT>,
+    larger_stt: &LargerModel,
+    store: &Arc<TranscriptStore>,
+    cache: &Mutex<RecordingCache>,
+) -> Result<(), BotError> {
+    let cached = cache.lock().unwrap().get(chat_id.0, message_id);
+
+    // Actions on the transcript alone can fall back to the stored copy
+    let transcript = match &cached {
+        Some(recording) => Some(recording.transcript.clone()),
+        None => stored_transcript(store, chat_id, message_id),
+    };
+    let transcript = match transcript {
+        Some(transcript) => transcript,
+        None => {
+            bot.send_message(chat_id, lang.tr("followup.expired")).await?;
+            return Ok(());
+        }
+    };
+
+    match action {
+        FollowUp::Summarize => {
+            let summary = summarize_text(&transcript.text(), SUMMARY_SENTENCES);
+            send_text(bot, &config.messages, chat_id, summary, "summary.txt", None).await?;
+        }
+        FollowUp::Subtitles => {
+            let segments = with_speaker_labels(&transcript.segments, |speaker| speaker_label(lang, speaker));
+            send_file(bot, chat_id, "transcript.srt", to_srt(&segments).as_bytes()).await?;
+        }
+        FollowUp::Timestamps => {
+            let segments = with_speaker_labels(&transcript.segments, |speaker| speaker_label(lang, speaker));
+            let timestamps = to_timestamped_text(&segments);
+            send_text(bot, &config.messages, chat_id, timestamps, "timestamps.txt", None).await?;
+        }
+        FollowUp::Translate => {
+            let Some(recording) = cached else {
+                bot.send_message(chat_id, lang.tr("followup.audio_expired")).await?;
+                return Ok(());
+            };
+            let options = RecognitionOptions { translate: true, vocabulary: chat_vocabulary(store, chat_id) };
+            let translation = run_blocking({
+                let (store, config, stt) = (Arc::clone(store), Arc::clone(config), Arc::clone(stt));
+                move || recognize_cached(&store, &config, &stt, &recording, options)
+            })
+            .await??;
+            if translation.is_empty() {
+                bot.send_message(chat_id, lang.tr("recognize.no_speech")).await?;
+                return Ok(());
+            }
+            let text = transcript_text(lang, &chat_paragraphs(store, config, chat_id), &translation);
+            send_text(bot, &config.messages, chat_id, text, "translation.txt", None).await?;
+        }
+        FollowUp::RetryLarger => {
+            let larger_stt = match &larger_stt.0 {
+                Some(stt) => stt,
+                None => return Ok(()),
+            };
+            let Some(recording) = cached else {
+                bot.send_message(chat_id, lang.tr("followup.audio_expired")).await?;
+                return Ok(());
+            };
+            let options = RecognitionOptions { vocabulary: chat_vocabulary(store, chat_id), ..Default::default() };
+            let transcript = run_blocking({
+                let (store, config, stt) = (Arc::clone(store), Arc::clone(config), Arc::clone(larger_stt));
+                move || recognize_cached(&store, &config, &stt, &recording, options)
+            })
+            .await??;
+            if transcript.is_empty() {
+                bot.send_message(chat_id, lang.tr("recognize.no_speech")).await?;
+                return Ok(());
+            }
+            let text = transcript_text(lang, &chat_paragraphs(store, config, chat_id), &transcript);
+            // Later follow-ups should use the better transcript
+            cache
+                .lock()
+                .unwrap()
+                .update_transcript(chat_id.0, message_id, transcript, larger_stt.model_name());
+            send_text(bot, &config.messages, chat_id, text, "recognized_text.txt", None).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs `work` on a thread meant for blocking code, so that decoding,
+/// recognition and diarization don't hold up the async handlers.
+async fn run_blocking<T, F>(work: F) -> Result<T, BotError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    Ok(tokio::task::spawn_blocking(work).await.map_err(io::Error::other)?)
+}
+
+/// Recognises a cached recording again, or takes the result from the transcript
+/// cache if the same file was already recognised this way.
+fn recognize_cached(
+    store: &TranscriptStore,
+    config: &Config,
+    stt: &WhisperSTT,
+    recording: &CachedRecording,
+    options: RecognitionOptions,
+) -> Result<Transcript, BotError> {
+    let options_key = options_key(&options, &recording.preprocessing, recording.speakers);
+    let key = CacheKey {
+        file_unique_id: &recording.file_unique_id,
+        model: stt.model_name(),
+        options: &options_key,
+    };
+    if let Some(cached) = cached_transcript(store, &key) {
+        return Ok(cached.transcript);
+    }
+
+    let mut transcript = transcribe(stt, config, &recording.samples, options)?;
+    if recording.speakers != Speakers::Off {
+        let sample_rate = stt.input_spec().sample_rate;
+        let regions = Vad::new(&config.vad, sample_rate).detect(&recording.samples);
+        label_speakers(config, recording.speakers, &recording.samples, &regions, sample_rate, &mut transcript);
+    }
+    transcript.shift(recording.offset);
+    if transcript.is_empty() {
+        return Ok(transcript);
+    }
+    if let Err(e) = store.cache_transcript(&key, recording.duration, &transcript, chrono::Utc::now().timestamp()) {
+        log::warn!("Failed to cache transcript: {}", e);
+    }
+    Ok(transcript)
+}
+
+/// Key of the cached transcripts recognised with `options` from audio
+/// preprocessed with `stages`, with `speakers` told apart.
+fn options_key(options: &RecognitionOptions, stages: &[PreprocessStage], speakers: Speakers) -> String {
+    format!("{};preprocess={};speakers={}", options.cache_key(), format_stages(stages), speakers.name())
+}
+
+/// Attributes the segments of `transcript` to speakers, unless `speakers`
+/// is off. The timestamps of the transcript and `regions` must both be
+/// relative to the start of `samples`.
+fn label_speakers(
+    config: &Config,
+    speakers: Speakers,
+    samples: &[f32],
+    regions: &[SpeechRegion],
+    sample_rate: u32,
+    transcript: &mut Transcript,
+) {
+    if speakers == Speakers::Off {
+        return;
+    }
+    let started = Instant::now();
+    let turns = Diarizer::new(&config.diarization, sample_rate).diarize(samples, regions, speakers);
+    assign_speakers(&mut transcript.segments, &turns);
+    log::info!(
+        "Diarization found {} speakers in {} turns in {:.2}s",
+        turns.iter().map(|turn| turn.speaker).max().unwrap_or(0),
+        turns.len(),
+        started.elapsed().as_secs_f64()
+    );
+}
+
+/// Name of speaker `number` in transcripts, e.g. "Speaker 2".
+fn speaker_label(lang: Language, number: u32) -> String {
+    lang.format("transcript.speaker", &[("number", &number)])
+}
+
+/// Text of the transcript in paragraphs laid out as in `layout`, in turns of
+/// "Speaker N: ..." if the speakers were told apart.
+fn transcript_text(lang: Language, layout: &ParagraphConfig, transcript: &Transcript) -> String {
+    to_paragraphs(&transcript.segments, layout, |speaker| speaker_label(lang, speaker))
+}
+
+/// Warnings about recordings that are too quiet or distorted to be recognised well.
+fn quality_warnings(lang: Language, config: &Config, levels: &Levels) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if levels.loudness_lufs < config.quality.quiet_lufs {
+        let loudness = format!("{:.0}", levels.loudness_lufs);
+        warnings.push(lang.format("recognize.quiet", &[("loudness", &loudness)]));
+    }
+    if levels.clipping_ratio > config.quality.max_clipping_ratio {
+        let percent = format!("{:.1}", levels.clipping_ratio * 100.0);
+        warnings.push(lang.format("recognize.clipped", &[("percent", &percent)]));
+    }
+    warnings
+}
+
+/// Recognises `samples`. Long recordings are split at pauses and the chunks
+/// recognised in parallel.
+fn transcribe(
+    stt: &WhisperSTT,
+    config: &Config,
+    samples: &[f32],
+    options: RecognitionOptions,
+) -> Result<Transcript, RecognitionError> {
+    let chunking = &config.chunking;
+    let sample_rate = stt.input_spec().sample_rate;
+    let duration = samples.len() as f64 / sample_rate as f64;
+    if !chunking.enabled || duration < chunking.min_duration_secs {
+        return stt.transcribe_with(samples, options);
+    }
+
+    let regions = Vad::new(&config.vad, sample_rate).detect(samples);
+    transcribe_chunked(stt, samples, &regions, sample_rate, options, chunking)
+}
+
+/// The stored transcript of a message, for follow-ups on recordings that are no
+/// longer cached.
+fn stored_transcript(store: &TranscriptStore, chat_id: ChatId, message_id: i32) -> Option<Transcript> {
+    let stored = store.by_message(chat_id.0, message_id).unwrap_or_else(|e| {
+        log::warn!("Failed to read the transcript of message {} in chat {}: {}", message_id, chat_id, e);
+        None
+    })?;
+
+    // Transcripts stored without timestamps still work as one segment
+    let segments = stored.segments.unwrap_or_else(|| {
+        vec![Segment {
+            start: 0.0,
+            end: stored.duration,
+            text: stored.text.clone(),
+            speaker: None,
+        }]
+    });
+    Some(Transcript {
+        segments,
+        language: stored.language,
+    })
+}
+
+/// Everything that can go wrong while recognising a message.
+#[derive(Debug, Error)]
+enum BotError {
+    #[error("message has no audio attached")]
+    NoAudio,
+    #[error("file of {0} bytes is too big to be downloaded via the cloud Bot API")]
+    ApiDownloadLimit(u64),
+    #[error("file of {0} bytes is larger than the configured limit")]
+    FileTooLarge(u64),
+    #[error("recording of {0} seconds is longer than the configured limit")]
+    TooLong(u32),
+    #[error("decoded recording is longer than the configured limit")]
+    DecodedTooLong,
+    #[error("audio conversion failed: {0}")]
+    Conversion(#[from] ConversionError),
+    #[error("recognition failed: {0}")]
+    Recognition(#[from] RecognitionError),
+    #[error(transparent)]
+    Request(#[from] RequestError),
+    #[error(transparent)]
+    Download(#[from] DownloadError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl BotError {
+    /// What the user is told, in their language. The details only go to the log.
+    fn user_message(&self, config: &Config, lang: Language) -> String {
+        let limits = &config.limits;
+        let megabytes = |bytes: u64| format!("{:.1}", bytes as f64 / (1024.0 * 1024.0));
+        let too_long = |key: &str, duration: u32| {
+            lang.format(
+                key,
+                &[
+                    ("duration", &format_duration(duration)),
+                    ("limit", &format_duration(limits.max_duration_secs)),
+                ],
+            )
+        };
+
+        match self {
+            BotError::NoAudio => lang.tr("error.generic").to_string(),
+            BotError::ApiDownloadLimit(size) => lang.format(
+                "error.api_download_limit",
+                &[("size", &megabytes(*size)), ("limit", &(CLOUD_API_DOWNLOAD_LIMIT / (1024 * 1024)))],
+            ),
+            BotError::FileTooLarge(size) => lang.format(
+                "error.file_too_large",
+                &[("size", &megabytes(*size)), ("limit", &megabytes(limits.max_file_size))],
+            ),
+            BotError::TooLong(duration) => too_long("error.too_long", *duration),
+            BotError::DecodedTooLong => {
+                lang.format("error.decoded_too_long", &[("limit", &format_duration(limits.max_duration_secs))])
+            }
+            BotError::Conversion(
+                ConversionError::FFmpeg { .. } | ConversionError::Timeout(_) | ConversionError::Decode { .. },
+            ) => {
+                lang.tr("error.decode_failed").to_string()
+            }
+            BotError::Conversion(ConversionError::Spawn { .. }) => {
+                lang.tr("error.conversion_unavailable").to_string()
+            }
+            BotError::Conversion(ConversionError::Wav(_) | ConversionError::UnsupportedFormat(_)) => {
+                lang.tr("error.unreadable_audio").to_string()
+            }
+            BotError::Recognition(RecognitionError::InvalidModelPath(_) | RecognitionError::ModelLoad { .. }) => {
+                lang.tr("error.model_unavailable").to_string()
+            }
+            BotError::Recognition(RecognitionError::Whisper(_)) => lang.tr("error.recognition_failed").to_string(),
+            BotError::Conversion(ConversionError::Io(_))
+            | BotError::Request(_)
+            | BotError::Download(_)
+            | BotError::Io(_) => lang.tr("error.retry").to_string(),
+        }
+    }
+}
+
+/// Downloads a file attached to a message and decodes it.
+///
+/// With the cloud Bot API the file is downloaded over HTTP, which Telegram only
+/// allows for files of up to 20 MB, and fed to ffmpeg while it is downloading.
+/// A local Bot API server has no such limit and returns a path on its
+/// filesystem instead, which ffmpeg reads directly.
+///
+/// Formats that a native converter reads, like voice messages, are downloaded
+/// in full and decoded by `converters` instead, with ffmpeg as the fallback.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_audio(
+    bot: &Bot,
+    config: &Config,
+    converter: &FFMpegAudioConverter,
+    converters: &Arc<ConverterRegistry>,
+    file_meta: &FileMeta,
+    mime_type: Option<&str>,
+    target: &TargetSpec,
+) -> Result<AudioData, BotError> {
+    let local_mode = config.telegram.local_mode;
+    if !local_mode && file_meta.size > CLOUD_API_DOWNLOAD_LIMIT {
+        return Err(BotError::ApiDownloadLimit(u64::from(file_meta.size)));
+    }
+
+    let file = match bot.get_file(&file_meta.id).await {
+        Ok(file) => file,
+        // The size in the message can be missing, Telegram reports it as an API error then
+        Err(RequestError::Api(ApiError::Unknown(e))) if e.contains("file is too big") => {
+            return Err(BotError::ApiDownloadLimit(u64::from(file_meta.size)))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let limit = config.limits.max_file_size;
+    // Only small audio files are decoded in memory. Larger ones and videos
+    // are streamed through ffmpeg, whose time and memory are limited.
+    let video = mime_type.is_some_and(|mime| mime.starts_with("video/"));
+    let native = !video
+        && u64::from(file_meta.size) <= config.limits.max_native_file_size
+        && converters.native_first(InputFormat::detect(mime_type, &[]));
+    if native {
+        let data = if local_mode {
+            log::info!("Reading {} from the local Bot API server", file.path);
+            let size = tokio::fs::metadata(&file.path).await?.len();
+            if size > limit {
+                return Err(BotError::FileTooLarge(size));
+            }
+            tokio::fs::read(&file.path).await?
         } else {
-            bot.send_message(msg.chat.id, recognized_text).await?;
+            let mut buffer = LimitedBuffer::new(limit, None);
+            let result = bot.download_file(&file.path, &mut buffer).await;
+            if buffer.exceeded {
+                return Err(BotError::FileTooLarge(buffer.data.len() as u64));
+            }
+            result?;
+            buffer.data
+        };
+
+        let converters = Arc::clone(converters);
+        let mime_type = mime_type.map(str::to_string);
+        let target = *target;
+        return Ok(run_blocking(move || converters.decode_bytes(data.into(), mime_type.as_deref(), &target)).await??);
+    }
+
+    if local_mode {
+        log::info!("Reading {} from the local Bot API server", file.path);
+        let size = tokio::fs::metadata(&file.path).await?.len();
+        if size > limit {
+            return Err(BotError::FileTooLarge(size));
         }
+        return Ok(converter.decode_file(&file.path, target).await?);
+    }
+
+    let decoder = converter.decode_stream(target)?;
+    // A stalled download would otherwise keep ffmpeg waiting for input forever
+    let deadline = decoder.deadline();
+    let mut buffer = LimitedBuffer::new(limit, Some(decoder));
+    let result = tokio::time::timeout_at(deadline, bot.download_file(&file.path, &mut buffer))
+        .await
+        .map_err(|_| ConversionError::Timeout(Duration::from_secs(config.ffmpeg.timeout_secs)))?;
+    if buffer.exceeded {
+        return Err(BotError::FileTooLarge(buffer.data.len() as u64));
+    }
+    result?;
+
+    // The decoder keeps the deadline it started with
+    match buffer.decoder.take() {
+        Some(decoder) => Ok(converter.finish_stream(decoder, &buffer.data, target).await?),
+        None => Ok(converter.decode_buffered(&buffer.data, target).await?),
+    }
+}
+
+/// In-memory download target that refuses to grow beyond `limit` bytes, in case
+/// the size Telegram reported was wrong. Everything written is also passed on
+/// to `decoder` for as long as it accepts input.
+struct LimitedBuffer {
+    data: Vec<u8>,
+    limit: u64,
+    exceeded: bool,
+    decoder: Option<StreamingDecoder>,
+}
+
+impl LimitedBuffer {
+    fn new(limit: u64, decoder: Option<StreamingDecoder>) -> Self {
+        LimitedBuffer {
+            data: Vec::new(),
+            limit,
+            exceeded: false,
+            decoder,
+        }
+    }
+}
+
+impl AsyncWrite for LimitedBuffer {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if (self.data.len() + buf.len()) as u64 > self.limit {
+            self.exceeded = true;
+            return Poll::Ready(Err(io::Error::other("file size limit exceeded")));
+        }
+
+        // Only what the decoder took is buffered, so both see the same bytes
+        let written = match self.decoder.as_mut().map(|decoder| Pin::new(decoder).poll_write(cx, buf)) {
+            Some(Poll::Pending) => return Poll::Pending,
+            Some(Poll::Ready(Ok(written))) => written,
+            // The decoder gave up, the buffer is decoded again once complete
+            Some(Poll::Ready(Err(_))) | None => buf.len(),
+        };
+        self.data.extend_from_slice(&buf[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.decoder.as_mut() {
+            // Errors show up when the decoder is finished
+            Some(decoder) => Pin::new(decoder).poll_flush(cx).map(|_| Ok(())),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // The decoder's input is closed by `StreamingDecoder::finish`
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// `m:ss`, or `h:mm:ss` for an hour and longer.
+fn format_duration(seconds: u32) -> String {
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
     } else {
-        bot.send_message(msg.chat.id, "Something went wrong").await?;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
     }
+}
+
+/// A request sending `contents` to the chat as a document named `file_name`.
+fn send_file(bot: &Bot, chat_id: ChatId, file_name: &str, contents: &[u8]) -> <Bot as Requester>::SendDocument {
+    bot.send_document(chat_id, InputFile::memory(contents.to_vec()).file_name(file_name.to_string()))
+}
 
+/// Sends `text` as a message. Text too long for one message is split into
+/// several or sent as a document named `file_name`, depending on `config`.
+/// `reply_markup` goes with the last message.
+async fn send_text(
+    bot: &Bot,
+    config: &MessagesConfig,
+    chat_id: ChatId,
+    text: String,
+    file_name: &str,
+    reply_markup: Option<InlineKeyboardMarkup>,
+) -> ResponseResult<()> {
+    let length = utf16_len(&text);
+    let as_document = length > config.max_message_chars
+        && (config.long_text == LongTextMode::Document || length > config.split_max_chars);
 
+    if as_document {
+        let request = send_file(bot, chat_id, file_name, text.as_bytes());
+        match reply_markup {
+            Some(markup) => request.reply_markup(markup).await?,
+            None => request.await?,
+        };
+        return Ok(());
+    }
+
+    let mut parts = split_message(&text, config.max_message_chars);
+    if parts.is_empty() {
+        parts.push(text);
+    }
+    let last = parts.len() - 1;
+    for (i, part) in parts.into_iter().enumerate() {
+        let request = bot.send_message(chat_id, part);
+        match &reply_markup {
+            Some(markup) if i == last => request.reply_markup(markup.clone()).await?,
+            _ => request.await?,
+        };
+    }
     Ok(())
 }
 
-async fn summarize(bot: Bot, msg: Message) -> ResponseResult<()> {
-    let text = msg.text().unwrap_or("No text provided");
-    let summary = format!("Summary of: {}", text); // Implement actual summarization logic here
+async fn summarize(bot: Bot, msg: Message, store: Arc<TranscriptStore>) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let text = msg.text().unwrap_or(lang.tr("summarize.no_text"));
+    let summary = lang.format("summarize.summary", &[("text", &text)]); // Implement actual summarization logic here
 
     bot.send_message(msg.chat.id, summary).await?;
 
@@ -167,3 +1143,478 @@ async fn summarize(bot: Bot, msg: Message) -> ResponseResult<()> {
 }
 
 
+
+async fn history(bot: Bot, msg: Message, page: String, store: Arc<TranscriptStore>) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let page: u32 = page.trim().parse().unwrap_or(1).max(1);
+
+    let offset = (page - 1).saturating_mul(HISTORY_PAGE_SIZE);
+
+    let chat_id = msg.chat.id.0;
+    let result = store.count(chat_id).and_then(|total| {
+        let transcripts = store.recent(chat_id, HISTORY_PAGE_SIZE, offset)?;
+        Ok((total, transcripts))
+    });
+    let (total, transcripts) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            log::error!("Failed to read history: {}", e);
+            bot.send_message(msg.chat.id, lang.tr("error.generic")).await?;
+            return Ok(());
+        }
+    };
+
+    if transcripts.is_empty() {
+        let text = if total == 0 {
+            lang.tr("history.empty").to_string()
+        } else {
+            lang.format("history.no_page", &[("page", &page)])
+        };
+        bot.send_message(msg.chat.id, text).await?;
+        return Ok(());
+    }
+
+    let first = offset + 1;
+    let last = first + transcripts.len() as u32 - 1;
+    let mut text = lang.format("history.header", &[("first", &first), ("last", &last), ("total", &total)]);
+    text.push_str("\n\n");
+    for transcript in &transcripts {
+        text.push_str(&transcript_header(transcript));
+        text.push('\n');
+        text.push_str(&html::escape(&preview(&transcript.text, PREVIEW_CHARS)));
+        text.push_str("\n\n");
+    }
+    if u64::from(offset) + (transcripts.len() as u64) < total {
+        text.push_str(&lang.format("history.older", &[("page", &(page + 1))]));
+    }
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .disable_web_page_preview(true)
+        .await?;
+    Ok(())
+}
+
+async fn search(bot: Bot, msg: Message, query: String, store: Arc<TranscriptStore>) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    if query.trim().is_empty() {
+        bot.send_message(msg.chat.id, lang.tr("search.usage")).await?;
+        return Ok(());
+    }
+
+    let hits = match store.search(msg.chat.id.0, &query, SEARCH_LIMIT) {
+        Ok(hits) => hits,
+        Err(e) => {
+            log::error!("Search for {:?} failed: {}", query, e);
+            bot.send_message(msg.chat.id, lang.tr("error.generic")).await?;
+            return Ok(());
+        }
+    };
+
+    if hits.is_empty() {
+        bot.send_message(msg.chat.id, lang.tr("search.nothing_found")).await?;
+        return Ok(());
+    }
+
+    let mut text = String::new();
+    for hit in &hits {
+        // Escape first, then turn the match markers into bold tags
+        let snippet = html::escape(&hit.snippet)
+            .replace(MATCH_START, "<b>")
+            .replace(MATCH_END, "</b>");
+        text.push_str(&transcript_header(&hit.transcript));
+        text.push('\n');
+        text.push_str(&snippet);
+        text.push_str("\n\n");
+    }
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .disable_web_page_preview(true)
+        .await?;
+    Ok(())
+}
+
+/// One line describing a stored transcript: date, sender, duration and a link
+/// to the original message where Telegram allows one.
+fn transcript_header(transcript: &StoredTranscript) -> String {
+    let date = chrono::DateTime::from_timestamp(transcript.created_at, 0)
+        .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    let duration = transcript.duration.round() as u64;
+
+    let mut header = format!("{} · {}:{:02}", date, duration / 60, duration % 60);
+    if let Some(sender) = &transcript.sender_name {
+        header = format!("{} · {}", header, sender);
+    }
+
+    let url = Message::url_of(
+        ChatId(transcript.chat_id),
+        transcript.chat_username.as_deref(),
+        MessageId(transcript.message_id),
+    );
+    match url {
+        Some(url) => html::link(url.as_str(), &html::escape(&header)),
+        None => html::escape(&header),
+    }
+}
+
+/// Shortens `text` to at most `max_chars` characters, cutting at a word boundary.
+fn preview(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+
+    let cut: String = text.chars().take(max_chars).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(pos) => &cut[..pos],
+        None => &cut[..],
+    };
+    format!("{}…", cut.trim_end())
+}
+
+async fn export(
+    bot: Bot,
+    msg: Message,
+    range: String,
+    config: Arc<Config>,
+    store: Arc<TranscriptStore>,
+) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let (from, to) = match parse_date_range(&range) {
+        Some(range) => range,
+        None => {
+            bot.send_message(msg.chat.id, lang.tr("export.usage")).await?;
+            return Ok(());
+        }
+    };
+
+    let transcripts = match store.in_range(msg.chat.id.0, from, to) {
+        Ok(transcripts) => transcripts,
+        Err(e) => {
+            log::error!("Failed to read transcripts for export: {}", e);
+            bot.send_message(msg.chat.id, lang.tr("error.generic")).await?;
+            return Ok(());
+        }
+    };
+    if transcripts.is_empty() {
+        bot.send_message(msg.chat.id, lang.tr("export.empty")).await?;
+        return Ok(());
+    }
+
+    let speaker = |number| speaker_label(lang, number);
+    let layout = chat_paragraphs(&store, &config, msg.chat.id);
+    let archives =
+        build_archives(&transcripts, config.export.max_archive_bytes, &layout, &speaker).map_err(|e| e.to_string());
+    let archives = match archives {
+        Ok(archives) => archives,
+        Err(e) => {
+            log::error!("Failed to build export archive: {}", e);
+            bot.send_message(msg.chat.id, lang.tr("error.generic")).await?;
+            return Ok(());
+        }
+    };
+
+    let parts = archives.len();
+    for (i, archive) in archives.iter().enumerate() {
+        let file_name = if parts == 1 {
+            "transcripts.zip".to_string()
+        } else {
+            format!("transcripts_part{}of{}.zip", i + 1, parts)
+        };
+        send_file(&bot, msg.chat.id, &file_name, archive).await?;
+    }
+
+    Ok(())
+}
+
+/// Parses the optional `[from] [to]` arguments of /export into an inclusive
+/// range of Unix timestamps (UTC days). Returns `None` on malformed input.
+fn parse_date_range(args: &str) -> Option<(i64, i64)> {
+    let parse = |date: &str| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (from, to) = match args.as_slice() {
+        [] => (None, None),
+        [from] => (Some(parse(from)?), None),
+        [from, to] => (Some(parse(from)?), Some(parse(to)?)),
+        _ => return None,
+    };
+
+    let from = from
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp())
+        .unwrap_or(i64::MIN);
+    let to = to
+        .map(|date| date.and_hms_opt(23, 59, 59).unwrap().and_utc().timestamp())
+        .unwrap_or(i64::MAX);
+
+    Some((from, to))
+}
+
+/// Shows the language of the chat, or changes it: `en`, `ru`, or `auto` to follow
+/// the language of each user's Telegram app again.
+async fn language(bot: Bot, msg: Message, code: String, store: Arc<TranscriptStore>) -> ResponseResult<()> {
+    let code = code.trim();
+    let chat_id = msg.chat.id.0;
+
+    let update = match code {
+        "" => None,
+        "auto" => Some(None),
+        code => match Language::from_code(code) {
+            Some(lang) => Some(Some(lang)),
+            None => {
+                let lang = chat_language(&store, &msg);
+                bot.send_message(msg.chat.id, lang.tr("language.usage")).await?;
+                return Ok(());
+            }
+        },
+    };
+
+    if let Some(new_language) = update {
+        if let Err(e) = store.set_chat_setting(chat_id, LANGUAGE_SETTING, new_language.map(Language::code)) {
+            log::error!("Failed to change the language of chat {}: {}", chat_id, e);
+            let lang = chat_language(&store, &msg);
+            bot.send_message(msg.chat.id, lang.tr("error.generic")).await?;
+            return Ok(());
+        }
+    }
+
+    let lang = chat_language(&store, &msg);
+    let name = lang.tr("language.name");
+    let key = match update {
+        Some(_) => "language.set",
+        None if store.chat_setting(chat_id, LANGUAGE_SETTING).ok().flatten().is_some() => "language.current",
+        None => "language.current_auto",
+    };
+    bot.send_message(msg.chat.id, lang.format(key, &[("language", &name)])).await?;
+    Ok(())
+}
+
+/// Shows or changes the preprocessing stages of the chat.
+async fn preprocess(
+    bot: Bot,
+    msg: Message,
+    stages: String,
+    config: Arc<Config>,
+    store: Arc<TranscriptStore>,
+) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let stages = stages.trim();
+    let chat_id = msg.chat.id.0;
+
+    let update = match stages {
+        "" => None,
+        "default" => Some(None),
+        stages => match parse_stages(stages) {
+            Some(stages) => Some(Some(format_stages(&stages))),
+            None => {
+                bot.send_message(msg.chat.id, lang.tr("preprocess.usage")).await?;
+                return Ok(());
+            }
+        },
+    };
+
+    if let Some(setting) = &update {
+        if let Err(e) = store.set_chat_setting(chat_id, PREPROCESS_SETTING, setting.as_deref()) {
+            log::error!("Failed to change the preprocessing of chat {}: {}", chat_id, e);
+            bot.send_message(msg.chat.id, lang.tr("error.generic")).await?;
+            return Ok(());
+        }
+    }
+
+    let current = chat_preprocessing(&store, &config, msg.chat.id);
+    let names = if current.is_empty() {
+        lang.tr("preprocess.none").to_string()
+    } else {
+        current.iter().map(|stage| stage.name()).collect::<Vec<_>>().join(", ")
+    };
+    let key = match update {
+        Some(_) => "preprocess.set",
+        None if store.chat_setting(chat_id, PREPROCESS_SETTING).ok().flatten().is_some() => "preprocess.current",
+        None => "preprocess.current_default",
+    };
+    bot.send_message(msg.chat.id, lang.format(key, &[("stages", &names)])).await?;
+    Ok(())
+}
+
+/// Shows or changes how many speakers are told apart in the chat.
+async fn speakers(
+    bot: Bot,
+    msg: Message,
+    speakers: String,
+    config: Arc<Config>,
+    store: Arc<TranscriptStore>,
+) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let chat_id = msg.chat.id.0;
+    let max_speakers = config.diarization.max_speakers;
+
+    let update = match speakers.trim() {
+        "" => None,
+        "default" => Some(None),
+        speakers => match Speakers::parse(speakers) {
+            Some(speakers) if !matches!(speakers, Speakers::Count(count) if count > max_speakers) => {
+                Some(Some(speakers.name()))
+            }
+            _ => {
+                bot.send_message(msg.chat.id, lang.format("speakers.usage", &[("max", &max_speakers)])).await?;
+                return Ok(());
+            }
+        },
+    };
+
+    if let Some(setting) = &update {
+        if let Err(e) = store.set_chat_setting(chat_id, SPEAKERS_SETTING, setting.as_deref()) {
+            log::error!("Failed to change the speakers of chat {}: {}", chat_id, e);
+            bot.send_message(msg.chat.id, lang.tr("error.generic")).await?;
+            return Ok(());
+        }
+    }
+
+    let name = match chat_speakers(&store, &config, msg.chat.id) {
+        Speakers::Off => lang.tr("speakers.off").to_string(),
+        Speakers::Auto => lang.tr("speakers.auto").to_string(),
+        Speakers::Count(count) => count.to_string(),
+    };
+    let key = match update {
+        Some(_) => "speakers.set",
+        None if store.chat_setting(chat_id, SPEAKERS_SETTING).ok().flatten().is_some() => "speakers.current",
+        None => "speakers.current_default",
+    };
+    bot.send_message(msg.chat.id, lang.format(key, &[("speakers", &name)])).await?;
+    Ok(())
+}
+
+/// Shows or changes how transcripts in the chat are split into paragraphs.
+async fn paragraphs(
+    bot: Bot,
+    msg: Message,
+    mode: String,
+    config: Arc<Config>,
+    store: Arc<TranscriptStore>,
+) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let chat_id = msg.chat.id.0;
+
+    let update = match mode.trim() {
+        "" => None,
+        "default" => Some(None),
+        mode => match ParagraphMode::from_name(&mode.to_lowercase()) {
+            Some(mode) => Some(Some(mode.name())),
+            None => {
+                bot.send_message(msg.chat.id, lang.tr("paragraphs.usage")).await?;
+                return Ok(());
+            }
+        },
+    };
+
+    if let Some(setting) = update {
+        if let Err(e) = store.set_chat_setting(chat_id, PARAGRAPHS_SETTING, setting) {
+            log::error!("Failed to change the paragraph mode of chat {}: {}", chat_id, e);
+            bot.send_message(msg.chat.id, lang.tr("error.generic")).await?;
+            return Ok(());
+        }
+    }
+
+    let mode = chat_paragraphs(&store, &config, msg.chat.id).mode;
+    let name = lang.tr(&format!("paragraphs.{}", mode.name())).to_string();
+    let key = match update {
+        Some(_) => "paragraphs.set",
+        None if store.chat_setting(chat_id, PARAGRAPHS_SETTING).ok().flatten().is_some() => "paragraphs.current",
+        None => "paragraphs.current_default",
+    };
+    bot.send_message(msg.chat.id, lang.format(key, &[("mode", &name)])).await?;
+    Ok(())
+}
+
+/// Lists the chat's vocabulary or adds terms to it or removes them.
+async fn vocab(
+    bot: Bot,
+    msg: Message,
+    args: String,
+    config: Arc<Config>,
+    store: Arc<TranscriptStore>,
+) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let chat_id = msg.chat.id.0;
+    let limits = &config.vocabulary;
+    let mut vocabulary = chat_vocabulary(&store, msg.chat.id);
+
+    let (action, terms) = args.trim().split_once(char::is_whitespace).unwrap_or((args.trim(), ""));
+    let terms = parse_terms(terms);
+    let reply = match (action, terms.is_empty()) {
+        ("" | "list", true) if vocabulary.is_empty() => lang.tr("vocab.empty").to_string(),
+        ("" | "list", true) => lang.format("vocab.list", &[("terms", &vocabulary.join(", "))]),
+        ("add", false) => {
+            if let Some(term) = terms.iter().find(|term| term.chars().count() > limits.max_term_chars) {
+                let reply = lang.format("vocab.too_long", &[("term", term), ("max", &limits.max_term_chars)]);
+                bot.send_message(msg.chat.id, reply).await?;
+                return Ok(());
+            }
+            let added: Vec<String> = terms.into_iter().filter(|term| !contains(&vocabulary, term)).collect();
+            if vocabulary.len() + added.len() > limits.max_terms {
+                bot.send_message(msg.chat.id, lang.format("vocab.too_many", &[("max", &limits.max_terms)])).await?;
+                return Ok(());
+            }
+            vocabulary.extend(added);
+            lang.format("vocab.list", &[("terms", &vocabulary.join(", "))])
+        }
+        ("remove", false) => {
+            let missing: Vec<&String> = terms.iter().filter(|term| !contains(&vocabulary, term)).collect();
+            if !missing.is_empty() {
+                let missing = missing.iter().map(|term| term.as_str()).collect::<Vec<_>>().join(", ");
+                bot.send_message(msg.chat.id, lang.format("vocab.not_found", &[("terms", &missing)])).await?;
+                return Ok(());
+            }
+            vocabulary.retain(|known| !contains(&terms, known));
+            if vocabulary.is_empty() {
+                lang.tr("vocab.empty").to_string()
+            } else {
+                lang.format("vocab.list", &[("terms", &vocabulary.join(", "))])
+            }
+        }
+        _ => {
+            bot.send_message(msg.chat.id, lang.tr("vocab.usage")).await?;
+            return Ok(());
+        }
+    };
+
+    if action == "add" || action == "remove" {
+        let setting = (!vocabulary.is_empty()).then(|| vocabulary.join("\n"));
+        if let Err(e) = store.set_chat_setting(chat_id, VOCABULARY_SETTING, setting.as_deref()) {
+            log::error!("Failed to change the vocabulary of chat {}: {}", chat_id, e);
+            bot.send_message(msg.chat.id, lang.tr("error.generic")).await?;
+            return Ok(());
+        }
+    }
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(())
+}
+
+/// Whether `terms` has `term`, ignoring case.
+fn contains(terms: &[String], term: &str) -> bool {
+    terms.iter().any(|known| known.to_lowercase() == term.to_lowercase())
+}
+
+/// Empties the transcript cache. Admins only.
+async fn purge_cache(bot: Bot, msg: Message, config: Arc<Config>, store: Arc<TranscriptStore>) -> ResponseResult<()> {
+    let lang = chat_language(&store, &msg);
+    let is_admin = msg.from().is_some_and(|user| config.is_admin(user.id.0));
+    if !is_admin {
+        bot.send_message(msg.chat.id, lang.tr("admin.not_allowed")).await?;
+        return Ok(());
+    }
+
+    let text = match store.purge_cache() {
+        Ok(removed) => {
+            log::info!("Transcript cache purged, {} entries removed", removed);
+            lang.format("cache.purged", &[("count", &removed)])
+        }
+        Err(e) => {
+            log::error!("Failed to purge the transcript cache: {}", e);
+            lang.tr("error.generic").to_string()
+        }
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
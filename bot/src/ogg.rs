@@ -0,0 +1,118 @@
+pub mod ogg {
+    use crate::audio_conversion::audio_conversion::ConversionError;
+
+    /// Length of a page header without the segment table.
+    const PAGE_HEADER: usize = 27;
+
+    const FLAG_CONTINUED: u8 = 0x01;
+    const FLAG_FIRST: u8 = 0x02;
+    const FLAG_LAST: u8 = 0x04;
+
+    /// Granule position of pages on which no packet ends.
+    const NO_GRANULE: u64 = u64::MAX;
+
+    /// Lookup table of the CRC-32 Ogg uses: polynomial 0x04C11DB7, not
+    /// reflected, starting from 0.
+    const CRC_TABLE: [u32; 256] = crc_table();
+
+    /// The packets of one logical stream of an Ogg file.
+    #[derive(Debug, Clone, Default)]
+    pub struct OggStream {
+        pub serial: u32,
+        pub packets: Vec<Vec<u8>>,
+        /// Granule position of the last page that has one. What it counts
+        /// depends on the codec.
+        pub final_granule: Option<u64>,
+    }
+
+    /// Reads the packets of the first logical stream in `data`. Pages of other
+    /// streams, e.g. a video track, are skipped.
+    pub fn read_ogg(data: &[u8]) -> Result<OggStream, ConversionError> {
+        let mut stream: Option<OggStream> = None;
+        let mut partial = Vec::new();
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let header = data.get(offset..offset + PAGE_HEADER).ok_or_else(|| invalid("file ends inside a page header"))?;
+            if &header[0..4] != b"OggS" {
+                return Err(invalid(format!("no page at byte {}", offset)));
+            }
+            if header[4] != 0 {
+                return Err(invalid(format!("unknown version {}", header[4])));
+            }
+            let flags = header[5];
+            let granule = u64::from_le_bytes(header[6..14].try_into().unwrap());
+            let serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+            let checksum = u32::from_le_bytes(header[22..26].try_into().unwrap());
+            let segments = header[26] as usize;
+
+            let lacing = data
+                .get(offset + PAGE_HEADER..offset + PAGE_HEADER + segments)
+                .ok_or_else(|| invalid("file ends inside a segment table"))?;
+            let body_len: usize = lacing.iter().map(|&len| len as usize).sum();
+            let page_len = PAGE_HEADER + segments + body_len;
+            let page = data.get(offset..offset + page_len).ok_or_else(|| invalid("file ends inside a page"))?;
+            if crc(page) != checksum {
+                return Err(invalid(format!("bad checksum of the page at byte {}", offset)));
+            }
+            offset += page_len;
+
+            let stream = match &mut stream {
+                Some(stream) if stream.serial != serial => continue,
+                Some(stream) => stream,
+                None if flags & FLAG_FIRST == 0 => return Err(invalid("the first page does not start a stream")),
+                None => stream.insert(OggStream { serial, ..OggStream::default() }),
+            };
+
+            // A packet left over from a page that isn't continued here is lost
+            if flags & FLAG_CONTINUED == 0 {
+                partial.clear();
+            }
+            let mut body = &page[PAGE_HEADER + segments..];
+            for &len in lacing {
+                partial.extend_from_slice(&body[..len as usize]);
+                body = &body[len as usize..];
+                // A segment shorter than 255 bytes ends the packet
+                if len < 255 {
+                    stream.packets.push(std::mem::take(&mut partial));
+                }
+            }
+            if granule != NO_GRANULE {
+                stream.final_granule = Some(granule);
+            }
+            if flags & FLAG_LAST != 0 {
+                break;
+            }
+        }
+
+        stream.ok_or_else(|| invalid("no pages"))
+    }
+
+    /// Checksum of `page` with its checksum field taken as zero.
+    pub fn crc(page: &[u8]) -> u32 {
+        page.iter().enumerate().fold(0u32, |crc, (i, &byte)| {
+            let byte = if (22..26).contains(&i) { 0 } else { byte };
+            (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]
+        })
+    }
+
+    const fn crc_table() -> [u32; 256] {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = (i as u32) << 24;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    }
+
+    fn invalid<S: Into<String>>(reason: S) -> ConversionError {
+        ConversionError::Decode { format: "Ogg", reason: reason.into() }
+    }
+}
@@ -0,0 +1,121 @@
+pub mod opus_converter {
+    use opus_decoder::OpusDecoder;
+    use crate::audio_conversion::audio_conversion::{
+        AudioConverter, AudioData, ConversionError, InputFormat, TargetSpec,
+    };
+    use crate::ogg::ogg::read_ogg;
+    use crate::wav::wav::write_wav;
+
+    /// Opus positions and pre-skip are always counted at 48 kHz, whatever
+    /// rate the audio is decoded at.
+    const GRANULE_RATE: u64 = 48_000;
+
+    /// Rates the Opus decoder can produce.
+    const DECODER_RATES: [u32; 5] = [8_000, 12_000, 16_000, 24_000, 48_000];
+
+    /// Fields of the identification header that decoding needs.
+    #[derive(Debug, Clone, Copy)]
+    struct OpusHead {
+        channels: u8,
+        pre_skip: u16,
+        /// Output gain in dB, Q7.8 fixed point
+        gain: i16,
+    }
+
+    /// Decodes Ogg Opus, which is what Telegram voice messages are, without
+    /// ffmpeg. Opus decodes straight to any rate Whisper could ask for, so no
+    /// resampling is needed.
+    pub struct OpusConverter;
+
+    impl AudioConverter for OpusConverter {
+        fn convert_audio_to_wav(&self, input_data: &[u8], target: &TargetSpec) -> Result<Vec<u8>, ConversionError> {
+            Ok(write_wav(&self.decode_audio(input_data, target)?.samples, target))
+        }
+
+        fn name(&self) -> &'static str {
+            "opus"
+        }
+
+        fn supports(&self, format: InputFormat) -> bool {
+            // Plain Ogg may still turn out to be Opus once it's read
+            matches!(format, InputFormat::OggOpus | InputFormat::Ogg)
+        }
+
+        fn decode_audio(&self, input_data: &[u8], target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            if !DECODER_RATES.contains(&target.sample_rate) {
+                return Err(ConversionError::UnsupportedFormat(format!("Opus can't be decoded at {} Hz", target.sample_rate)));
+            }
+            if !(1..=2).contains(&target.channels) {
+                return Err(ConversionError::UnsupportedFormat(format!("Opus can't be decoded to {} channels", target.channels)));
+            }
+
+            let stream = read_ogg(input_data)?;
+            let mut packets = stream.packets.iter();
+            let head = parse_head(packets.next().map(Vec::as_slice).unwrap_or_default())?;
+            match packets.next() {
+                Some(tags) if tags.starts_with(b"OpusTags") => {}
+                _ => return Err(invalid("missing OpusTags header")),
+            }
+
+            let channels = target.channels as usize;
+            let mut decoder = OpusDecoder::new(target.sample_rate, channels).map_err(|e| invalid(format!("{:?}", e)))?;
+            let mut frame = vec![0f32; decoder.max_frame_size_per_channel() * channels];
+            // The pre-skip is dropped below, so it doesn't count towards the limit
+            let max_samples = target
+                .max_samples(target.sample_rate, target.channels)
+                .saturating_add((head.pre_skip as u64 * target.sample_rate as u64 / GRANULE_RATE) as usize * channels);
+            let mut samples = Vec::new();
+            for packet in packets {
+                let decoded = decoder.decode_float(packet, &mut frame, false).map_err(|e| invalid(format!("{:?}", e)))?;
+                samples.extend_from_slice(&frame[..decoded * channels]);
+                if samples.len() > max_samples {
+                    samples.truncate(max_samples);
+                    break;
+                }
+            }
+
+            // The encoder's priming samples at the start, and the padding of
+            // the last packet past the final granule position at the end
+            let to_frames = |granule: u64| (granule * target.sample_rate as u64 / GRANULE_RATE) as usize;
+            let skip = to_frames(head.pre_skip as u64) * channels;
+            let end = stream.final_granule.map_or(samples.len(), |granule| to_frames(granule) * channels);
+            let mut samples = samples.get(skip..end.min(samples.len())).unwrap_or_default().to_vec();
+
+            if head.gain != 0 {
+                let gain = 10f32.powf(head.gain as f32 / (20.0 * 256.0));
+                samples.iter_mut().for_each(|sample| *sample = (*sample * gain).clamp(-1.0, 1.0));
+            }
+            log::debug!("Decoded {} Opus channels to {} at {} Hz", head.channels, target.channels, target.sample_rate);
+
+            Ok(AudioData::new(samples, target.sample_rate, target.channels)
+                .with_source(Some("ogg".to_string()), Some("opus".to_string())))
+        }
+    }
+
+    fn parse_head(packet: &[u8]) -> Result<OpusHead, ConversionError> {
+        if packet.len() < 19 || !packet.starts_with(b"OpusHead") {
+            return Err(invalid("missing OpusHead header"));
+        }
+        // Only the major version, the upper four bits, is incompatible
+        if packet[8] >> 4 != 0 {
+            return Err(invalid(format!("unknown version {}", packet[8])));
+        }
+        let head = OpusHead {
+            channels: packet[9],
+            pre_skip: u16::from_le_bytes([packet[10], packet[11]]),
+            gain: i16::from_le_bytes([packet[16], packet[17]]),
+        };
+        // Family 0 is mono or stereo without a channel mapping table
+        if packet[18] != 0 || !(1..=2).contains(&head.channels) {
+            return Err(ConversionError::UnsupportedFormat(format!(
+                "Opus with {} channels in mapping family {}",
+                head.channels, packet[18]
+            )));
+        }
+        Ok(head)
+    }
+
+    fn invalid<S: Into<String>>(reason: S) -> ConversionError {
+        ConversionError::Decode { format: "Opus", reason: reason.into() }
+    }
+}
@@ -0,0 +1,327 @@
+pub mod preprocess {
+    use std::f64::consts::PI;
+    use rustfft::num_complex::Complex;
+    use rustfft::{Fft, FftPlanner};
+    use std::sync::Arc;
+    use crate::audio_conversion::audio_conversion::AudioData;
+    use crate::config::config::{PreprocessConfig, PreprocessStage};
+    use crate::levels::levels::{Biquad, Levels, SILENCE_DB};
+
+    /// Length of the noise reducer's analysis frames in seconds, rounded up to a
+    /// power of two samples.
+    const DENOISE_FRAME_SECS: f64 = 0.032;
+
+    /// Fraction of the quietest frames the noise profile is taken from.
+    const NOISE_FRAME_FRACTION: f64 = 0.1;
+
+    /// Neighbouring frequency bins on each side the gating mask is averaged over,
+    /// which avoids "musical noise" from single bins switching on and off.
+    const MASK_SMOOTHING_BINS: usize = 2;
+
+    /// One step of the preprocessing, changing the samples of a recording in place.
+    pub trait Processor: Send + Sync {
+        fn name(&self) -> &'static str;
+
+        /// Processes `audio`. Its `levels` are measured again by the pipeline
+        /// afterwards.
+        fn process(&self, audio: &mut AudioData);
+    }
+
+    /// Processors applied one after the other.
+    #[derive(Default)]
+    pub struct Pipeline {
+        processors: Vec<Box<dyn Processor>>,
+    }
+
+    impl Pipeline {
+        pub fn new() -> Self {
+            Pipeline::default()
+        }
+
+        /// Adds `processor` at the end of the pipeline.
+        pub fn then<P: Processor + 'static>(mut self, processor: P) -> Self {
+            self.processors.push(Box::new(processor));
+            self
+        }
+
+        /// The pipeline for `stages`, in their fixed order, with the parameters
+        /// from `config`.
+        pub fn from_stages(stages: &[PreprocessStage], config: &PreprocessConfig) -> Self {
+            canonical(stages).into_iter().fold(Pipeline::new(), |pipeline, stage| match stage {
+                PreprocessStage::HighPass => pipeline.then(HighPass { cutoff_hz: config.high_pass_hz }),
+                PreprocessStage::Denoise => pipeline.then(Denoise {
+                    reduction_db: config.denoise_reduction_db,
+                    threshold: config.denoise_threshold,
+                }),
+                PreprocessStage::Normalize => pipeline.then(Normalize {
+                    target_lufs: config.target_lufs,
+                    max_gain_db: config.max_gain_db,
+                    peak_limit_db: config.peak_limit_db,
+                }),
+            })
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.processors.is_empty()
+        }
+
+        /// Names of the processors in the order they run.
+        pub fn names(&self) -> Vec<&'static str> {
+            self.processors.iter().map(|processor| processor.name()).collect()
+        }
+
+        /// Runs all processors on `audio` and measures its levels again.
+        pub fn apply(&self, mut audio: AudioData) -> AudioData {
+            if self.processors.is_empty() {
+                return audio;
+            }
+            for processor in &self.processors {
+                processor.process(&mut audio);
+            }
+            audio.levels = Levels::measure(&audio.samples, audio.sample_rate, audio.channels);
+            audio
+        }
+    }
+
+    /// `stages` sorted into the order they run in, without duplicates.
+    pub fn canonical(stages: &[PreprocessStage]) -> Vec<PreprocessStage> {
+        let mut stages = stages.to_vec();
+        stages.sort();
+        stages.dedup();
+        stages
+    }
+
+    /// Text form of `stages` as stored in the chat settings: names separated by
+    /// commas, or "none".
+    pub fn format_stages(stages: &[PreprocessStage]) -> String {
+        let stages = canonical(stages);
+        if stages.is_empty() {
+            return "none".to_string();
+        }
+        stages.iter().map(|stage| stage.name()).collect::<Vec<_>>().join(",")
+    }
+
+    /// Inverse of `format_stages`. Also accepts names separated by spaces.
+    pub fn parse_stages(text: &str) -> Option<Vec<PreprocessStage>> {
+        let names: Vec<&str> = text.split(|c: char| c == ',' || c.is_whitespace()).filter(|name| !name.is_empty()).collect();
+        if names == ["none"] {
+            return Some(Vec::new());
+        }
+        if names.is_empty() {
+            return None;
+        }
+        let stages = names.into_iter().map(PreprocessStage::from_name).collect::<Option<Vec<_>>>()?;
+        Some(canonical(&stages))
+    }
+
+    /// Removes the DC offset and everything below `cutoff_hz` with a
+    /// second-order Butterworth filter: rumble from cars, wind and handling noise.
+    pub struct HighPass {
+        pub cutoff_hz: f64,
+    }
+
+    impl Processor for HighPass {
+        fn name(&self) -> &'static str {
+            "high_pass"
+        }
+
+        fn process(&self, audio: &mut AudioData) {
+            let channels = audio.channels.max(1) as usize;
+            let rate = audio.sample_rate as f64;
+            let frames = audio.samples.len() / channels;
+            if frames == 0 {
+                return;
+            }
+
+            for channel in 0..channels {
+                // Removed up front, the filter would take a while to settle on a large offset
+                let offset =
+                    audio.samples.iter().skip(channel).step_by(channels).map(|&s| s as f64).sum::<f64>() / frames as f64;
+                let mut filter = butterworth_high_pass(self.cutoff_hz.min(rate * 0.45), rate);
+                for sample in audio.samples.iter_mut().skip(channel).step_by(channels) {
+                    *sample = filter.process(*sample as f64 - offset) as f32;
+                }
+            }
+        }
+    }
+
+    /// Filter coefficients from the Audio EQ Cookbook.
+    fn butterworth_high_pass(cutoff_hz: f64, rate: f64) -> Biquad {
+        let w0 = 2.0 * PI * cutoff_hz / rate;
+        let alpha = w0.sin() / 2.0f64.sqrt(); // Q of 1/sqrt(2)
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Biquad::new(
+            [(1.0 + cos) / 2.0 / a0, -(1.0 + cos) / a0, (1.0 + cos) / 2.0 / a0],
+            [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        )
+    }
+
+    /// Applies a constant gain that brings the loudness to `target_lufs`,
+    /// limited to `max_gain_db` and to what keeps the peaks below
+    /// `peak_limit_db`. Silent recordings are left alone.
+    pub struct Normalize {
+        pub target_lufs: f64,
+        pub max_gain_db: f64,
+        pub peak_limit_db: f64,
+    }
+
+    impl Processor for Normalize {
+        fn name(&self) -> &'static str {
+            "normalize"
+        }
+
+        fn process(&self, audio: &mut AudioData) {
+            let levels = Levels::measure(&audio.samples, audio.sample_rate, audio.channels);
+            if levels.loudness_lufs <= SILENCE_DB {
+                return;
+            }
+
+            let gain_db = (self.target_lufs - levels.loudness_lufs)
+                .min(self.max_gain_db)
+                .min(self.peak_limit_db - levels.peak_db);
+            let gain = 10f64.powf(gain_db / 20.0) as f32;
+            for sample in &mut audio.samples {
+                *sample *= gain;
+            }
+        }
+    }
+
+    /// Spectral gating noise reduction.
+    ///
+    /// The noise spectrum is estimated from the quietest frames of the
+    /// recording. Frequency components that are not clearly above it, by
+    /// `threshold` standard deviations, are attenuated by `reduction_db`.
+    pub struct Denoise {
+        pub reduction_db: f64,
+        pub threshold: f64,
+    }
+
+    impl Processor for Denoise {
+        fn name(&self) -> &'static str {
+            "denoise"
+        }
+
+        fn process(&self, audio: &mut AudioData) {
+            let channels = audio.channels.max(1) as usize;
+            for channel in 0..channels {
+                let samples: Vec<f32> = audio.samples.iter().skip(channel).step_by(channels).copied().collect();
+                let denoised = self.denoise(&samples, audio.sample_rate);
+                for (sample, denoised) in audio.samples.iter_mut().skip(channel).step_by(channels).zip(denoised) {
+                    *sample = denoised;
+                }
+            }
+        }
+    }
+
+    impl Denoise {
+        fn denoise(&self, samples: &[f32], sample_rate: u32) -> Vec<f32> {
+            let stft = Stft::new(sample_rate);
+            if samples.len() < stft.size {
+                // Too short to tell the noise from the rest
+                return samples.to_vec();
+            }
+
+            // Every sample is covered by two half-overlapping frames
+            let hop = stft.size / 2;
+            let mut padded = vec![0.0; hop];
+            padded.extend_from_slice(samples);
+            padded.resize(hop + samples.len() + stft.size, 0.0);
+            let starts: Vec<usize> = (0..=padded.len() - stft.size).step_by(hop).collect();
+            let bins = stft.size / 2 + 1;
+
+            // Noise profile from the quietest frames, in dB per bin. The spectra
+            // are computed again later instead of being kept, they'd take much
+            // more memory than the recording itself.
+            let mut energies: Vec<(usize, f32)> = starts
+                .iter()
+                // Frames reaching into the padding would pass for silence
+                .filter(|&&start| start >= hop && start + stft.size <= hop + samples.len())
+                .map(|&start| (start, stft.spectrum(&padded[start..]).iter().map(|c| c.norm_sqr()).sum()))
+                .collect();
+            energies.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let quiet = ((energies.len() as f64 * NOISE_FRAME_FRACTION) as usize).max(1);
+            let noise: Vec<Vec<f64>> =
+                energies[..quiet].iter().map(|&(start, _)| magnitudes_db(&stft.spectrum(&padded[start..]), bins)).collect();
+            let threshold: Vec<f64> = (0..bins)
+                .map(|bin| {
+                    let mean = noise.iter().map(|frame| frame[bin]).sum::<f64>() / quiet as f64;
+                    let variance = noise.iter().map(|frame| (frame[bin] - mean).powi(2)).sum::<f64>() / quiet as f64;
+                    mean + self.threshold * variance.sqrt()
+                })
+                .collect();
+
+            let floor = 10f64.powf(-self.reduction_db / 20.0);
+            let mut output = vec![0.0f32; padded.len()];
+            let mut previous = vec![1.0; bins];
+            for &start in &starts {
+                let mut spectrum = stft.spectrum(&padded[start..]);
+                let db = magnitudes_db(&spectrum, bins);
+                let open: Vec<f64> =
+                    db.iter().zip(&threshold).map(|(db, threshold)| if db >= threshold { 1.0 } else { 0.0 }).collect();
+
+                // Smoothed over neighbouring bins and, with the previous frame, over time
+                let mut mask = vec![0.0; bins];
+                for (bin, mask) in mask.iter_mut().enumerate() {
+                    let from = bin.saturating_sub(MASK_SMOOTHING_BINS);
+                    let until = (bin + MASK_SMOOTHING_BINS + 1).min(bins);
+                    let current = open[from..until].iter().sum::<f64>() / (until - from) as f64;
+                    *mask = (current + previous[bin]) / 2.0;
+                }
+
+                for (bin, &mask) in mask.iter().enumerate() {
+                    let gain = (floor + (1.0 - floor) * mask) as f32;
+                    spectrum[bin] *= gain;
+                    // The mirrored negative frequencies of the real signal
+                    if bin > 0 && bin < stft.size - bin {
+                        spectrum[stft.size - bin] *= gain;
+                    }
+                }
+                previous = mask;
+
+                stft.inverse.process(&mut spectrum);
+                // Hann windows half a frame apart add up to one, so the frames
+                // only have to be added
+                for (out, value) in output[start..start + stft.size].iter_mut().zip(&spectrum) {
+                    *out += value.re / stft.size as f32;
+                }
+            }
+
+            output[hop..hop + samples.len()].to_vec()
+        }
+    }
+
+    /// Short-time Fourier transform with a periodic Hann window.
+    struct Stft {
+        size: usize,
+        window: Vec<f32>,
+        forward: Arc<dyn Fft<f32>>,
+        inverse: Arc<dyn Fft<f32>>,
+    }
+
+    impl Stft {
+        fn new(sample_rate: u32) -> Self {
+            let size = ((sample_rate as f64 * DENOISE_FRAME_SECS) as usize).next_power_of_two().max(64);
+            let window = (0..size).map(|i| (0.5 - 0.5 * (2.0 * PI * i as f64 / size as f64).cos()) as f32).collect();
+            let mut planner = FftPlanner::new();
+            Stft {
+                size,
+                window,
+                forward: planner.plan_fft_forward(size),
+                inverse: planner.plan_fft_inverse(size),
+            }
+        }
+
+        /// Spectrum of the windowed frame at the start of `samples`.
+        fn spectrum(&self, samples: &[f32]) -> Vec<Complex<f32>> {
+            let mut buffer: Vec<Complex<f32>> =
+                samples[..self.size].iter().zip(&self.window).map(|(&s, &w)| Complex::new(s * w, 0.0)).collect();
+            self.forward.process(&mut buffer);
+            buffer
+        }
+    }
+
+    fn magnitudes_db(spectrum: &[Complex<f32>], bins: usize) -> Vec<f64> {
+        spectrum[..bins].iter().map(|c| 20.0 * (c.norm() as f64 + 1e-10).log10()).collect()
+    }
+}
@@ -1,70 +1,333 @@
 pub mod speech_to_text {
-    use std::env;
-    use std::error::Error;
     use std::ffi::c_int;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
-    use whisper_rs::{FullParams, WhisperContext};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use serde::{Deserialize, Serialize};
+    use thiserror::Error;
+    use whisper_rs::{FullParams, WhisperContext, WhisperError, WhisperState};
+    use crate::audio_conversion::audio_conversion::{SampleFormat, TargetSpec};
+    use crate::config::config::WhisperConfig;
+    use crate::hallucination::hallucination::HallucinationFilter;
+    use crate::vocabulary::vocabulary::{correct_segments, prompt};
+
+    /// Sample rate of the audio whisper expects.
+    pub const WHISPER_SAMPLE_RATE: u32 = 16000;
+
+    /// Audio whisper expects: 16 kHz mono samples as floats.
+    pub const WHISPER_INPUT: TargetSpec = TargetSpec {
+        sample_rate: WHISPER_SAMPLE_RATE,
+        channels: 1,
+        sample_format: SampleFormat::F32,
+        max_duration_secs: None,
+    };
+
+    /// Errors from loading the model or running recognition.
+    #[derive(Debug, Error)]
+    pub enum RecognitionError {
+        #[error("model path {} is not valid UTF-8", .0.display())]
+        InvalidModelPath(PathBuf),
+        #[error("failed to load whisper model {path}: {source}")]
+        ModelLoad { path: String, source: WhisperError },
+        #[error("whisper failed: {0}")]
+        Whisper(#[from] WhisperError),
+    }
+
+    /// A piece of recognised text with its position in the audio.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Segment {
+        /// Start of the segment in seconds
+        pub start: f64,
+        /// End of the segment in seconds
+        pub end: f64,
+        pub text: String,
+        /// Who says it, numbered from 1, when the speakers were told apart
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub speaker: Option<u32>,
+    }
+
+    /// Full recognition result: the segments in order and the detected language.
+    #[derive(Debug, Clone, Default)]
+    pub struct Transcript {
+        pub segments: Vec<Segment>,
+        /// Language code as reported by whisper, e.g. "en"
+        pub language: Option<String>,
+    }
+
+    /// How whisper should treat the audio, beyond the model itself.
+    #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+    pub struct RecognitionOptions {
+        /// Translate the speech to English instead of transcribing it as spoken.
+        pub translate: bool,
+        /// Names and jargon the speech is likely to contain. Whisper gets them
+        /// as its initial prompt and near misses are corrected to them.
+        pub vocabulary: Vec<String>,
+    }
+
+    impl RecognitionOptions {
+        /// Stable text form of the options, used to key cached transcripts.
+        pub fn cache_key(&self) -> String {
+            let mut key = format!("translate={}", u8::from(self.translate));
+            if !self.vocabulary.is_empty() {
+                key.push_str(&format!(";vocabulary={}", self.vocabulary.join("|")));
+            }
+            key
+        }
+    }
+
+    impl Transcript {
+        /// Text of all segments joined with spaces.
+        pub fn text(&self) -> String {
+            let parts: Vec<&str> = self.segments.iter().map(|s| s.text.trim()).collect();
+            parts.join(" ").trim().to_string()
+        }
+
+        /// Whether there's no text at all, e.g. when every segment was dropped
+        /// as made up.
+        pub fn is_empty(&self) -> bool {
+            self.text().is_empty()
+        }
+
+        /// Whether the segments were attributed to speakers.
+        pub fn has_speakers(&self) -> bool {
+            self.segments.iter().any(|segment| segment.speaker.is_some())
+        }
+
+        /// Moves all segments by `seconds`, e.g. when only a part of the audio
+        /// starting at that offset was recognised.
+        pub fn shift(&mut self, seconds: f64) {
+            for segment in &mut self.segments {
+                segment.start += seconds;
+                segment.end += seconds;
+            }
+        }
+    }
 
     pub trait SpeechToText {
         /// Recognize the audio and return the text.
         ///
         /// # Arguments
         /// * `audio` - The audio data as a vector of f32 samples. Note, these are not
-        ///    the bytes of the audio file, but the actual samples.
-        fn recognize(&self, audio: &Vec<f32>) -> String;
+        ///   the bytes of the audio file, but the actual samples.
+        fn recognize(&self, audio: &[f32]) -> Result<String, RecognitionError>;
+
+        /// The audio `recognize` expects. Input has to be converted to this spec
+        /// before it is passed in.
+        fn input_spec(&self) -> TargetSpec;
     }
 
 
     pub struct WhisperSTT {
         model_path: String,
+        model_name: String,
+        n_threads: c_int,
+        filter: Option<HallucinationFilter>,
     }
     impl SpeechToText for WhisperSTT {
-        fn recognize(&self, audio: &Vec<f32>) -> String {
-            self.wav_to_text(audio).unwrap_or_else(|e| format!("Error: {}", e))
+        fn recognize(&self, audio: &[f32]) -> Result<String, RecognitionError> {
+            self.wav_to_text(audio)
+        }
+
+        fn input_spec(&self) -> TargetSpec {
+            WHISPER_INPUT
         }
     }
 
     impl WhisperSTT {
-        pub fn new(ggml_path: Option<&str>) -> Result<Self, Box<dyn Error>> {
-            let model_path = match ggml_path {
-                Some(path) => path.to_owned(),
-                None => env::var("GGML").expect("GGML env var not set"),
-            };
+        pub fn new(config: &WhisperConfig) -> Result<Self, RecognitionError> {
+            Self::with_model(&config.model_path, config.threads)
+        }
+
+        /// Uses the model at `path` instead of the configured one.
+        pub fn with_model(path: &Path, threads: u16) -> Result<Self, RecognitionError> {
+            let model_path = path
+                .to_str()
+                .ok_or_else(|| RecognitionError::InvalidModelPath(path.to_owned()))?
+                .to_owned();
+            let model_name = path
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .unwrap_or(&model_path)
+                .to_owned();
+
+            Ok(WhisperSTT {
+                model_path,
+                model_name,
+                n_threads: threads as c_int,
+                filter: None,
+            })
+        }
 
-            Ok(WhisperSTT { model_path })
+        /// Cleans up the segments of every recognition with `filter`.
+        pub fn with_filter(mut self, filter: HallucinationFilter) -> Self {
+            self.filter = Some(filter);
+            self
         }
 
-        pub fn wav_to_text(&self, wav_data: &Vec<f32>) -> Result<String, Box<dyn std::error::Error>> {
-            let whisper_threads = env::var("WHISPER_THREADS").unwrap_or_else(|_| "4".to_string());
-            let n_threads: c_int = whisper_threads.parse()?;
+        /// Short model name (the model file name without extension), e.g. `ggml-base.en`.
+        pub fn model_name(&self) -> &str {
+            &self.model_name
+        }
+
+        pub fn threads(&self) -> u16 {
+            self.n_threads as u16
+        }
+
+        pub fn wav_to_text(&self, wav_data: &[f32]) -> Result<String, RecognitionError> {
+            Ok(self.transcribe(wav_data)?.text())
+        }
+
+        /// Recognize the samples and return the segments with their timestamps.
+        pub fn transcribe(&self, wav_data: &[f32]) -> Result<Transcript, RecognitionError> {
+            self.transcribe_with(wav_data, RecognitionOptions::default())
+        }
+
+        pub fn transcribe_with(
+            &self,
+            wav_data: &[f32],
+            options: RecognitionOptions,
+        ) -> Result<Transcript, RecognitionError> {
+            let ctx = self.load()?;
+            let prompt = prompt_tokens(&ctx, &options)?;
+            let mut state = ctx.create_state()?;
+            let params = self.params(&options, self.n_threads, &prompt);
+            Ok(self.collapse_loops(run(&mut state, params, wav_data, &options, self.filter.as_ref())?))
+        }
+
+        /// `transcript` with phrases repeated in a loop kept only once, if there
+        /// is a filter. Done on whole transcripts only, as a loop can run from
+        /// one part of a recording into the next.
+        pub fn collapse_loops(&self, mut transcript: Transcript) -> Transcript {
+            if let Some(filter) = &self.filter {
+                transcript.segments = filter.collapse(transcript.segments);
+            }
+            transcript
+        }
 
-            // Create a temporary file to store the .wav data
-            let f32_wav_data = wav_data.to_owned();
+        /// Recognises each of `parts` on its own, up to `parallel` of them at a
+        /// time. The model is loaded once and every worker runs its own whisper
+        /// state on it, with the configured threads split between the workers.
+        ///
+        /// The transcripts are returned in the order of `parts`, with timestamps
+        /// relative to the start of each part. Loops are left for
+        /// `collapse_loops` once the parts are put together.
+        pub fn transcribe_parts(
+            &self,
+            parts: &[&[f32]],
+            options: RecognitionOptions,
+            parallel: usize,
+        ) -> Result<Vec<Transcript>, RecognitionError> {
+            let workers = parallel.clamp(1, parts.len().max(1));
+            let threads = (self.n_threads / workers as c_int).max(1);
+            let ctx = self.load()?;
+            let prompt = prompt_tokens(&ctx, &options)?;
+            let next = AtomicUsize::new(0);
 
-            let ctx = WhisperContext::new(&self.model_path)?;
+            let results: Vec<Result<Vec<(usize, Transcript)>, RecognitionError>> = std::thread::scope(|scope| {
+                let workers: Vec<_> = (0..workers)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut state = ctx.create_state()?;
+                            let mut done = Vec::new();
+                            loop {
+                                let i = next.fetch_add(1, Ordering::SeqCst);
+                                let Some(part) = parts.get(i) else {
+                                    break;
+                                };
+                                let params = self.params(&options, threads, &prompt);
+                                done.push((i, run(&mut state, params, part, &options, self.filter.as_ref())?));
+                            }
+                            Ok(done)
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                    .collect()
+            });
 
-             // Set up the parameters
+            let mut transcripts: Vec<(usize, Transcript)> = Vec::with_capacity(parts.len());
+            for result in results {
+                transcripts.extend(result?);
+            }
+            transcripts.sort_by_key(|(i, _)| *i);
+            Ok(transcripts.into_iter().map(|(_, transcript)| transcript).collect())
+        }
+
+        fn load(&self) -> Result<WhisperContext, RecognitionError> {
+            WhisperContext::new(&self.model_path).map_err(|source| RecognitionError::ModelLoad {
+                path: self.model_path.clone(),
+                source,
+            })
+        }
+
+        fn params<'b>(
+            &self,
+            options: &RecognitionOptions,
+            threads: c_int,
+            prompt: &'b [c_int],
+        ) -> FullParams<'static, 'b> {
             let mut params = FullParams::new(whisper_rs::SamplingStrategy::Greedy { best_of: 1 });
             params.set_print_special(false);
             params.set_print_progress(false);
             params.set_print_realtime(false);
             params.set_print_timestamps(false);
-            params.set_n_threads(n_threads);
-
-            // Run the model
-            let mut state = ctx.create_state()?;
-            state.full(params, &f32_wav_data)?;
-
-            // Extract the text
-            let num_segments = state.full_n_segments()?;
-            let mut text = String::new();
-            for i in 0..num_segments {
-                text.push_str(&state.full_get_segment_text(i)?);
-                text.push(' ');
+            params.set_n_threads(threads);
+            if options.translate {
+                // The source language has to be detected for translation to make sense
+                params.set_language(Some("auto"));
+                params.set_translate(true);
+            }
+            if !prompt.is_empty() {
+                params.set_tokens(prompt);
             }
+            params
+        }
+    }
 
-            Ok(text.trim().to_string())
+    /// Tokens of the initial prompt made of the vocabulary of `options`.
+    fn prompt_tokens(ctx: &WhisperContext, options: &RecognitionOptions) -> Result<Vec<c_int>, RecognitionError> {
+        match prompt(&options.vocabulary) {
+            // No text has more tokens than bytes; whisper keeps as many of the
+            // last ones as fit into its context
+            Some(prompt) => Ok(ctx.tokenize(&prompt, prompt.len() + 1)?),
+            None => Ok(Vec::new()),
         }
     }
+
+    /// Runs the model over `samples` and collects the segments, cleaned up with
+    /// `filter` if there is one and corrected to the vocabulary of `options`.
+    fn run(
+        state: &mut WhisperState,
+        params: FullParams,
+        samples: &[f32],
+        options: &RecognitionOptions,
+        filter: Option<&HallucinationFilter>,
+    ) -> Result<Transcript, RecognitionError> {
+        state.full(params, samples)?;
+
+        // Extract the segments, whisper timestamps are in centiseconds
+        let num_segments = state.full_n_segments()?;
+        let mut segments = Vec::with_capacity(num_segments as usize);
+        for i in 0..num_segments {
+            segments.push(Segment {
+                start: state.full_get_segment_t0(i)? as f64 / 100.0,
+                end: state.full_get_segment_t1(i)? as f64 / 100.0,
+                text: state.full_get_segment_text(i)?,
+                speaker: None,
+            });
+        }
+        if let Some(filter) = filter {
+            segments = filter.drop_made_up(segments, samples, WHISPER_SAMPLE_RATE);
+        }
+        correct_segments(&mut segments, &options.vocabulary);
+
+        let language = state
+            .full_lang_id_from_state()
+            .ok()
+            .and_then(whisper_rs::get_lang_str)
+            .map(|lang| lang.to_owned());
+
+        Ok(Transcript { segments, language })
+    }
 }
\ No newline at end of file
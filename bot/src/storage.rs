@@ -0,0 +1,394 @@
+pub mod storage {
+    use crate::speech_to_text::speech_to_text::{Segment, Transcript};
+    use rusqlite::{params, Connection, OptionalExtension, Row};
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    /// Markers put around matched words in search snippets. They are control
+    /// characters so that callers can escape the snippet and then replace them
+    /// with whatever highlighting their output format uses.
+    pub const MATCH_START: char = '\u{2}';
+    pub const MATCH_END: char = '\u{3}';
+
+    /// A transcript to be stored.
+    pub struct NewTranscript<'a> {
+        pub chat_id: i64,
+        /// Public username of the chat, used to build links to the original message
+        pub chat_username: Option<&'a str>,
+        pub sender_id: Option<u64>,
+        pub sender_name: Option<&'a str>,
+        pub message_id: i32,
+        /// Unix timestamp of the original message
+        pub created_at: i64,
+        pub duration: f64,
+        pub language: Option<&'a str>,
+        pub model: &'a str,
+        pub text: &'a str,
+        pub segments: Option<&'a [Segment]>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct StoredTranscript {
+        pub id: i64,
+        pub chat_id: i64,
+        pub chat_username: Option<String>,
+        pub sender_id: Option<u64>,
+        pub sender_name: Option<String>,
+        pub message_id: i32,
+        pub created_at: i64,
+        pub duration: f64,
+        pub language: Option<String>,
+        pub model: String,
+        pub text: String,
+        /// Segment timestamps, if they were stored with the transcript
+        pub segments: Option<Vec<Segment>>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct SearchHit {
+        pub transcript: StoredTranscript,
+        /// Fragment of the text around the match, with matches wrapped in
+        /// `MATCH_START` and `MATCH_END`.
+        pub snippet: String,
+    }
+
+    /// Identifies a cached recognition result: the same file recognised with the
+    /// same model and options.
+    #[derive(Debug, Clone, Copy)]
+    pub struct CacheKey<'a> {
+        /// Telegram's `file_unique_id`, which stays the same when a file is forwarded
+        pub file_unique_id: &'a str,
+        pub model: &'a str,
+        /// `RecognitionOptions::cache_key` of the options used
+        pub options: &'a str,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct CachedTranscript {
+        /// Duration of the decoded audio in seconds
+        pub duration: f64,
+        pub transcript: Transcript,
+    }
+
+    /// SQLite-backed archive of transcripts with a full-text index.
+    pub struct TranscriptStore {
+        conn: Mutex<Connection>,
+    }
+
+    const COLUMNS: &str = "t.id, t.chat_id, t.chat_username, t.sender_id, t.sender_name, t.message_id, \
+        t.created_at, t.duration, t.language, t.model, t.text, t.segments";
+
+    impl TranscriptStore {
+        pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+            Self::init(Connection::open(path)?)
+        }
+
+        pub fn open_in_memory() -> rusqlite::Result<Self> {
+            Self::init(Connection::open_in_memory()?)
+        }
+
+        fn init(conn: Connection) -> rusqlite::Result<Self> {
+            // The FTS table mirrors `text` of `transcripts` and is kept in sync by triggers
+            conn.execute_batch(
+                "
+                PRAGMA journal_mode = WAL;
+
+                CREATE TABLE IF NOT EXISTS transcripts (
+                    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+                    chat_id       INTEGER NOT NULL,
+                    chat_username TEXT,
+                    sender_id     INTEGER,
+                    sender_name   TEXT,
+                    message_id    INTEGER NOT NULL,
+                    created_at    INTEGER NOT NULL,
+                    duration      REAL NOT NULL,
+                    language      TEXT,
+                    model         TEXT NOT NULL,
+                    text          TEXT NOT NULL,
+                    segments      TEXT
+                );
+                CREATE INDEX IF NOT EXISTS transcripts_chat_created
+                    ON transcripts (chat_id, created_at);
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS transcripts_fts USING fts5(
+                    text, content = 'transcripts', content_rowid = 'id'
+                );
+                CREATE TRIGGER IF NOT EXISTS transcripts_ai AFTER INSERT ON transcripts BEGIN
+                    INSERT INTO transcripts_fts (rowid, text) VALUES (new.id, new.text);
+                END;
+                CREATE TRIGGER IF NOT EXISTS transcripts_ad AFTER DELETE ON transcripts BEGIN
+                    INSERT INTO transcripts_fts (transcripts_fts, rowid, text)
+                        VALUES ('delete', old.id, old.text);
+                END;
+
+                CREATE TABLE IF NOT EXISTS transcript_cache (
+                    file_unique_id TEXT NOT NULL,
+                    model          TEXT NOT NULL,
+                    options        TEXT NOT NULL,
+                    duration       REAL NOT NULL,
+                    language       TEXT,
+                    segments       TEXT NOT NULL,
+                    created_at     INTEGER NOT NULL,
+                    PRIMARY KEY (file_unique_id, model, options)
+                );
+
+                CREATE TABLE IF NOT EXISTS chat_settings (
+                    chat_id INTEGER NOT NULL,
+                    key     TEXT NOT NULL,
+                    value   TEXT NOT NULL,
+                    PRIMARY KEY (chat_id, key)
+                );
+                ",
+            )?;
+
+            Ok(TranscriptStore {
+                conn: Mutex::new(conn),
+            })
+        }
+
+        pub fn insert(&self, transcript: &NewTranscript) -> rusqlite::Result<i64> {
+            let segments = transcript
+                .segments
+                .map(serde_json::to_string)
+                .transpose()
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "INSERT INTO transcripts (chat_id, chat_username, sender_id, sender_name, message_id,
+                     created_at, duration, language, model, text, segments)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    transcript.chat_id,
+                    transcript.chat_username,
+                    transcript.sender_id,
+                    transcript.sender_name,
+                    transcript.message_id,
+                    transcript.created_at,
+                    transcript.duration,
+                    transcript.language,
+                    transcript.model,
+                    transcript.text,
+                    segments,
+                ],
+            )?;
+
+            Ok(conn.last_insert_rowid())
+        }
+
+        pub fn get(&self, id: i64) -> rusqlite::Result<Option<StoredTranscript>> {
+            let conn = self.conn.lock().unwrap();
+            conn.query_row(
+                &format!("SELECT {} FROM transcripts t WHERE t.id = ?1", COLUMNS),
+                params![id],
+                row_to_transcript,
+            )
+            .optional()
+        }
+
+        pub fn count(&self, chat_id: i64) -> rusqlite::Result<u64> {
+            let conn = self.conn.lock().unwrap();
+            conn.query_row(
+                "SELECT COUNT(*) FROM transcripts WHERE chat_id = ?1",
+                params![chat_id],
+                |row| row.get(0),
+            )
+        }
+
+        /// The latest transcript of a message, if it was stored.
+        pub fn by_message(&self, chat_id: i64, message_id: i32) -> rusqlite::Result<Option<StoredTranscript>> {
+            let conn = self.conn.lock().unwrap();
+            conn.query_row(
+                &format!(
+                    "SELECT {} FROM transcripts t WHERE t.chat_id = ?1 AND t.message_id = ?2
+                     ORDER BY t.id DESC LIMIT 1",
+                    COLUMNS
+                ),
+                params![chat_id, message_id],
+                row_to_transcript,
+            )
+            .optional()
+        }
+
+        /// Most recent transcripts of a chat, newest first.
+        pub fn recent(&self, chat_id: i64, limit: u32, offset: u32) -> rusqlite::Result<Vec<StoredTranscript>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM transcripts t WHERE t.chat_id = ?1
+                 ORDER BY t.created_at DESC, t.id DESC LIMIT ?2 OFFSET ?3",
+                COLUMNS
+            ))?;
+            let rows = stmt.query_map(params![chat_id, limit, offset], row_to_transcript)?;
+            rows.collect()
+        }
+
+        /// Transcripts of a chat created within `[from, to]` (Unix timestamps), oldest first.
+        pub fn in_range(&self, chat_id: i64, from: i64, to: i64) -> rusqlite::Result<Vec<StoredTranscript>> {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM transcripts t
+                 WHERE t.chat_id = ?1 AND t.created_at BETWEEN ?2 AND ?3
+                 ORDER BY t.created_at, t.id",
+                COLUMNS
+            ))?;
+            let rows = stmt.query_map(params![chat_id, from, to], row_to_transcript)?;
+            rows.collect()
+        }
+
+        /// Full-text search over the transcripts of a chat, best matches first.
+        ///
+        /// Every word of `query` must be present; words are matched as plain
+        /// terms, so FTS query syntax typed by users has no special meaning.
+        pub fn search(&self, chat_id: i64, query: &str, limit: u32) -> rusqlite::Result<Vec<SearchHit>> {
+            let fts_query = to_fts_query(query);
+            if fts_query.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {}, snippet(transcripts_fts, 0, ?3, ?4, '…', 16)
+                 FROM transcripts_fts JOIN transcripts t ON t.id = transcripts_fts.rowid
+                 WHERE transcripts_fts MATCH ?1 AND t.chat_id = ?2
+                 ORDER BY rank LIMIT ?5",
+                COLUMNS
+            ))?;
+            let rows = stmt.query_map(
+                params![
+                    fts_query,
+                    chat_id,
+                    MATCH_START.to_string(),
+                    MATCH_END.to_string(),
+                    limit
+                ],
+                |row| {
+                    Ok(SearchHit {
+                        transcript: row_to_transcript(row)?,
+                        snippet: row.get(12)?,
+                    })
+                },
+            )?;
+            rows.collect()
+        }
+
+        /// A previous recognition result for the same file, model and options.
+        pub fn cached_transcript(&self, key: &CacheKey) -> rusqlite::Result<Option<CachedTranscript>> {
+            let conn = self.conn.lock().unwrap();
+            let row = conn
+                .query_row(
+                    "SELECT duration, language, segments FROM transcript_cache
+                     WHERE file_unique_id = ?1 AND model = ?2 AND options = ?3",
+                    params![key.file_unique_id, key.model, key.options],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?)),
+                )
+                .optional()?;
+
+            row.map(|(duration, language, segments)| {
+                let segments = serde_json::from_str(&segments).map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))
+                })?;
+                Ok(CachedTranscript {
+                    duration,
+                    transcript: Transcript { segments, language },
+                })
+            })
+            .transpose()
+        }
+
+        /// Remembers a recognition result, replacing an older one for the same key.
+        pub fn cache_transcript(
+            &self,
+            key: &CacheKey,
+            duration: f64,
+            transcript: &Transcript,
+            created_at: i64,
+        ) -> rusqlite::Result<()> {
+            let segments = serde_json::to_string(&transcript.segments)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "INSERT OR REPLACE INTO transcript_cache
+                     (file_unique_id, model, options, duration, language, segments, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    key.file_unique_id,
+                    key.model,
+                    key.options,
+                    duration,
+                    transcript.language,
+                    segments,
+                    created_at
+                ],
+            )?;
+            Ok(())
+        }
+
+        /// Empties the transcript cache and returns the number of removed entries.
+        /// The transcript history of chats is not affected.
+        pub fn purge_cache(&self) -> rusqlite::Result<usize> {
+            let conn = self.conn.lock().unwrap();
+            conn.execute("DELETE FROM transcript_cache", [])
+        }
+
+        /// A per-chat setting, `None` if the chat never changed it.
+        pub fn chat_setting(&self, chat_id: i64, key: &str) -> rusqlite::Result<Option<String>> {
+            let conn = self.conn.lock().unwrap();
+            conn.query_row(
+                "SELECT value FROM chat_settings WHERE chat_id = ?1 AND key = ?2",
+                params![chat_id, key],
+                |row| row.get(0),
+            )
+            .optional()
+        }
+
+        /// Stores a per-chat setting, or resets it to the default when `value` is `None`.
+        pub fn set_chat_setting(&self, chat_id: i64, key: &str, value: Option<&str>) -> rusqlite::Result<()> {
+            let conn = self.conn.lock().unwrap();
+            match value {
+                Some(value) => conn.execute(
+                    "INSERT INTO chat_settings (chat_id, key, value) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (chat_id, key) DO UPDATE SET value = excluded.value",
+                    params![chat_id, key, value],
+                )?,
+                None => conn.execute(
+                    "DELETE FROM chat_settings WHERE chat_id = ?1 AND key = ?2",
+                    params![chat_id, key],
+                )?,
+            };
+            Ok(())
+        }
+    }
+
+    /// Turns user input into an FTS5 query where every word is a quoted term.
+    fn to_fts_query(query: &str) -> String {
+        query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn row_to_transcript(row: &Row) -> rusqlite::Result<StoredTranscript> {
+        let segments: Option<String> = row.get(11)?;
+        let segments = segments
+            .map(|json| serde_json::from_str(&json))
+            .transpose()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(11, rusqlite::types::Type::Text, Box::new(e)))?;
+
+        Ok(StoredTranscript {
+            id: row.get(0)?,
+            chat_id: row.get(1)?,
+            chat_username: row.get(2)?,
+            sender_id: row.get(3)?,
+            sender_name: row.get(4)?,
+            message_id: row.get(5)?,
+            created_at: row.get(6)?,
+            duration: row.get(7)?,
+            language: row.get(8)?,
+            model: row.get(9)?,
+            text: row.get(10)?,
+            segments,
+        })
+    }
+}
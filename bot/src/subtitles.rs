@@ -0,0 +1,125 @@
+pub mod subtitles {
+    use crate::config::config::{ParagraphConfig, ParagraphMode};
+    use crate::speech_to_text::speech_to_text::Segment;
+
+    /// Characters that end a sentence, where overlong paragraphs are split.
+    const SENTENCE_ENDS: &[char] = &['.', '!', '?', '…'];
+
+    /// Formats segments as SubRip (SRT) subtitles.
+    pub fn to_srt(segments: &[Segment]) -> String {
+        let mut srt = String::new();
+        for (i, segment) in segments.iter().enumerate() {
+            srt.push_str(&format!(
+                "{}\n{} --> {}\n{}\n\n",
+                i + 1,
+                srt_timestamp(segment.start),
+                srt_timestamp(segment.end),
+                segment.text.trim()
+            ));
+        }
+        srt
+    }
+
+    /// One line per segment, prefixed with its start time: `[mm:ss] text`.
+    pub fn to_timestamped_text(segments: &[Segment]) -> String {
+        segments
+            .iter()
+            .map(|segment| format!("[{}] {}", short_timestamp(segment.start), segment.text.trim()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Segments with `label(speaker)` in front of the text of those whose
+    /// speaker is known, e.g. "Speaker 2: text", for formats that have no
+    /// place of their own for speakers.
+    pub fn with_speaker_labels(segments: &[Segment], label: impl Fn(u32) -> String) -> Vec<Segment> {
+        segments
+            .iter()
+            .map(|segment| match segment.speaker {
+                Some(speaker) => Segment { text: format!("{}: {}", label(speaker), segment.text.trim()), ..segment.clone() },
+                None => segment.clone(),
+            })
+            .collect()
+    }
+
+    /// The text of the segments in paragraphs separated by blank lines. A new
+    /// paragraph starts with every change of speaker and, unless the mode is
+    /// off, after long pauses and once a paragraph has grown too long. The
+    /// first paragraph of each speaker's turn starts with `label(speaker)`.
+    pub fn to_paragraphs(segments: &[Segment], layout: &ParagraphConfig, label: impl Fn(u32) -> String) -> String {
+        struct Paragraph<'a> {
+            start: f64,
+            speaker: Option<u32>,
+            labelled: bool,
+            texts: Vec<&'a str>,
+        }
+
+        let mut paragraphs: Vec<Paragraph> = Vec::new();
+        let mut previous: Option<&Segment> = None;
+        for segment in segments {
+            let text = segment.text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let new_turn = previous.is_none_or(|previous| previous.speaker != segment.speaker);
+            let new_paragraph = match (paragraphs.last(), previous) {
+                (Some(paragraph), Some(previous)) if layout.mode != ParagraphMode::Off => {
+                    let pause = segment.start - previous.end >= layout.pause_secs;
+                    let too_long = segment.start - paragraph.start >= layout.max_paragraph_secs
+                        && previous.text.trim_end().ends_with(SENTENCE_ENDS);
+                    pause || too_long
+                }
+                _ => false,
+            };
+
+            match paragraphs.last_mut() {
+                Some(paragraph) if !new_turn && !new_paragraph => paragraph.texts.push(text),
+                _ => paragraphs.push(Paragraph {
+                    start: segment.start,
+                    speaker: segment.speaker,
+                    labelled: new_turn,
+                    texts: vec![text],
+                }),
+            }
+            previous = Some(segment);
+        }
+
+        paragraphs
+            .into_iter()
+            .map(|paragraph| {
+                let mut text = String::new();
+                if layout.mode == ParagraphMode::Timestamps {
+                    text.push_str(&format!("[{}] ", short_timestamp(paragraph.start)));
+                }
+                if let Some(speaker) = paragraph.speaker.filter(|_| paragraph.labelled) {
+                    text.push_str(&format!("{}: ", label(speaker)));
+                }
+                text.push_str(&paragraph.texts.join(" "));
+                text
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// `mm:ss`, or `h:mm:ss` from an hour on.
+    pub fn short_timestamp(seconds: f64) -> String {
+        let seconds = seconds.max(0.0) as u64;
+        if seconds >= 3600 {
+            format!("{}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+        } else {
+            format!("{:02}:{:02}", seconds / 60, seconds % 60)
+        }
+    }
+
+    /// `hh:mm:ss,mmm` as used by SRT.
+    pub fn srt_timestamp(seconds: f64) -> String {
+        let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+        format!(
+            "{:02}:{:02}:{:02},{:03}",
+            millis / 3_600_000,
+            (millis / 60_000) % 60,
+            (millis / 1000) % 60,
+            millis % 1000
+        )
+    }
+}
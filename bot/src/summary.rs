@@ -0,0 +1,77 @@
+pub mod summary {
+    use std::collections::HashMap;
+
+    /// Words this short are mostly articles, prepositions and the like, which
+    /// say nothing about what a sentence is about.
+    const MIN_WORD_CHARS: usize = 4;
+
+    /// Extractive summary: the `max_sentences` sentences whose words occur most
+    /// often in the whole text, in their original order.
+    ///
+    /// This needs no model and works for any language that separates words with
+    /// spaces, at the cost of only ever repeating what was said.
+    pub fn summarize(text: &str, max_sentences: usize) -> String {
+        let sentences = split_sentences(text);
+        if sentences.len() <= max_sentences {
+            return sentences.join(" ");
+        }
+
+        let mut frequencies: HashMap<String, usize> = HashMap::new();
+        for sentence in &sentences {
+            for word in words(sentence) {
+                *frequencies.entry(word).or_default() += 1;
+            }
+        }
+
+        let mut scored: Vec<(usize, f64)> = sentences
+            .iter()
+            .enumerate()
+            .map(|(i, sentence)| {
+                let words = words(sentence);
+                let total: usize = words.iter().map(|word| frequencies[word]).sum();
+                // Normalised so that long sentences don't win just by being long
+                let score = total as f64 / (words.len() as f64).sqrt().max(1.0);
+                (i, score)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut chosen: Vec<usize> = scored.iter().take(max_sentences).map(|(i, _)| *i).collect();
+        chosen.sort_unstable();
+        chosen.iter().map(|&i| sentences[i]).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Splits text after `.`, `!`, `?` and `…` followed by whitespace.
+    pub fn split_sentences(text: &str) -> Vec<&str> {
+        let mut sentences = Vec::new();
+        let mut start = 0;
+        let mut chars = text.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            let at_boundary = matches!(c, '.' | '!' | '?' | '…')
+                && !matches!(chars.peek(), Some((_, next)) if !next.is_whitespace());
+            if at_boundary {
+                let end = i + c.len_utf8();
+                let sentence = text[start..end].trim();
+                if !sentence.is_empty() {
+                    sentences.push(sentence);
+                }
+                start = end;
+            }
+        }
+
+        let rest = text[start..].trim();
+        if !rest.is_empty() {
+            sentences.push(rest);
+        }
+        sentences
+    }
+
+    fn words(sentence: &str) -> Vec<String> {
+        sentence
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| word.chars().count() >= MIN_WORD_CHARS)
+            .map(|word| word.to_lowercase())
+            .collect()
+    }
+}
@@ -0,0 +1,131 @@
+pub mod symphonia_converter {
+    use bytes::Bytes;
+    use std::io::{Cursor, ErrorKind};
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+    use symphonia::core::errors::Error as SymphoniaError;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+    use crate::audio_conversion::audio_conversion::{
+        remix, resample, AudioConverter, AudioData, ConversionError, InputFormat, TargetSpec,
+    };
+    use crate::wav::wav::write_wav;
+
+    /// Decodes MP3, FLAC, AAC in MP4 or ADTS, Vorbis and WAV in Rust, so that
+    /// common audio files don't need ffmpeg. The audio is resampled to the
+    /// target rate with rubato.
+    pub struct SymphoniaConverter;
+
+    impl AudioConverter for SymphoniaConverter {
+        fn convert_audio_to_wav(&self, input_data: &[u8], target: &TargetSpec) -> Result<Vec<u8>, ConversionError> {
+            Ok(write_wav(&self.decode_audio(input_data, target)?.samples, target))
+        }
+
+        fn name(&self) -> &'static str {
+            "symphonia"
+        }
+
+        fn supports(&self, format: InputFormat) -> bool {
+            matches!(
+                format,
+                InputFormat::Wav
+                    | InputFormat::OggVorbis
+                    | InputFormat::Ogg
+                    | InputFormat::Mp3
+                    | InputFormat::Flac
+                    | InputFormat::Mp4
+                    | InputFormat::Aac
+            )
+        }
+
+        fn decode_audio(&self, input_data: &[u8], target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            // Symphonia reads from a source it owns
+            self.decode_bytes(Bytes::copy_from_slice(input_data), target)
+        }
+
+        fn decode_bytes(&self, input_data: Bytes, target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            let container = InputFormat::from_magic(&input_data).container().map(str::to_string);
+            let source = MediaSourceStream::new(Box::new(Cursor::new(input_data)), Default::default());
+            // Gapless playback drops the encoder delay and padding, as ffmpeg does
+            let options = FormatOptions { enable_gapless: true, ..Default::default() };
+            let probed = symphonia::default::get_probe()
+                .format(&Hint::new(), source, &options, &MetadataOptions::default())
+                .map_err(failed)?;
+            let mut reader = probed.format;
+
+            // The first audio track; video tracks have no audio codec
+            let track = reader
+                .tracks()
+                .iter()
+                .find(|track| track.codec_params.codec != CODEC_TYPE_NULL && track.codec_params.sample_rate.is_some())
+                .ok_or_else(|| ConversionError::UnsupportedFormat("no audio track".to_string()))?;
+            let track_id = track.id;
+            let codec = symphonia::default::get_codecs()
+                .get_codec(track.codec_params.codec)
+                .map(|codec| codec.short_name.to_string());
+            let mut decoder = symphonia::default::get_codecs()
+                .make(&track.codec_params, &DecoderOptions::default())
+                .map_err(failed)?;
+
+            let mut samples = Vec::new();
+            let mut sample_rate = 0;
+            let mut channels = 0;
+            loop {
+                let packet = match reader.next_packet() {
+                    Ok(packet) => packet,
+                    // The end of the file, or of a truncated one
+                    Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(failed(e)),
+                };
+                if packet.track_id() != track_id {
+                    continue;
+                }
+                let decoded = match decoder.decode(&packet) {
+                    Ok(decoded) => decoded,
+                    // A damaged frame is skipped, like ffmpeg does
+                    Err(SymphoniaError::DecodeError(e)) => {
+                        log::debug!("Skipping a damaged packet: {}", e);
+                        continue;
+                    }
+                    Err(e) => return Err(failed(e)),
+                };
+
+                let spec = *decoded.spec();
+                if channels == 0 {
+                    sample_rate = spec.rate;
+                    channels = spec.channels.count() as u16;
+                } else if spec.rate != sample_rate || spec.channels.count() as u16 != channels {
+                    return Err(ConversionError::UnsupportedFormat("format changes within the stream".to_string()));
+                }
+                let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                buffer.copy_interleaved_ref(decoded);
+                samples.extend_from_slice(buffer.samples());
+                // Whatever follows is too long to transcribe anyway
+                if samples.len() > target.max_samples(sample_rate, channels) {
+                    samples.truncate(target.max_samples(sample_rate, channels));
+                    break;
+                }
+            }
+            if samples.is_empty() {
+                return Err(ConversionError::Decode { format: "audio", reason: "no audio decoded".to_string() });
+            }
+
+            let samples = remix(&samples, channels, target.channels).ok_or_else(|| {
+                ConversionError::UnsupportedFormat(format!("{} channels to {}", channels, target.channels))
+            })?;
+            let samples = resample(&samples, target.channels, sample_rate, target.sample_rate)?;
+            Ok(AudioData::new(samples, target.sample_rate, target.channels).with_source(container, codec))
+        }
+    }
+
+    /// The input is in memory, so reading only fails where the data ends
+    /// early; that is reported as bad data rather than as an I/O error.
+    fn failed(e: SymphoniaError) -> ConversionError {
+        match e {
+            SymphoniaError::Unsupported(what) => ConversionError::UnsupportedFormat(what.to_string()),
+            e => ConversionError::Decode { format: "audio", reason: e.to_string() },
+        }
+    }
+}
@@ -0,0 +1,78 @@
+pub mod text_split {
+    /// Splits `text` into parts of at most `max_units` UTF-16 code units each, the
+    /// unit Telegram uses for its message length limit.
+    ///
+    /// Parts end at a line break or sentence end where one falls into the second
+    /// half of the allowed length, otherwise at the last space. Only a single word
+    /// longer than `max_units` is cut, and then at a character boundary.
+    pub fn split_message(text: &str, max_units: usize) -> Vec<String> {
+        assert!(max_units > 0, "max_units must be positive");
+
+        let mut parts = Vec::new();
+        let mut rest = text.trim();
+
+        while utf16_len(rest) > max_units {
+            let limit = prefix_end(rest, max_units);
+            let cut = sentence_boundary(rest, limit, max_units / 2)
+                .or_else(|| word_boundary(rest, limit))
+                .unwrap_or(limit);
+
+            parts.push(rest[..cut].trim_end().to_string());
+            rest = rest[cut..].trim_start();
+        }
+
+        if !rest.is_empty() {
+            parts.push(rest.to_string());
+        }
+        parts
+    }
+
+    /// Length of `text` in UTF-16 code units.
+    pub fn utf16_len(text: &str) -> usize {
+        text.chars().map(char::len_utf16).sum()
+    }
+
+    /// Byte index where the longest prefix of at most `max_units` ends. Always
+    /// includes the first character, so that splitting makes progress.
+    fn prefix_end(text: &str, max_units: usize) -> usize {
+        let mut units = 0;
+        for (i, c) in text.char_indices() {
+            units += c.len_utf16();
+            if units > max_units {
+                return if i == 0 { c.len_utf8() } else { i };
+            }
+        }
+        text.len()
+    }
+
+    /// The last end of a line or sentence in `text[..limit]`, if it leaves at
+    /// least `min_units` in the part.
+    fn sentence_boundary(text: &str, limit: usize, min_units: usize) -> Option<usize> {
+        // Scanning backwards, `next` is the character after `c`
+        let mut next: Option<char> = text[limit..].chars().next();
+        let mut boundary = None;
+        for (i, c) in text[..limit].char_indices().rev() {
+            let followed_by_space = !matches!(next, Some(n) if !n.is_whitespace());
+            if c == '\n' || (matches!(c, '.' | '!' | '?' | '…') && followed_by_space) {
+                boundary = Some(i + c.len_utf8());
+                break;
+            }
+            next = Some(c);
+        }
+
+        boundary.filter(|&end| utf16_len(&text[..end]) >= min_units)
+    }
+
+    /// The last space in `text[..limit]`, or `limit` itself if a word ends there.
+    fn word_boundary(text: &str, limit: usize) -> Option<usize> {
+        if text[limit..].starts_with(char::is_whitespace) {
+            return Some(limit);
+        }
+        text[..limit]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, _)| i)
+            .filter(|&i| i > 0)
+    }
+}
@@ -0,0 +1,149 @@
+pub mod vad {
+    use crate::config::config::VadConfig;
+
+    /// A stretch of audio that contains speech, in seconds from the start.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct SpeechRegion {
+        pub start: f64,
+        pub end: f64,
+    }
+
+    impl SpeechRegion {
+        pub fn duration(&self) -> f64 {
+            self.end - self.start
+        }
+    }
+
+    /// Energy and zero-crossing based voice activity detector.
+    ///
+    /// Each frame is compared against a threshold derived from the background
+    /// noise of the recording (a low percentile of the frame energies) and from
+    /// its loudest frame, so that both noisy and clean recordings work without
+    /// tuning. Hangover smoothing then joins speech frames into regions.
+    pub struct Vad {
+        config: VadConfig,
+        sample_rate: u32,
+    }
+
+    /// Percentile of frame energies taken as the background noise level.
+    const NOISE_PERCENTILE: f64 = 0.1;
+
+    impl Vad {
+        pub fn new(config: &VadConfig, sample_rate: u32) -> Self {
+            Vad { config: config.clone(), sample_rate }
+        }
+
+        /// Speech regions of mono `samples`, in order and not overlapping.
+        pub fn detect(&self, samples: &[f32]) -> Vec<SpeechRegion> {
+            let total = samples.len() as f64 / self.sample_rate as f64;
+            if !self.config.enabled {
+                return if samples.is_empty() { Vec::new() } else { vec![SpeechRegion { start: 0.0, end: total }] };
+            }
+
+            let frame_len = self.ms_to_samples(self.config.frame_ms).max(1);
+            let frames: Vec<(f64, f64)> =
+                samples.chunks(frame_len).map(|frame| (energy_db(frame), zero_crossing_rate(frame))).collect();
+            if frames.is_empty() {
+                return Vec::new();
+            }
+
+            let threshold = self.threshold(&frames);
+            let is_speech: Vec<bool> = frames
+                .iter()
+                .map(|&(energy, zcr)| energy >= threshold && zcr <= self.config.max_zero_crossing_rate)
+                .collect();
+
+            let frame_secs = frame_len as f64 / self.sample_rate as f64;
+            let hangover = (self.config.hangover_ms as f64 / 1000.0 / frame_secs).ceil() as usize;
+            let min_speech = self.config.min_speech_ms as f64 / 1000.0;
+
+            let mut regions = Vec::new();
+            let mut current: Option<(usize, usize)> = None; // first and last speech frame
+            for (i, &speech) in is_speech.iter().enumerate() {
+                current = match (current, speech) {
+                    (None, true) => Some((i, i)),
+                    (Some((first, _)), true) => Some((first, i)),
+                    (Some((first, last)), false) if i - last > hangover => {
+                        regions.push((first, last));
+                        None
+                    }
+                    (current, false) => current,
+                };
+            }
+            if let Some(region) = current {
+                regions.push(region);
+            }
+
+            regions
+                .into_iter()
+                // Clicks and other short bursts are not speech
+                .filter(|&(first, last)| (last + 1 - first) as f64 * frame_secs >= min_speech)
+                .map(|(first, last)| SpeechRegion {
+                    start: first as f64 * frame_secs,
+                    // The hangover also covers the quiet tail of the last word
+                    end: (((last + 1 + hangover) as f64) * frame_secs).min(total),
+                })
+                .collect()
+        }
+
+        /// The samples from the start of the first region to the end of the last
+        /// one, with `padding_ms` of context on both sides, and the offset of the
+        /// returned slice in seconds.
+        pub fn trim<'a>(&self, samples: &'a [f32], regions: &[SpeechRegion]) -> (&'a [f32], f64) {
+            let (first, last) = match (regions.first(), regions.last()) {
+                (Some(first), Some(last)) => (first, last),
+                _ => return (&samples[..0], 0.0),
+            };
+
+            let padding = self.config.padding_ms as f64 / 1000.0;
+            let start = self.secs_to_samples(first.start - padding).min(samples.len());
+            let end = self.secs_to_samples(last.end + padding).clamp(start, samples.len());
+            (&samples[start..end], start as f64 / self.sample_rate as f64)
+        }
+
+        /// Energy above which a frame may be speech.
+        fn threshold(&self, frames: &[(f64, f64)]) -> f64 {
+            let mut energies: Vec<f64> = frames.iter().map(|&(energy, _)| energy).collect();
+            energies.sort_by(f64::total_cmp);
+            let noise = energies[((energies.len() - 1) as f64 * NOISE_PERCENTILE) as usize];
+            let peak = energies[energies.len() - 1];
+
+            // Without pauses the noise estimate is speech itself, so the margin
+            // is also measured down from the loudest frame
+            let relative = (noise + self.config.noise_margin_db).min(peak - self.config.noise_margin_db);
+            relative.max(self.config.min_energy_db)
+        }
+
+        fn ms_to_samples(&self, ms: u32) -> usize {
+            (self.sample_rate as u64 * ms as u64 / 1000) as usize
+        }
+
+        fn secs_to_samples(&self, secs: f64) -> usize {
+            (secs.max(0.0) * self.sample_rate as f64).round() as usize
+        }
+    }
+
+    /// Total duration of the regions in seconds.
+    pub fn speech_duration(regions: &[SpeechRegion]) -> f64 {
+        regions.iter().map(SpeechRegion::duration).sum()
+    }
+
+    /// RMS level of the frame in dBFS, -100 for digital silence.
+    fn energy_db(frame: &[f32]) -> f64 {
+        let mean_square = frame.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / frame.len() as f64;
+        if mean_square <= 1e-10 {
+            -100.0
+        } else {
+            10.0 * mean_square.log10()
+        }
+    }
+
+    /// Fraction of adjacent sample pairs with different signs.
+    fn zero_crossing_rate(frame: &[f32]) -> f64 {
+        if frame.len() < 2 {
+            return 0.0;
+        }
+        let crossings = frame.windows(2).filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0)).count();
+        crossings as f64 / (frame.len() - 1) as f64
+    }
+}
@@ -0,0 +1,142 @@
+pub mod vocabulary {
+    use crate::speech_to_text::speech_to_text::Segment;
+
+    /// Words at least this similar to a term, from 0 to 1, are taken to be a
+    /// misspelling of it.
+    const MIN_SIMILARITY: f64 = 0.8;
+    /// Shorter terms are too close to ordinary words to be matched loosely;
+    /// only their case is corrected.
+    const MIN_FUZZY_CHARS: usize = 6;
+
+    /// Terms of a /vocab command, separated by commas or new lines, without
+    /// blanks and repeats.
+    pub fn parse_terms(text: &str) -> Vec<String> {
+        let mut terms: Vec<String> = Vec::new();
+        for term in text.split([',', '\n']) {
+            let term = term.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !term.is_empty() && !terms.iter().any(|known| known.to_lowercase() == term.to_lowercase()) {
+                terms.push(term);
+            }
+        }
+        terms
+    }
+
+    /// Initial prompt for whisper listing the terms, which makes it more
+    /// likely to spell them the same way.
+    pub fn prompt(terms: &[String]) -> Option<String> {
+        (!terms.is_empty()).then(|| format!("{}.", terms.join(", ")))
+    }
+
+    /// Replaces near misses of the terms in the text of `segments` with the
+    /// terms as written in the vocabulary.
+    pub fn correct_segments(segments: &mut [Segment], terms: &[String]) {
+        if terms.is_empty() {
+            return;
+        }
+        for segment in segments {
+            if let Some(corrected) = correct(&segment.text, terms) {
+                log::debug!("Vocabulary corrected {:?} to {:?}", segment.text, corrected);
+                segment.text = corrected;
+            }
+        }
+    }
+
+    /// `text` with near misses of the terms replaced, or `None` if nothing
+    /// changed. A term may also be matched by one word less than it has, and a
+    /// single word term by two words, e.g. "Open Telemetry" for "OpenTelemetry".
+    pub fn correct(text: &str, terms: &[String]) -> Option<String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let terms: Vec<(&str, String, usize)> =
+            terms.iter().map(|term| (term.as_str(), letters(term), term.split_whitespace().count())).collect();
+
+        let mut corrected: Vec<String> = Vec::with_capacity(words.len());
+        let mut changed = false;
+        let mut i = 0;
+        while i < words.len() {
+            // The most similar term over the following words
+            let mut best: Option<(&str, usize, f64)> = None;
+            for (term, term_letters, term_words) in &terms {
+                for length in term_words.saturating_sub(1).max(1)..=term_words + 1 {
+                    let Some(window) = words.get(i..i + length) else {
+                        break;
+                    };
+                    let similarity = similarity(&letters(&window.join(" ")), term_letters);
+                    if length > *term_words && !split_term(window, term_letters, *term_words, similarity) {
+                        continue;
+                    }
+                    let matches = if term_letters.chars().count() < MIN_FUZZY_CHARS {
+                        similarity == 1.0
+                    } else {
+                        similarity >= MIN_SIMILARITY
+                    };
+                    if matches && best.is_none_or(|(_, _, best_similarity)| similarity > best_similarity) {
+                        best = Some((term, length, similarity));
+                    }
+                }
+            }
+
+            match best {
+                Some((term, length, _)) => {
+                    let window = &words[i..i + length];
+                    // Punctuation around the words stays
+                    let prefix: String = window[0].chars().take_while(|c| !c.is_alphanumeric()).collect();
+                    let last = window[length - 1];
+                    let suffix_len = last.chars().rev().take_while(|c| !c.is_alphanumeric()).count();
+                    let suffix: String = last.chars().skip(last.chars().count() - suffix_len).collect();
+                    let replacement = format!("{}{}{}", prefix, term, suffix);
+                    changed |= replacement != window.join(" ");
+                    corrected.push(replacement);
+                    i += length;
+                }
+                None => {
+                    corrected.push(words[i].to_string());
+                    i += 1;
+                }
+            }
+        }
+        if !changed {
+            return None;
+        }
+
+        // Whisper starts segments with a space, which is kept
+        let space = if text.starts_with(char::is_whitespace) { " " } else { "" };
+        Some(format!("{}{}", space, corrected.join(" ")))
+    }
+
+    /// Whether `window`, one word longer than the term, is the term split in two
+    /// rather than the term next to another word, as in "a kubernetes". Only
+    /// single word terms split, and the window has to be more `similar` than
+    /// it is without its first or its last word.
+    fn split_term(window: &[&str], term_letters: &str, term_words: usize, similar: f64) -> bool {
+        let without = |part: &[&str]| similarity(&letters(&part.join(" ")), term_letters);
+        term_words == 1 && without(&window[1..]) < similar && without(&window[..window.len() - 1]) < similar
+    }
+
+    /// The letters and digits of `text` in lower case, without spaces and
+    /// punctuation.
+    fn letters(text: &str) -> String {
+        text.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+    }
+
+    /// 1 minus the edit distance between `a` and `b` relative to the longer of
+    /// them.
+    fn similarity(a: &str, b: &str) -> f64 {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        let longest = a.len().max(b.len());
+        if longest == 0 {
+            return 0.0;
+        }
+
+        let mut previous: Vec<usize> = (0..=b.len()).collect();
+        for (i, &x) in a.iter().enumerate() {
+            let mut current = vec![i + 1; b.len() + 1];
+            for (j, &y) in b.iter().enumerate() {
+                let substitution = previous[j] + usize::from(x != y);
+                current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            }
+            previous = current;
+        }
+        1.0 - previous[b.len()] as f64 / longest as f64
+    }
+}
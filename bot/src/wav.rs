@@ -0,0 +1,353 @@
+pub mod wav {
+    use std::io::{self, BufReader, Read};
+    use crate::audio_conversion::audio_conversion::{
+        remix, AudioConverter, AudioData, ConversionError, InputFormat, SampleFormat, TargetSpec,
+    };
+
+    const FORMAT_PCM: u16 = 0x0001;
+    const FORMAT_IEEE_FLOAT: u16 = 0x0003;
+    const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+    /// Bytes 2..16 of the `KSDATAFORMAT_SUBTYPE_*` GUIDs used by
+    /// WAVE_FORMAT_EXTENSIBLE. The first two bytes are the plain format tag.
+    const SUBFORMAT_GUID_TAIL: [u8; 14] = [
+        0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+    ];
+
+    /// Longest `fmt ` chunk accepted. The extensible format needs 40 bytes.
+    const MAX_FORMAT_CHUNK: u32 = 1024;
+
+    /// Data chunk sizes that writers use when they don't know the length yet,
+    /// e.g. when writing to a pipe. The samples then run to the end of the file.
+    const UNKNOWN_DATA_SIZES: [u32; 2] = [0, u32::MAX];
+
+    /// Sample frames decoded at a time.
+    const FRAMES_PER_READ: usize = 4096;
+
+    /// Encodings of samples in the data chunk.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Encoding {
+        /// 8-bit samples are unsigned, with silence at 128
+        U8,
+        I16,
+        I24,
+        I32,
+        F32,
+        F64,
+    }
+
+    impl Encoding {
+        fn bytes(self) -> usize {
+            match self {
+                Encoding::U8 => 1,
+                Encoding::I16 => 2,
+                Encoding::I24 => 3,
+                Encoding::I32 | Encoding::F32 => 4,
+                Encoding::F64 => 8,
+            }
+        }
+
+        /// Name of the encoding as ffmpeg calls it.
+        fn codec(self) -> &'static str {
+            match self {
+                Encoding::U8 => "pcm_u8",
+                Encoding::I16 => "pcm_s16le",
+                Encoding::I24 => "pcm_s24le",
+                Encoding::I32 => "pcm_s32le",
+                Encoding::F32 => "pcm_f32le",
+                Encoding::F64 => "pcm_f64le",
+            }
+        }
+
+        /// The sample in `bytes` scaled to [-1, 1] (floats are passed through).
+        fn decode(self, bytes: &[u8]) -> f32 {
+            match self {
+                Encoding::U8 => (bytes[0] as f32 - 128.0) / 128.0,
+                Encoding::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+                // Shifted into the top of an i32 and back for the sign
+                Encoding::I24 => (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as f32 / 8388608.0,
+                Encoding::I32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / 2147483648.0,
+                Encoding::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                Encoding::F64 => f64::from_le_bytes([
+                    bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+                ]) as f32,
+            }
+        }
+    }
+
+    /// What the `fmt ` chunk says about the samples.
+    #[derive(Debug, Clone, Copy)]
+    struct Format {
+        encoding: Encoding,
+        channels: u16,
+        sample_rate: u32,
+    }
+
+    impl Format {
+        fn frame_bytes(&self) -> usize {
+            self.encoding.bytes() * self.channels as usize
+        }
+    }
+
+    /// Reads a WAV file from `reader` into interleaved samples in [-1, 1].
+    ///
+    /// Reads 8-bit unsigned, 16, 24 and 32-bit signed integer and 32 and 64-bit
+    /// float samples, in plain and WAVE_FORMAT_EXTENSIBLE files. The input is
+    /// read once from start to end, so it can come from a pipe. Malformed and
+    /// truncated files are reported as `ConversionError::Wav`.
+    pub fn read_wav<R: Read>(reader: R) -> Result<AudioData, ConversionError> {
+        read_wav_limited(reader, None)
+    }
+
+    /// Same as `read_wav`, stopping after `max_duration_secs` seconds of audio
+    /// if given. The rest of the file isn't read.
+    pub fn read_wav_limited<R: Read>(reader: R, max_duration_secs: Option<u32>) -> Result<AudioData, ConversionError> {
+        let mut reader = BufReader::new(reader);
+
+        let mut header = [0; 12];
+        read_exact(&mut reader, &mut header, "RIFF header")?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(invalid("not a RIFF WAVE file"));
+        }
+
+        let mut format = None;
+        loop {
+            let mut chunk = [0; 8];
+            match read_full(&mut reader, &mut chunk)? {
+                0 => return Err(invalid("no data chunk")),
+                8 => {}
+                _ => return Err(invalid("file ends inside a chunk header")),
+            }
+            let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+
+            match &chunk[0..4] {
+                b"fmt " => {
+                    if !(16..=MAX_FORMAT_CHUNK).contains(&size) {
+                        return Err(invalid(format!("fmt chunk of {} bytes", size)));
+                    }
+                    let mut body = vec![0; size as usize];
+                    read_exact(&mut reader, &mut body, "fmt chunk")?;
+                    skip(&mut reader, u64::from(size & 1))?;
+                    format = Some(parse_format(&body)?);
+                }
+                b"data" => {
+                    let format = format.ok_or_else(|| invalid("data chunk before the fmt chunk"))?;
+                    let size = (!UNKNOWN_DATA_SIZES.contains(&size)).then_some(u64::from(size));
+                    return read_samples(reader, format, size, max_duration_secs);
+                }
+                // Chunks are padded to an even length
+                _ => skip(&mut reader, u64::from(size) + u64::from(size & 1))?,
+            }
+        }
+    }
+
+    fn parse_format(body: &[u8]) -> Result<Format, ConversionError> {
+        let u16_at = |i: usize| u16::from_le_bytes([body[i], body[i + 1]]);
+        let mut tag = u16_at(0);
+        let channels = u16_at(2);
+        let sample_rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+        let block_align = u16_at(12);
+        let bits = u16_at(14);
+
+        if tag == FORMAT_EXTENSIBLE {
+            // cbSize, valid bits, channel mask and the 16-byte subformat GUID
+            if body.len() < 40 {
+                return Err(invalid("WAVE_FORMAT_EXTENSIBLE fmt chunk too short"));
+            }
+            if body[26..40] != SUBFORMAT_GUID_TAIL {
+                return Err(ConversionError::UnsupportedFormat("unknown WAVE_FORMAT_EXTENSIBLE subformat".to_string()));
+            }
+            // Samples with fewer valid bits are stored left-aligned in `bits`,
+            // so they decode like full-width ones
+            tag = u16_at(24);
+        }
+
+        let encoding = match (tag, bits) {
+            (FORMAT_PCM, 8) => Encoding::U8,
+            (FORMAT_PCM, 16) => Encoding::I16,
+            (FORMAT_PCM, 24) => Encoding::I24,
+            (FORMAT_PCM, 32) => Encoding::I32,
+            (FORMAT_IEEE_FLOAT, 32) => Encoding::F32,
+            (FORMAT_IEEE_FLOAT, 64) => Encoding::F64,
+            (FORMAT_PCM, bits) => {
+                return Err(ConversionError::UnsupportedFormat(format!("{}-bit integer WAV", bits)))
+            }
+            (FORMAT_IEEE_FLOAT, bits) => {
+                return Err(ConversionError::UnsupportedFormat(format!("{}-bit float WAV", bits)))
+            }
+            (tag, _) => return Err(ConversionError::UnsupportedFormat(format!("WAV format tag {:#06x}", tag))),
+        };
+
+        if channels == 0 {
+            return Err(invalid("no channels"));
+        }
+        if sample_rate == 0 {
+            return Err(invalid("sample rate of 0"));
+        }
+        let format = Format {
+            encoding,
+            channels,
+            sample_rate,
+        };
+        if block_align as usize != format.frame_bytes() {
+            return Err(invalid(format!(
+                "block align of {} bytes for {} channels of {} bytes",
+                block_align,
+                channels,
+                encoding.bytes()
+            )));
+        }
+        Ok(format)
+    }
+
+    /// Decodes the data chunk of `size` bytes, or up to the end of the input
+    /// if the size is unknown, but no more than `max_duration_secs` of it.
+    fn read_samples<R: Read>(
+        mut reader: R,
+        format: Format,
+        size: Option<u64>,
+        max_duration_secs: Option<u32>,
+    ) -> Result<AudioData, ConversionError> {
+        let frame_bytes = format.frame_bytes();
+        let mut allowed = max_duration_secs
+            .map_or(u64::MAX, |secs| u64::from(secs) * u64::from(format.sample_rate) * frame_bytes as u64);
+        if size.is_some_and(|size| size % frame_bytes as u64 != 0) {
+            return Err(invalid("data chunk size is not a whole number of sample frames"));
+        }
+
+        // The size comes from the file and is not trusted for the allocation
+        let expected = size.map_or(0, |size| size as usize / format.encoding.bytes());
+        let mut samples = Vec::with_capacity(expected.min(FRAMES_PER_READ * 64));
+        let mut buffer = vec![0; frame_bytes * FRAMES_PER_READ];
+        let mut remaining = size;
+        loop {
+            let wanted = remaining.unwrap_or(u64::MAX).min(allowed).min(buffer.len() as u64) as usize;
+            if wanted == 0 {
+                break;
+            }
+            let read = read_full(&mut reader, &mut buffer[..wanted])?;
+            if read < wanted && remaining.is_some() {
+                return Err(invalid("file ends inside the data chunk"));
+            }
+            if read % frame_bytes != 0 {
+                return Err(invalid("file ends inside a sample frame"));
+            }
+
+            let encoding = format.encoding;
+            samples.extend(buffer[..read].chunks_exact(encoding.bytes()).map(|bytes| encoding.decode(bytes)));
+
+            if read < wanted {
+                break;
+            }
+            remaining = remaining.map(|remaining| remaining - read as u64);
+            allowed -= read as u64;
+        }
+
+        Ok(AudioData::new(samples, format.sample_rate, format.channels)
+            .with_source(Some("wav".to_string()), Some(format.encoding.codec().to_string())))
+    }
+
+    /// Writes interleaved `samples` in [-1, 1] as a WAV file in the `spec` format.
+    pub fn write_wav(samples: &[f32], spec: &TargetSpec) -> Vec<u8> {
+        let sample_bytes = spec.sample_format.bytes();
+        let data_size = (samples.len() * sample_bytes) as u32;
+        let tag = match spec.sample_format {
+            SampleFormat::I16 => FORMAT_PCM,
+            SampleFormat::F32 => FORMAT_IEEE_FLOAT,
+        };
+        let block_align = spec.channels * sample_bytes as u16;
+
+        let mut wav = Vec::with_capacity(44 + data_size as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&tag.to_le_bytes());
+        wav.extend_from_slice(&spec.channels.to_le_bytes());
+        wav.extend_from_slice(&spec.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(spec.sample_rate * u32::from(block_align)).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&(sample_bytes as u16 * 8).to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        for &sample in samples {
+            match spec.sample_format {
+                SampleFormat::I16 => {
+                    let sample = (sample.clamp(-1.0, 1.0) * 32768.0).round().clamp(-32768.0, 32767.0) as i16;
+                    wav.extend_from_slice(&sample.to_le_bytes());
+                }
+                SampleFormat::F32 => wav.extend_from_slice(&sample.to_le_bytes()),
+            }
+        }
+        wav
+    }
+
+    /// Reads WAV files without an external process. Only changes the number of
+    /// channels; files at another sample rate are left to the next converter.
+    pub struct WavConverter;
+
+    impl AudioConverter for WavConverter {
+        fn convert_audio_to_wav(&self, input_data: &[u8], target: &TargetSpec) -> Result<Vec<u8>, ConversionError> {
+            Ok(write_wav(&self.decode_audio(input_data, target)?.samples, target))
+        }
+
+        fn name(&self) -> &'static str {
+            "wav"
+        }
+
+        fn supports(&self, format: InputFormat) -> bool {
+            format == InputFormat::Wav
+        }
+
+        fn decode_audio(&self, input_data: &[u8], target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            let audio = read_wav_limited(input_data, target.max_duration_secs)?;
+            if audio.sample_rate != target.sample_rate {
+                return Err(ConversionError::UnsupportedFormat(format!(
+                    "{} Hz WAV would need resampling to {} Hz",
+                    audio.sample_rate, target.sample_rate
+                )));
+            }
+            if audio.channels == target.channels {
+                return Ok(audio);
+            }
+
+            let samples = remix(&audio.samples, audio.channels, target.channels).ok_or_else(|| {
+                ConversionError::UnsupportedFormat(format!("{} channels to {}", audio.channels, target.channels))
+            })?;
+            Ok(AudioData::new(samples, target.sample_rate, target.channels).with_source(audio.container, audio.codec))
+        }
+    }
+
+    /// Fills as much of `buffer` as the input allows and returns how much that was.
+    fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, ConversionError> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match reader.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(filled)
+    }
+
+    fn read_exact<R: Read>(reader: &mut R, buffer: &mut [u8], what: &str) -> Result<(), ConversionError> {
+        if read_full(reader, buffer)? < buffer.len() {
+            return Err(invalid(format!("file ends inside the {}", what)));
+        }
+        Ok(())
+    }
+
+    fn skip<R: Read>(reader: &mut R, bytes: u64) -> Result<(), ConversionError> {
+        let skipped = io::copy(&mut reader.take(bytes), &mut io::sink())?;
+        if skipped < bytes {
+            return Err(invalid("file ends inside a chunk"));
+        }
+        Ok(())
+    }
+
+    fn invalid<S: Into<String>>(reason: S) -> ConversionError {
+        ConversionError::Wav(reason.into())
+    }
+}
@@ -0,0 +1,33 @@
+pub mod wer {
+    /// Word error rate of `hypothesis` against `reference`: the substituted,
+    /// deleted and inserted words over the number of words in the reference.
+    /// Case and punctuation are ignored. Can be more than 1 when the
+    /// hypothesis has many extra words.
+    pub fn word_error_rate(reference: &str, hypothesis: &str) -> f64 {
+        let reference = words(reference);
+        let hypothesis = words(hypothesis);
+        if reference.is_empty() {
+            return if hypothesis.is_empty() { 0.0 } else { 1.0 };
+        }
+
+        // Edit distance over words, one row at a time
+        let mut previous: Vec<usize> = (0..=hypothesis.len()).collect();
+        for (i, word) in reference.iter().enumerate() {
+            let mut current = vec![i + 1; hypothesis.len() + 1];
+            for (j, other) in hypothesis.iter().enumerate() {
+                let substitution = previous[j] + usize::from(word != other);
+                current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            }
+            previous = current;
+        }
+        previous[hypothesis.len()] as f64 / reference.len() as f64
+    }
+
+    /// Lowercase words without punctuation.
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .map(|word| word.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect::<String>())
+            .filter(|word| !word.is_empty())
+            .collect()
+    }
+}
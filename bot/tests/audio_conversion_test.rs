@@ -1,9 +1,8 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
     use hound::{WavSpec, WavWriter, SampleFormat};
     use std::io::Cursor;
-    use voicebot::audio_conversion::audio_conversion::convert_wav_to_samples;
+    use voicebot::audio_conversion::audio_conversion::{convert_wav_to_samples, ConversionError};
 
     #[test]
     fn test_convert_wav_to_samples() {
@@ -49,8 +48,14 @@ mod tests {
 
         // Check the contents of the samples (e.g., check the first few samples)
         // For a sine wave, we expect the values to oscillate between -1.0 and 1.0
-        assert!(samples.iter().all(|&s| s >= -1.0 && s <= 1.0), "Samples are out of expected range");
+        assert!(samples.iter().all(|&s| (-1.0..=1.0).contains(&s)), "Samples are out of expected range");
 
         assert_eq!(audio_data.duration, duration_seconds as f64, "The duration of the audio data does not match the expected value");
     }
+
+    #[test]
+    fn test_invalid_wav_is_an_error() {
+        let result = convert_wav_to_samples(b"definitely not a RIFF file");
+        assert!(matches!(result, Err(ConversionError::Wav(_))));
+    }
 }
@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::chunking::chunking::{plan, repeated_words, stitch, Chunk};
+    use voicebot::config::config::ChunkingConfig;
+    use voicebot::speech_to_text::speech_to_text::{Segment, Transcript};
+    use voicebot::vad::vad::SpeechRegion;
+
+    const RATE: u32 = 100;
+
+    fn config() -> ChunkingConfig {
+        ChunkingConfig { max_chunk_secs: 10.0, overlap_secs: 1.0, ..ChunkingConfig::default() }
+    }
+
+    fn region(start: f64, end: f64) -> SpeechRegion {
+        SpeechRegion { start, end }
+    }
+
+    fn segment(start: f64, end: f64, text: &str) -> Segment {
+        Segment { start, end, text: text.to_string(), speaker: None }
+    }
+
+    #[test]
+    fn test_short_audio_is_one_chunk() {
+        let chunks = plan(8 * RATE as usize, &[region(0.0, 8.0)], RATE, &config());
+        assert_eq!(chunks, vec![Chunk { start: 0, end: 800, keep_from: 0.0, keep_until: 8.0 }]);
+    }
+
+    #[test]
+    fn test_cuts_at_longest_pause() {
+        // Pauses at 6-6.4 (short), 7-8 (long) and 9.5-9.6; the cut goes into the long one
+        let regions = [region(0.0, 6.0), region(6.4, 7.0), region(8.0, 9.5), region(9.6, 25.0)];
+        let chunks = plan(25 * RATE as usize, &regions, RATE, &config());
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].keep_until, 7.5);
+        assert_eq!(chunks[0].end, 850);
+        assert_eq!(chunks[1].keep_from, 7.5);
+        assert_eq!(chunks[1].start, 650);
+        // No pause in the rest, so the cut falls at the maximum length
+        assert_eq!(chunks[1].keep_until, 17.5);
+        assert_eq!(chunks[2].keep_until, 25.0);
+        assert_eq!(chunks[2].end, 2500);
+        assert!(chunks.iter().all(|chunk| chunk.keep_until - chunk.keep_from <= 10.0));
+    }
+
+    #[test]
+    fn test_stitch_shifts_and_drops_overlaps() {
+        let chunks = [
+            Chunk { start: 0, end: 1100, keep_from: 0.0, keep_until: 10.0 },
+            Chunk { start: 900, end: 1500, keep_from: 10.0, keep_until: 15.0 },
+        ];
+        let transcripts = vec![
+            Transcript {
+                segments: vec![segment(0.0, 5.0, " Hello there."), segment(5.0, 9.8, " How are you")],
+                language: Some("en".to_string()),
+            },
+            Transcript {
+                // The first segment lies in the previous chunk, the second one repeats "you"
+                segments: vec![segment(0.0, 0.8, " are you"), segment(0.8, 3.0, " you doing today?")],
+                language: Some("en".to_string()),
+            },
+        ];
+
+        let transcript = stitch(&chunks, transcripts, RATE);
+        assert_eq!(transcript.language.as_deref(), Some("en"));
+        assert_eq!(transcript.text(), "Hello there. How are you doing today?");
+        assert_eq!(transcript.segments[2].start, 9.8);
+        assert_eq!(transcript.segments[2].end, 12.0);
+    }
+
+    #[test]
+    fn test_repeated_words() {
+        assert_eq!(repeated_words("how are you", "You, doing?"), 1);
+        assert_eq!(repeated_words("see you at the", "at the station"), 2);
+        assert_eq!(repeated_words("hello", "world"), 0);
+        assert_eq!(repeated_words("", "world"), 0);
+    }
+}
@@ -0,0 +1,214 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::NamedTempFile;
+    use voicebot::config::config::{Config, ConfigError, ParagraphMode, PreprocessStage, Speakers};
+
+    fn write_config(contents: &str) -> NamedTempFile {
+        let file = NamedTempFile::new().expect("Failed to create temp config");
+        fs::write(file.path(), contents).expect("Failed to write temp config");
+        file
+    }
+
+    #[test]
+    fn test_config_from_file() {
+        let model = NamedTempFile::new().unwrap();
+        let file = write_config(&format!(
+            r#"
+            [telegram]
+            token = "123:abc"
+
+            [whisper]
+            model_path = "{}"
+            threads = 8
+
+            [bot]
+            recording_to_wall_ratio = 4.5
+
+            [preprocess]
+            stages = ["normalize", "denoise"]
+
+            [diarization]
+            speakers = "auto"
+
+            [paragraphs]
+            mode = "timestamps"
+            "#,
+            model.path().display()
+        ));
+
+        let config = Config::from_file(file.path()).expect("Failed to parse config");
+        config.validate().expect("Config should be valid");
+
+        assert_eq!(config.telegram_token().unwrap(), "123:abc");
+        assert_eq!(config.whisper.threads, 8);
+        assert_eq!(config.bot.recording_to_wall_ratio, 4.5);
+        assert_eq!(config.preprocess.stages, vec![PreprocessStage::Normalize, PreprocessStage::Denoise]);
+        assert_eq!(config.diarization.speakers, Speakers::Auto);
+        assert_eq!(config.paragraphs.mode, ParagraphMode::Timestamps);
+        // Sections that are not present fall back to defaults
+        assert_eq!(config.ffmpeg.binary.to_str(), Some("ffmpeg"));
+    }
+
+    #[test]
+    fn test_env_overrides_file_values() {
+        let model = NamedTempFile::new().unwrap();
+        let file = write_config("[whisper]\nthreads = 2\n");
+        let mut config = Config::from_file(file.path()).unwrap();
+
+        let env: HashMap<&str, String> = HashMap::from([
+            ("GGML", model.path().display().to_string()),
+            ("WHISPER_THREADS", "6".to_string()),
+            ("TELOXIDE_TOKEN", "42:xyz".to_string()),
+            ("BOT_ADMINS", "1001, 1002".to_string()),
+        ]);
+        config
+            .apply_env_overrides(|var| env.get(var).cloned())
+            .expect("Overrides should apply");
+
+        assert_eq!(config.whisper.threads, 6);
+        assert_eq!(config.whisper.model_path, model.path());
+        assert_eq!(config.telegram_token().unwrap(), "42:xyz");
+        assert!(config.is_admin(1002));
+        assert!(!config.is_admin(42));
+        config.validate().expect("Config should be valid");
+    }
+
+    #[test]
+    fn test_invalid_env_value_is_reported() {
+        let mut config = Config::default();
+        let result = config.apply_env_overrides(|var| {
+            (var == "WHISPER_THREADS").then(|| "many".to_string())
+        });
+
+        match result {
+            Err(ConfigError::InvalidEnv { var, value, .. }) => {
+                assert_eq!(var, "WHISPER_THREADS");
+                assert_eq!(value, "many");
+            }
+            other => panic!("Expected InvalidEnv error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        // Model path is required
+        let config = Config::default();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "whisper.model_path", .. })
+        ));
+
+        let model = NamedTempFile::new().unwrap();
+        let mut config = Config::default();
+        config.whisper.model_path = model.path().to_owned();
+        config.bot.recording_to_wall_ratio = 0.0;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "bot.recording_to_wall_ratio", .. })
+        ));
+
+        config.bot.recording_to_wall_ratio = 10.0;
+        config.messages.max_message_chars = 5000;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "messages.max_message_chars", .. })
+        ));
+
+        config.messages.max_message_chars = 4096;
+        config.chunking.max_chunk_secs = 1.5;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "chunking.max_chunk_secs", .. })
+        ));
+
+        config.chunking.max_chunk_secs = 60.0;
+        config.quality.max_clipping_ratio = 1.5;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "quality.max_clipping_ratio", .. })
+        ));
+
+        config.quality.max_clipping_ratio = 0.01;
+        config.diarization.speakers = Speakers::Count(10);
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "diarization.speakers", .. })
+        ));
+
+        config.diarization.speakers = Speakers::Auto;
+        config.hallucinations.min_repeats = 1;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "hallucinations.min_repeats", .. })
+        ));
+
+        config.hallucinations.min_repeats = 3;
+        config.paragraphs.max_paragraph_secs = 1.0;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "paragraphs.max_paragraph_secs", .. })
+        ));
+
+        config.paragraphs.max_paragraph_secs = 60.0;
+        config.vocabulary.max_terms = 0;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "vocabulary.max_terms", .. })
+        ));
+
+        // The token is only checked when asked for
+        assert!(config.telegram_token().is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let file = write_config("[whisper]\nthread = 2\n");
+        assert!(matches!(
+            Config::from_file(file.path()),
+            Err(ConfigError::Parse { .. })
+        ));
+
+        let file = write_config("[diarization]\nspeakers = \"many\"\n");
+        assert!(matches!(
+            Config::from_file(file.path()),
+            Err(ConfigError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn test_speakers_parsing() {
+        assert_eq!(Speakers::parse("off"), Some(Speakers::Off));
+        assert_eq!(Speakers::parse(" Auto "), Some(Speakers::Auto));
+        assert_eq!(Speakers::parse("3"), Some(Speakers::Count(3)));
+        assert_eq!(Speakers::parse("0"), None);
+        assert_eq!(Speakers::parse("two"), None);
+        assert_eq!(Speakers::Count(3).name(), "3");
+    }
+
+    #[test]
+    fn test_custom_bot_api_server() {
+        let model = NamedTempFile::new().unwrap();
+        let mut config = Config::default();
+        config.whisper.model_path = model.path().to_owned();
+        assert!(config.api_url().unwrap().is_none());
+
+        // Local mode only makes sense with a custom server
+        config.telegram.local_mode = true;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "telegram.local_mode", .. })
+        ));
+
+        config.telegram.api_url = Some("not a url".to_string());
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "telegram.api_url", .. })
+        ));
+
+        config.telegram.api_url = Some("http://localhost:8081".to_string());
+        config.validate().expect("Config should be valid");
+        assert_eq!(config.api_url().unwrap().unwrap().as_str(), "http://localhost:8081/");
+    }
+}
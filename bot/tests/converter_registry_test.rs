@@ -0,0 +1,234 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::audio_conversion::audio_conversion::{
+        AudioConverter, AudioData, ConversionError, InputFormat, SampleFormat, TargetSpec,
+    };
+    use voicebot::converter_registry::converter_registry::ConverterRegistry;
+    use voicebot::ogg::ogg::{crc, read_ogg};
+    use voicebot::opus_converter::opus_converter::OpusConverter;
+    use voicebot::wav::wav::{read_wav, write_wav, WavConverter};
+
+    const WHISPER_SPEC: TargetSpec =
+        TargetSpec { sample_rate: 16000, channels: 1, sample_format: SampleFormat::F32, max_duration_secs: None };
+
+    /// A converter that always fails or always returns a second of silence.
+    struct Fake {
+        name: &'static str,
+        formats: Vec<InputFormat>,
+        works: bool,
+    }
+
+    impl AudioConverter for Fake {
+        fn convert_audio_to_wav(&self, input_data: &[u8], target: &TargetSpec) -> Result<Vec<u8>, ConversionError> {
+            Ok(write_wav(&self.decode_audio(input_data, target)?.samples, target))
+        }
+
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn supports(&self, format: InputFormat) -> bool {
+            self.formats.contains(&format)
+        }
+
+        fn decode_audio(&self, _input_data: &[u8], target: &TargetSpec) -> Result<AudioData, ConversionError> {
+            if !self.works {
+                return Err(ConversionError::UnsupportedFormat(self.name.to_string()));
+            }
+            let samples = vec![0.0; target.sample_rate as usize];
+            Ok(AudioData::new(samples, target.sample_rate, target.channels).with_source(None, Some(self.name.to_string())))
+        }
+    }
+
+    fn fake(name: &'static str, formats: &[InputFormat], works: bool) -> Fake {
+        Fake { name, formats: formats.to_vec(), works }
+    }
+
+    /// One Ogg page of stream 1 holding `packets`, which must be shorter than
+    /// 255 bytes each.
+    fn ogg_page(flags: u8, granule: u64, sequence: u32, packets: &[&[u8]]) -> Vec<u8> {
+        let mut page = b"OggS\0".to_vec();
+        page.push(flags);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&1u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(packets.len() as u8);
+        page.extend(packets.iter().map(|packet| packet.len() as u8));
+        packets.iter().for_each(|packet| page.extend_from_slice(packet));
+        let checksum = crc(&page);
+        page[22..26].copy_from_slice(&checksum.to_le_bytes());
+        page
+    }
+
+    /// An Ogg Opus file of `packets` 20 ms packets that only have a TOC byte,
+    /// each decoding to 960 samples at 48 kHz.
+    fn ogg_opus(pre_skip: u16, final_granule: u64, packets: usize) -> Vec<u8> {
+        let mut head = b"OpusHead".to_vec();
+        head.push(1); // version
+        head.push(1); // channels
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&48000u32.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // gain
+        head.push(0); // mapping family
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&4u32.to_le_bytes());
+        tags.extend_from_slice(b"test");
+        tags.extend_from_slice(&0u32.to_le_bytes());
+
+        // Configuration 31: CELT, fullband, 20 ms frames, one frame per packet
+        let toc = [31u8 << 3];
+        let audio: Vec<&[u8]> = vec![&toc; packets];
+
+        let mut file = ogg_page(0x02, 0, 0, &[&head]);
+        file.extend(ogg_page(0, 0, 1, &[&tags]));
+        file.extend(ogg_page(0x04, final_granule, 2, &audio));
+        file
+    }
+
+    #[test]
+    fn test_format_detection() {
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.extend_from_slice(&[0; 8]);
+        assert_eq!(InputFormat::from_magic(&wav), InputFormat::Wav);
+        assert_eq!(InputFormat::from_magic(&ogg_opus(0, 0, 1)), InputFormat::OggOpus);
+        assert_eq!(InputFormat::from_magic(b"fLaC\0\0\0\x22"), InputFormat::Flac);
+        assert_eq!(InputFormat::from_magic(b"ID3\x04\0"), InputFormat::Mp3);
+        assert_eq!(InputFormat::from_magic(&[0xFF, 0xFB, 0x90, 0x64]), InputFormat::Mp3);
+        assert_eq!(InputFormat::from_magic(b"\0\0\0\x20ftypM4A "), InputFormat::Mp4);
+        assert_eq!(InputFormat::from_magic(&[0xFF, 0xF1, 0x50, 0x80]), InputFormat::Aac);
+        assert_eq!(InputFormat::from_magic(b"\x1aE\xdf\xa3"), InputFormat::Unknown);
+
+        assert_eq!(InputFormat::from_mime("audio/ogg; codecs=opus"), InputFormat::OggOpus);
+        assert_eq!(InputFormat::from_mime("audio/ogg"), InputFormat::Ogg);
+        assert_eq!(InputFormat::from_mime("Audio/MPEG"), InputFormat::Mp3);
+        assert_eq!(InputFormat::from_mime("audio/x-m4a"), InputFormat::Mp4);
+        assert_eq!(InputFormat::from_mime("video/webm"), InputFormat::Unknown);
+
+        // The content wins over the MIME type, which is only a fallback
+        assert_eq!(InputFormat::detect(Some("audio/mpeg"), b"fLaC"), InputFormat::Flac);
+        assert_eq!(InputFormat::detect(Some("audio/mpeg"), &[]), InputFormat::Mp3);
+        assert_eq!(InputFormat::detect(None, &[]), InputFormat::Unknown);
+    }
+
+    #[test]
+    fn test_failed_converter_falls_through() {
+        let registry = ConverterRegistry::new()
+            .then(fake("broken", &[InputFormat::Flac], false))
+            .then(fake("flac", &[InputFormat::Flac], true))
+            .then(fake("anything", &[InputFormat::Flac, InputFormat::Mp3], true));
+
+        assert_eq!(registry.chain(InputFormat::Flac), vec!["broken", "flac", "anything"]);
+        assert_eq!(registry.chain(InputFormat::Mp3), vec!["anything"]);
+
+        let audio = registry.decode(b"fLaC", None, &WHISPER_SPEC).expect("Decoding should fall through");
+        assert_eq!(audio.codec.as_deref(), Some("flac"));
+
+        // Nothing supports WAV here
+        assert!(matches!(
+            registry.decode(b"RIFF\0\0\0\0WAVE", None, &WHISPER_SPEC),
+            Err(ConversionError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_last_error_is_returned() {
+        let registry = ConverterRegistry::new().then(fake("broken", &[InputFormat::Mp3], false));
+        match registry.decode(&[], Some("audio/mpeg"), &WHISPER_SPEC) {
+            Err(ConversionError::UnsupportedFormat(name)) => assert_eq!(name, "broken"),
+            other => panic!("Expected the converter's error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_standard_order() {
+        let registry = ConverterRegistry::standard(&Default::default());
+        assert_eq!(registry.chain(InputFormat::Wav), vec!["wav", "symphonia", "ffmpeg"]);
+        assert_eq!(registry.chain(InputFormat::OggOpus), vec!["opus", "ffmpeg"]);
+        assert_eq!(registry.chain(InputFormat::Ogg), vec!["opus", "symphonia", "ffmpeg"]);
+        assert_eq!(registry.chain(InputFormat::Mp4), vec!["symphonia", "ffmpeg"]);
+        assert_eq!(registry.chain(InputFormat::Unknown), vec!["ffmpeg"]);
+        assert!(registry.native_first(InputFormat::OggOpus));
+        assert!(!registry.native_first(InputFormat::Unknown));
+    }
+
+    #[test]
+    fn test_wav_converter() {
+        let stereo_spec = TargetSpec { channels: 2, ..WHISPER_SPEC };
+        let wav = write_wav(&[0.5, -0.5, 0.25, 0.75], &stereo_spec);
+        let stereo = read_wav(wav.as_slice()).unwrap();
+        assert_eq!((stereo.sample_rate, stereo.channels), (16000, 2));
+        assert_eq!(stereo.samples, vec![0.5, -0.5, 0.25, 0.75]);
+
+        let mono = WavConverter.decode_audio(&wav, &WHISPER_SPEC).expect("Channels should be mixed down");
+        assert_eq!(mono.channels, 1);
+        assert_eq!(mono.samples, vec![0.0, 0.5]);
+
+        // Resampling is left to another converter
+        let other_rate = TargetSpec { sample_rate: 8000, ..WHISPER_SPEC };
+        assert!(matches!(WavConverter.decode_audio(&wav, &other_rate), Err(ConversionError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_wav_decoding_stops_at_max_duration() {
+        // 3 s of stereo, of which only the first second is read
+        let stereo_spec = TargetSpec { channels: 2, ..WHISPER_SPEC };
+        let wav = write_wav(&vec![0.25; 3 * 16000 * 2], &stereo_spec);
+        let audio = WavConverter.decode_audio(&wav, &WHISPER_SPEC.with_max_duration(1)).unwrap();
+        assert_eq!(audio.samples.len(), 16000);
+        assert_eq!(audio.duration, 1.0);
+
+        // A file ending early past the limit is still read up to it
+        let audio = WavConverter.decode_audio(&wav[..wav.len() - 100], &WHISPER_SPEC.with_max_duration(2)).unwrap();
+        assert_eq!(audio.samples.len(), 32000);
+    }
+
+    #[test]
+    fn test_ogg_reader() {
+        let file = ogg_opus(312, 9000, 3);
+        let stream = read_ogg(&file).expect("Valid Ogg file");
+        assert_eq!(stream.packets.len(), 5);
+        assert!(stream.packets[0].starts_with(b"OpusHead"));
+        assert_eq!(stream.final_granule, Some(9000));
+
+        let mut corrupt = file.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xFF;
+        assert!(matches!(read_ogg(&corrupt), Err(ConversionError::Decode { .. })));
+        assert!(matches!(read_ogg(&file[..file.len() - 1]), Err(ConversionError::Decode { .. })));
+    }
+
+    #[test]
+    fn test_opus_decoding_trims_pre_skip_and_padding() {
+        // 10 packets of 20 ms are 3200 samples at 16 kHz. The first 312 at
+        // 48 kHz are priming and everything past 9000 at 48 kHz is padding.
+        let file = ogg_opus(312, 9000, 10);
+        let audio = OpusConverter.decode_audio(&file, &WHISPER_SPEC).expect("Opus should decode");
+        assert_eq!(audio.samples.len(), 3000 - 104);
+        assert_eq!((audio.sample_rate, audio.channels), (16000, 1));
+        assert_eq!(audio.describe_format(), "ogg/opus, 16000 Hz, 1 ch");
+
+        let registry = ConverterRegistry::standard(&Default::default());
+        let audio = registry.decode(&file, Some("audio/ogg"), &WHISPER_SPEC).unwrap();
+        assert_eq!(audio.codec.as_deref(), Some("opus"));
+    }
+
+    #[test]
+    fn test_opus_decoding_stops_at_max_duration() {
+        // 2 s of audio, of which only the first second is decoded
+        let file = ogg_opus(312, 96312, 101);
+        let audio = OpusConverter.decode_audio(&file, &WHISPER_SPEC.with_max_duration(1)).unwrap();
+        assert_eq!(audio.samples.len(), 16000);
+    }
+
+    #[test]
+    fn test_opus_rejects_unsupported_targets() {
+        let file = ogg_opus(0, 960, 1);
+        let cd_rate = TargetSpec { sample_rate: 44100, ..WHISPER_SPEC };
+        assert!(matches!(OpusConverter.decode_audio(&file, &cd_rate), Err(ConversionError::UnsupportedFormat(_))));
+        assert!(matches!(
+            OpusConverter.decode_audio(b"not ogg at all", &WHISPER_SPEC),
+            Err(ConversionError::Decode { .. })
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use voicebot::config::config::{DiarizationConfig, Speakers};
+    use voicebot::diarization::diarization::{assign_speakers, Diarizer, SpeakerTurn};
+    use voicebot::speech_to_text::speech_to_text::Segment;
+    use voicebot::vad::vad::SpeechRegion;
+
+    const RATE: u32 = 16000;
+
+    /// Vowel formants (Hz) of an adult male voice.
+    const VOWELS: [[f64; 3]; 4] = [[730.0, 1090.0, 2440.0], [270.0, 2290.0, 3010.0], [300.0, 870.0, 2240.0], [530.0, 1840.0, 2480.0]];
+
+    struct Voice { pitch: f64, formant_scale: f64, seed: u64 }
+
+    const LOW: Voice = Voice { pitch: 110.0, formant_scale: 1.0, seed: 1 };
+    const HIGH: Voice = Voice { pitch: 210.0, formant_scale: 1.18, seed: 2 };
+
+    /// `secs` of a synthetic voice: a pulse train at the voice's pitch through
+    /// three formant resonators, changing vowel every 200 ms.
+    fn speak(voice: &Voice, secs: f64, offset: usize) -> Vec<f32> {
+        let mut rng = voice.seed.wrapping_add(offset as u64 * 7919);
+        let mut noise = move || { rng ^= rng << 13; rng ^= rng >> 7; rng ^= rng << 17; (rng % 10000) as f64 / 10000.0 - 0.5 };
+        let n = (secs * RATE as f64) as usize;
+        let mut states = [[0.0f64; 2]; 3];
+        let mut phase = 0.0;
+        let mut out = Vec::with_capacity(n);
+        for i in 0..n {
+            let t = (i + offset) as f64 / RATE as f64;
+            let pitch = voice.pitch * (1.0 + 0.08 * (2.0 * PI * 0.7 * t).sin() + 0.01 * noise());
+            phase += pitch / RATE as f64;
+            let excitation = if phase >= 1.0 { phase -= 1.0; 1.0 } else { 0.0 } + 0.02 * noise();
+            let vowel = VOWELS[((t / 0.2) as usize + offset) % VOWELS.len()];
+            let mut sample = 0.0;
+            for (formant, state) in vowel.iter().zip(states.iter_mut()) {
+                let f = formant * voice.formant_scale;
+                let r = (-PI * 80.0 / RATE as f64).exp();
+                let y = excitation + 2.0 * r * (2.0 * PI * f / RATE as f64).cos() * state[0] - r * r * state[1];
+                state[1] = state[0];
+                state[0] = y;
+                sample += y;
+            }
+            out.push(sample);
+        }
+        let peak = out.iter().fold(0.0f64, |m, s| m.max(s.abs()));
+        out.iter().map(|s| (0.5 * s / peak) as f32).collect()
+    }
+
+    /// The voices one after another, each speaking for `secs`, and a speech
+    /// region covering all of it.
+    fn conversation(voices: &[&Voice], secs: f64) -> (Vec<f32>, Vec<SpeechRegion>) {
+        let mut samples = Vec::new();
+        for voice in voices {
+            let offset = samples.len();
+            samples.extend(speak(voice, secs, offset));
+        }
+        let regions = vec![SpeechRegion { start: 0.0, end: voices.len() as f64 * secs }];
+        (samples, regions)
+    }
+
+    fn speakers(turns: &[SpeakerTurn]) -> Vec<u32> {
+        turns.iter().map(|turn| turn.speaker).collect()
+    }
+
+    fn segment(start: f64, end: f64) -> Segment {
+        Segment { start, end, text: "text".to_string(), speaker: None }
+    }
+
+    #[test]
+    fn test_speakers_taking_turns() {
+        let (samples, regions) = conversation(&[&LOW, &HIGH, &LOW], 6.0);
+        let diarizer = Diarizer::new(&DiarizationConfig::default(), RATE);
+        let turns = diarizer.diarize(&samples, &regions, Speakers::Auto);
+        assert_eq!(speakers(&turns), vec![1, 2, 1], "{:?}", turns);
+
+        // The changes are found to within a window
+        assert!((turns[0].end - 6.0).abs() <= 1.5, "{:?}", turns);
+        assert!((turns[1].end - 12.0).abs() <= 1.5, "{:?}", turns);
+        assert_eq!(turns[0].start, 0.0);
+        assert_eq!(turns[2].end, 18.0);
+        assert!(turns.windows(2).all(|pair| pair[0].end == pair[1].start));
+
+        // The same with the number of speakers given
+        let turns = diarizer.diarize(&samples, &regions, Speakers::Count(2));
+        assert_eq!(speakers(&turns), vec![1, 2, 1], "{:?}", turns);
+    }
+
+    #[test]
+    fn test_single_speaker() {
+        let (samples, regions) = conversation(&[&LOW, &LOW], 6.0);
+        let diarizer = Diarizer::new(&DiarizationConfig::default(), RATE);
+        let turns = diarizer.diarize(&samples, &regions, Speakers::Auto);
+        assert_eq!(speakers(&turns), vec![1], "{:?}", turns);
+    }
+
+    #[test]
+    fn test_nothing_to_diarize() {
+        let (samples, regions) = conversation(&[&LOW, &HIGH], 4.0);
+        let diarizer = Diarizer::new(&DiarizationConfig::default(), RATE);
+        assert!(diarizer.diarize(&samples, &regions, Speakers::Off).is_empty());
+        assert!(diarizer.diarize(&samples, &[], Speakers::Auto).is_empty());
+        assert!(diarizer.diarize(&[], &[], Speakers::Count(2)).is_empty());
+    }
+
+    #[test]
+    fn test_speech_outside_regions_is_ignored() {
+        // The second voice is silence as far as the regions go
+        let (samples, _) = conversation(&[&LOW, &HIGH], 6.0);
+        let regions = [SpeechRegion { start: 0.0, end: 6.0 }];
+        let diarizer = Diarizer::new(&DiarizationConfig::default(), RATE);
+        let turns = diarizer.diarize(&samples, &regions, Speakers::Auto);
+        assert_eq!(speakers(&turns), vec![1], "{:?}", turns);
+        assert!(turns[0].end <= 6.0);
+    }
+
+    #[test]
+    fn test_assign_speakers() {
+        let turns = [
+            SpeakerTurn { start: 0.0, end: 5.0, speaker: 1 },
+            SpeakerTurn { start: 5.0, end: 9.0, speaker: 2 },
+            SpeakerTurn { start: 12.0, end: 15.0, speaker: 1 },
+        ];
+        let mut segments = vec![segment(0.0, 4.0), segment(4.0, 8.0), segment(9.5, 10.0), segment(10.8, 11.5)];
+        assign_speakers(&mut segments, &turns);
+        let assigned: Vec<_> = segments.iter().map(|segment| segment.speaker).collect();
+        // Mostly the second speaker, then the nearest turns in the gap
+        assert_eq!(assigned, vec![Some(1), Some(2), Some(2), Some(1)]);
+
+        // Without turns nothing changes
+        let mut segments = vec![segment(0.0, 1.0)];
+        assign_speakers(&mut segments, &[]);
+        assert_eq!(segments[0].speaker, None);
+    }
+}
@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use voicebot::eta::eta::SpeedEstimator;
+
+    #[test]
+    fn test_falls_back_to_configured_ratio() {
+        let estimator = SpeedEstimator::new(10.0, 0.5);
+        let key = SpeedEstimator::key("ggml-base.en", 4);
+
+        assert!(estimator.stats(&key).is_none());
+        assert_eq!(estimator.estimate(&key, 100.0, 1), 10.0);
+    }
+
+    #[test]
+    fn test_learns_rolling_speed() {
+        let mut estimator = SpeedEstimator::new(10.0, 0.5);
+        let key = SpeedEstimator::key("ggml-base.en", 4);
+
+        // 100 seconds of audio in 50 seconds: 2x real time
+        estimator.record(&key, 100.0, 50.0, 1).unwrap();
+        assert_eq!(estimator.speed(&key), 2.0);
+
+        // 100 seconds of audio in 25 seconds: 4x, averaged with the previous 2x
+        estimator.record(&key, 100.0, 25.0, 1).unwrap();
+        assert_eq!(estimator.speed(&key), 3.0);
+        assert_eq!(estimator.stats(&key).unwrap().samples, 2);
+
+        // Other models and thread counts are tracked separately
+        assert_eq!(estimator.speed(&SpeedEstimator::key("ggml-base.en", 8)), 10.0);
+    }
+
+    #[test]
+    fn test_accounts_for_queue_load() {
+        let mut estimator = SpeedEstimator::new(10.0, 1.0);
+        let key = SpeedEstimator::key("ggml-base.en", 4);
+
+        // Measured while sharing the machine with another job
+        estimator.record(&key, 100.0, 50.0, 2).unwrap();
+        assert_eq!(estimator.speed(&key), 4.0);
+
+        assert_eq!(estimator.estimate(&key, 100.0, 1), 25.0);
+        assert_eq!(estimator.estimate(&key, 100.0, 3), 75.0);
+    }
+
+    #[test]
+    fn test_history_is_persisted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("eta.json");
+        let key = SpeedEstimator::key("ggml-base.en", 4);
+
+        {
+            let mut estimator = SpeedEstimator::load(path.clone(), 10.0, 0.5).unwrap();
+            estimator.record(&key, 60.0, 20.0, 1).unwrap();
+        }
+
+        let estimator = SpeedEstimator::load(path, 10.0, 0.5).unwrap();
+        assert_eq!(estimator.speed(&key), 3.0);
+    }
+}
@@ -0,0 +1,214 @@
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use voicebot::config::config::{ParagraphConfig, ParagraphMode};
+    use voicebot::export::export::build_archives;
+    use voicebot::speech_to_text::speech_to_text::Segment;
+    use voicebot::storage::storage::StoredTranscript;
+    use voicebot::subtitles::subtitles::{
+        srt_timestamp, to_paragraphs, to_srt, to_timestamped_text, with_speaker_labels,
+    };
+    use zip::ZipArchive;
+
+    fn transcript(id: i64, text: &str, segments: Option<Vec<Segment>>) -> StoredTranscript {
+        StoredTranscript {
+            id,
+            chat_id: 100,
+            chat_username: None,
+            sender_id: Some(1),
+            sender_name: Some("Alice".to_string()),
+            message_id: id as i32,
+            created_at: 1_700_000_000 + id,
+            duration: 3.0,
+            language: Some("en".to_string()),
+            model: "ggml-base.en".to_string(),
+            text: text.to_string(),
+            segments,
+        }
+    }
+
+    fn speaker(number: u32) -> String {
+        format!("Speaker {}", number)
+    }
+
+    /// The default layout in `mode`.
+    fn layout(mode: ParagraphMode) -> ParagraphConfig {
+        ParagraphConfig::default().with_mode(mode)
+    }
+
+    fn file_names(archive: &[u8]) -> Vec<String> {
+        let archive = ZipArchive::new(Cursor::new(archive)).expect("Invalid zip archive");
+        let mut names: Vec<String> = archive.file_names().map(|n| n.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_srt_formatting() {
+        assert_eq!(srt_timestamp(3723.456), "01:02:03,456");
+
+        let segments = vec![
+            Segment { start: 0.0, end: 1.5, text: " Hello".to_string(), speaker: None },
+            Segment { start: 1.5, end: 62.0, text: "world ".to_string(), speaker: None },
+        ];
+        assert_eq!(
+            to_srt(&segments),
+            "1\n00:00:00,000 --> 00:00:01,500\nHello\n\n2\n00:00:01,500 --> 00:01:02,000\nworld\n\n"
+        );
+    }
+
+    #[test]
+    fn test_timestamped_text() {
+        let segments = vec![
+            Segment { start: 5.2, end: 7.0, text: " First".to_string(), speaker: None },
+            Segment { start: 3725.0, end: 3730.0, text: "Later".to_string(), speaker: None },
+        ];
+        assert_eq!(to_timestamped_text(&segments), "[00:05] First\n[1:02:05] Later");
+    }
+
+    #[test]
+    fn test_speaker_labels() {
+        let segment = |start: f64, text: &str, speaker: Option<u32>| Segment {
+            start,
+            end: start + 1.0,
+            text: text.to_string(),
+            speaker,
+        };
+        let segments = vec![
+            segment(0.0, " Hi there.", Some(1)),
+            segment(1.0, "How are you?", Some(1)),
+            segment(2.0, " Fine.", Some(2)),
+            segment(3.0, "Good.", Some(1)),
+        ];
+        assert_eq!(
+            to_paragraphs(&segments, &layout(ParagraphMode::Off), speaker),
+            "Speaker 1: Hi there. How are you?\n\nSpeaker 2: Fine.\n\nSpeaker 1: Good."
+        );
+        assert_eq!(
+            to_srt(&with_speaker_labels(&segments[2..3], speaker)),
+            "1\n00:00:02,000 --> 00:00:03,000\nSpeaker 2: Fine.\n\n"
+        );
+
+        // Segments without a speaker are left as they are
+        let unknown = vec![segment(0.0, "Hello", None)];
+        assert_eq!(with_speaker_labels(&unknown, speaker), unknown);
+        assert_eq!(to_paragraphs(&unknown, &layout(ParagraphMode::Off), speaker), "Hello");
+    }
+
+    #[test]
+    fn test_paragraphs_at_pauses() {
+        let segment = |start: f64, end: f64, text: &str| Segment { start, end, text: text.to_string(), speaker: None };
+        let segments = vec![
+            segment(0.0, 2.0, " First thought,"),
+            segment(2.5, 4.0, " still going."),
+            segment(6.0, 8.0, " A new one."),
+            segment(8.0, 8.0, " "),
+            segment(75.0, 78.0, " Much later."),
+        ];
+        assert_eq!(
+            to_paragraphs(&segments, &layout(ParagraphMode::Off), speaker),
+            "First thought, still going. A new one. Much later."
+        );
+        assert_eq!(
+            to_paragraphs(&segments, &layout(ParagraphMode::Pauses), speaker),
+            "First thought, still going.\n\nA new one.\n\nMuch later."
+        );
+        assert_eq!(
+            to_paragraphs(&segments, &layout(ParagraphMode::Timestamps), speaker),
+            "[00:00] First thought, still going.\n\n[00:06] A new one.\n\n[01:15] Much later."
+        );
+        assert_eq!(to_paragraphs(&[], &layout(ParagraphMode::Timestamps), speaker), "");
+    }
+
+    #[test]
+    fn test_long_paragraphs_end_with_a_sentence() {
+        // Ten seconds per segment without pauses
+        let texts = [" One", " two.", " Three", " four", " five.", " Six", " seven", " eight."];
+        let segments: Vec<Segment> = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Segment {
+                start: i as f64 * 10.0,
+                end: (i + 1) as f64 * 10.0,
+                text: text.to_string(),
+                speaker: None,
+            })
+            .collect();
+        let layout = ParagraphConfig { max_paragraph_secs: 25.0, ..layout(ParagraphMode::Pauses) };
+        assert_eq!(to_paragraphs(&segments, &layout, speaker), "One two. Three four five.\n\nSix seven eight.");
+    }
+
+    #[test]
+    fn test_paragraphs_of_speakers() {
+        let segment = |start: f64, text: &str, speaker: u32| Segment {
+            start,
+            end: start + 1.0,
+            text: text.to_string(),
+            speaker: Some(speaker),
+        };
+        let segments = vec![
+            segment(0.0, " Hi.", 1),
+            segment(5.0, " Anyone there?", 1),
+            segment(6.0, " Yes.", 2),
+        ];
+        // Only the first paragraph of a turn is labelled
+        assert_eq!(
+            to_paragraphs(&segments, &layout(ParagraphMode::Timestamps), speaker),
+            "[00:00] Speaker 1: Hi.\n\n[00:05] Anyone there?\n\n[00:06] Speaker 2: Yes."
+        );
+    }
+
+    #[test]
+    fn test_archive_contents() {
+        let segments = vec![
+            Segment { start: 0.0, end: 1.0, text: " Hi.".to_string(), speaker: None },
+            Segment { start: 4.0, end: 5.0, text: " Bye.".to_string(), speaker: None },
+        ];
+        let transcripts = vec![transcript(1, "Hi. Bye.", Some(segments)), transcript(2, "No timestamps", None)];
+
+        let archives = build_archives(&transcripts, 1024 * 1024, &layout(ParagraphMode::Pauses), &speaker).unwrap();
+        assert_eq!(archives.len(), 1);
+
+        let names = file_names(&archives[0]);
+        assert_eq!(names.len(), 5, "Unexpected files: {:?}", names);
+        assert_eq!(names.iter().filter(|n| n.ends_with(".srt")).count(), 1);
+
+        let mut archive = ZipArchive::new(Cursor::new(&archives[0])).unwrap();
+        let txt_name = names.iter().find(|n| n.ends_with("_2.txt")).unwrap().clone();
+        let mut text = String::new();
+        archive.by_name(&txt_name).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "No timestamps");
+
+        // Text with timestamps is laid out in paragraphs
+        let txt_name = names.iter().find(|n| n.ends_with("_1.txt")).unwrap().clone();
+        let mut text = String::new();
+        archive.by_name(&txt_name).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "Hi.\n\nBye.");
+
+        let json_name = names.iter().find(|n| n.ends_with("_2.json")).unwrap().clone();
+        let mut json = String::new();
+        archive.by_name(&json_name).unwrap().read_to_string(&mut json).unwrap();
+        let metadata: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(metadata["message_id"], 2);
+        assert_eq!(metadata["language"], "en");
+    }
+
+    #[test]
+    fn test_large_exports_are_split() {
+        let text = "word ".repeat(1000);
+        let transcripts: Vec<StoredTranscript> = (0..10).map(|id| transcript(id, &text, None)).collect();
+
+        let archives = build_archives(&transcripts, 12 * 1024, &ParagraphConfig::default(), &speaker).unwrap();
+        assert!(archives.len() > 1);
+        assert!(archives.iter().all(|a| a.len() <= 12 * 1024));
+
+        // Every transcript ends up in exactly one archive
+        let total: usize = archives.iter().map(|a| file_names(a).len()).sum();
+        assert_eq!(total, 20);
+    }
+
+    #[test]
+    fn test_empty_export() {
+        assert!(build_archives(&[], 1024, &ParagraphConfig::default(), &speaker).unwrap().is_empty());
+    }
+}
@@ -2,8 +2,21 @@
 mod tests {
     use std::fs;
     // use voicebot::ffmpeg_converter::audio_conversion::{voicebot::audio_conversion::audio_conversion::AudioConverter, FFMpegAudioConverter};
-    use voicebot::ffmpeg_converter::audio_conversion::FFMpegAudioConverter;
-    use voicebot::audio_conversion::audio_conversion::AudioConverter;
+    use voicebot::ffmpeg_converter::audio_conversion::{input_format, FFMpegAudioConverter};
+    use voicebot::audio_conversion::audio_conversion::{
+        convert_wav_to_samples, AudioConverter, ConversionError, SampleFormat, TargetSpec,
+    };
+    use tokio::io::AsyncWriteExt;
+    use voicebot::config::config::FFmpegConfig;
+    use voicebot::speech_to_text::speech_to_text::WHISPER_INPUT;
+
+    /// The format golden_ffmpeg.wav was made in.
+    const GOLDEN_SPEC: TargetSpec = TargetSpec {
+        sample_rate: 16000,
+        channels: 1,
+        sample_format: SampleFormat::I16,
+        max_duration_secs: None,
+    };
 
     #[test]
     fn test_mp3_to_wav_conversion() {
@@ -12,10 +25,10 @@ mod tests {
         let input_data = fs::read(test_file_path).expect("Failed to read test MP3 file");
 
         // Create an instance of FFMpegAudioConverter
-        let converter = FFMpegAudioConverter;
+        let converter = FFMpegAudioConverter::new(&FFmpegConfig::default());
 
         // Convert the MP3 file to WAV format
-        let result = converter.convert_audio_to_wav(&input_data);
+        let result = converter.convert_audio_to_wav(&input_data, &GOLDEN_SPEC);
         let wav_data = result.expect("Audio conversion failed");
 
         assert!(!wav_data.is_empty(), "Output WAV data is empty");
@@ -29,4 +42,209 @@ mod tests {
             .expect("Failed to read golden WAV file");
         assert_eq!(wav_data, golden_data);
     }
+
+    #[tokio::test]
+    async fn test_mp3_stream_decoding() {
+        let input_data = fs::read("test_assets/test.mp3").expect("Failed to read test MP3 file");
+        let converter = FFMpegAudioConverter::new(&FFmpegConfig::default());
+
+        let audio = converter.decode(&input_data, &WHISPER_INPUT).await.expect("Audio decoding failed");
+
+        // Same audio as the WAV conversion, up to its 16-bit quantisation and rounding
+        let golden = convert_wav_to_samples(&fs::read("test_assets/golden_ffmpeg.wav").unwrap()).unwrap();
+        assert_eq!(audio.samples.len(), golden.samples.len());
+        assert!((audio.duration - golden.duration).abs() < 1e-6);
+        let max_error = audio
+            .samples
+            .iter()
+            .zip(&golden.samples)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(max_error <= 2.0 / 32768.0, "samples differ by {}", max_error);
+    }
+
+    #[tokio::test]
+    async fn test_broken_files() {
+        let converter = FFMpegAudioConverter::new(&FFmpegConfig::default());
+        let full = converter.decode(&fs::read("test_assets/test.mp3").unwrap(), &WHISPER_INPUT).await.unwrap();
+
+        // Decodes up to where the file ends
+        let truncated = fs::read("test_assets/broken/truncated.mp3").unwrap();
+        let audio = converter.decode(&truncated, &WHISPER_INPUT).await.expect("Truncated file not decoded");
+        assert!(audio.duration > 0.0 && audio.duration < full.duration);
+
+        // Random bytes, and playlists pointing at local files, which must not be read
+        for name in ["garbage.bin", "playlist.m3u8", "concat.ffconcat"] {
+            let path = format!("test_assets/broken/{}", name);
+            let input = fs::read(&path).unwrap();
+            assert!(
+                matches!(converter.decode(&input, &WHISPER_INPUT).await, Err(ConversionError::FFmpeg { .. })),
+                "{} decoded from a pipe",
+                name
+            );
+            assert!(
+                matches!(converter.decode_file(&path, &WHISPER_INPUT).await, Err(ConversionError::FFmpeg { .. })),
+                "{} decoded from a file",
+                name
+            );
+            assert!(converter.convert_file_to_wav(&path, &WHISPER_INPUT).is_err(), "{} converted", name);
+        }
+    }
+
+    /// A stand-in for ffmpeg running `script`, for tests of the process handling.
+    #[cfg(unix)]
+    fn fake_ffmpeg(script: &str, config: FFmpegConfig) -> (tempfile::TempDir, FFMpegAudioConverter) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("ffmpeg");
+        fs::write(&binary, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+        let converter = FFMpegAudioConverter::new(&FFmpegConfig { binary, ..config });
+        (dir, converter)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stream_decoding_reads_raw_samples() {
+        // Passes the input through as if it were decoded samples
+        let (_dir, converter) = fake_ffmpeg("exec cat", FFmpegConfig::default());
+        let samples: Vec<f32> = (0..64_000).map(|i| (i as f32 / 64_000.0) - 0.5).collect();
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+        let audio = converter.decode(&bytes, &WHISPER_INPUT).await.unwrap();
+        assert_eq!(audio.samples, samples);
+        assert_eq!(audio.duration, 4.0);
+
+        // 16-bit stereo
+        let stereo = TargetSpec { sample_rate: 8000, channels: 2, ..GOLDEN_SPEC };
+        let bytes: Vec<u8> = [0i16, 16384, -32768, 32767].iter().cycle().take(32_000).flat_map(|s| s.to_le_bytes()).collect();
+        let audio = converter.decode(&bytes, &stereo).await.unwrap();
+        assert_eq!(audio.samples[..4], [0.0, 0.5, -1.0, 32767.0 / 32768.0]);
+        assert_eq!(audio.duration, 2.0);
+        assert_eq!((audio.sample_rate, audio.channels), (8000, 2));
+    }
+
+    #[test]
+    fn test_input_format_from_stderr() {
+        let stderr = "Input #0, ogg, from 'pipe:0':\n  Duration: N/A, start: 0.000000, bitrate: N/A\n  \
+            Stream #0:0: Audio: opus, 48000 Hz, mono, fltp\nStream mapping:\n  Stream #0:0 -> #0:0 (opus (native) -> pcm_f32le (native))\n\
+            Output #0, f32le, to 'pipe:1':\n  Stream #0:0: Audio: pcm_f32le, 16000 Hz, mono, flt, 512 kb/s\n";
+        assert_eq!(input_format(stderr), (Some("ogg".to_string()), Some("opus".to_string())));
+
+        let stderr = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from '/tmp/a.m4a':\n  \
+            Stream #0:0[0x1](und): Audio: aac (LC) (mp4a / 0x6134706D), 44100 Hz, stereo, fltp, 128 kb/s (default)\n";
+        assert_eq!(
+            input_format(stderr),
+            (Some("mov,mp4,m4a,3gp,3g2,mj2".to_string()), Some("aac".to_string()))
+        );
+
+        assert_eq!(input_format(""), (None, None));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stream_decoding_errors() {
+        let (_dir, converter) = fake_ffmpeg(
+            "echo 'pipe:0: Invalid data found when processing input' >&2; exit 1",
+            FFmpegConfig::default(),
+        );
+        match converter.decode(&[0; 1_000_000], &WHISPER_INPUT).await {
+            Err(ConversionError::FFmpeg { code: Some(1), stderr }) => assert!(stderr.contains("Invalid data")),
+            other => panic!("unexpected result {:?}", other.map(|audio| audio.duration)),
+        }
+
+        let config = FFmpegConfig { binary: "/nonexistent/ffmpeg".into(), ..FFmpegConfig::default() };
+        let converter = FFMpegAudioConverter::new(&config);
+        assert!(matches!(converter.decode(b"data", &WHISPER_INPUT).await, Err(ConversionError::Spawn { .. })));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hanging_ffmpeg_is_killed() {
+        let config = FFmpegConfig { timeout_secs: 1, ..FFmpegConfig::default() };
+        let (_dir, converter) = fake_ffmpeg("exec sleep 30", config);
+
+        let started = std::time::Instant::now();
+        assert!(matches!(converter.decode(b"data", &WHISPER_INPUT).await, Err(ConversionError::Timeout(_))));
+        assert!(matches!(converter.convert_file_to_wav("test_assets/test.mp3", &WHISPER_INPUT), Err(ConversionError::Timeout(_))));
+        assert!(started.elapsed().as_secs() < 10);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_timeout_counts_from_start() {
+        let config = FFmpegConfig { timeout_secs: 1, ..FFmpegConfig::default() };
+        let (_dir, converter) = fake_ffmpeg("exec sleep 30", config);
+
+        // ffmpeg not reading its input doesn't leave the writer waiting
+        let started = std::time::Instant::now();
+        assert!(matches!(converter.decode(&[0; 1_000_000], &WHISPER_INPUT).await, Err(ConversionError::Timeout(_))));
+        assert!(started.elapsed().as_secs() < 10);
+
+        // Time spent feeding the input is taken from the timeout
+        let mut decoder = converter.decode_stream(&WHISPER_INPUT).unwrap();
+        decoder.write_all(b"data").await.unwrap();
+        tokio::time::sleep_until(decoder.deadline()).await;
+        let started = std::time::Instant::now();
+        assert!(matches!(decoder.finish().await, Err(ConversionError::Timeout(_))));
+        assert!(started.elapsed().as_millis() < 500);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stalled_stream_is_not_retried() {
+        // Every start of the fake ffmpeg is counted
+        let runs = tempfile::NamedTempFile::new().unwrap();
+        let config = FFmpegConfig { timeout_secs: 1, ..FFmpegConfig::default() };
+        let (_dir, converter) = fake_ffmpeg(&format!("echo run >> {}; exec sleep 30", runs.path().display()), config);
+
+        let started = std::time::Instant::now();
+        let mut decoder = converter.decode_stream(&WHISPER_INPUT).unwrap();
+        decoder.write_all(b"data").await.unwrap();
+        let result = converter.finish_stream(decoder, b"data", &WHISPER_INPUT).await;
+        assert!(matches!(result, Err(ConversionError::Timeout(_))));
+        assert!(started.elapsed().as_millis() < 1900);
+        assert_eq!(fs::read_to_string(runs.path()).unwrap().lines().count(), 1);
+
+        // Input ffmpeg can't read from the pipe is tried once more from a file
+        fs::write(runs.path(), "").unwrap();
+        let (_dir, converter) = fake_ffmpeg(&format!("echo run >> {}; exit 1", runs.path().display()), FFmpegConfig::default());
+        let mut decoder = converter.decode_stream(&WHISPER_INPUT).unwrap();
+        let _ = decoder.write_all(b"data").await;
+        let result = converter.finish_stream(decoder, b"data", &WHISPER_INPUT).await;
+        assert!(matches!(result, Err(ConversionError::FFmpeg { .. })));
+        assert_eq!(fs::read_to_string(runs.path()).unwrap().lines().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sandbox_options() {
+        let config = FFmpegConfig {
+            max_memory_mb: 512,
+            max_cpu_secs: 30,
+            allowed_formats: vec!["ogg".to_string(), "mp3".to_string()],
+            ..FFmpegConfig::default()
+        };
+        let (_dir, converter) = fake_ffmpeg(
+            "echo \"args: $*\" >&2; echo \"memory: $(ulimit -v) cpu: $(ulimit -t)\" >&2; exit 1",
+            config,
+        );
+
+        let stderr = match converter.decode(b"data", &WHISPER_INPUT).await {
+            Err(ConversionError::FFmpeg { stderr, .. }) => stderr,
+            other => panic!("unexpected result {:?}", other.map(|audio| audio.duration)),
+        };
+        assert!(stderr.contains("-protocol_whitelist pipe -format_whitelist ogg,mp3 -i pipe:0"), "{}", stderr);
+        // Output as the recogniser expects it
+        assert!(stderr.contains("-ar 16000 -ac 1 -acodec pcm_f32le -f f32le pipe:1"), "{}", stderr);
+        assert!(stderr.contains("memory: 524288 cpu: 30"), "{}", stderr);
+
+        match converter.decode_file("test_assets/test.mp3", &WHISPER_INPUT).await {
+            Err(ConversionError::FFmpeg { stderr, .. }) => {
+                assert!(stderr.contains("-protocol_whitelist file -format_whitelist ogg,mp3"), "{}", stderr)
+            }
+            other => panic!("unexpected result {:?}", other.map(|audio| audio.duration)),
+        }
+    }
 }
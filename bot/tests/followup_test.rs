@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::config::config::Speakers;
+    use voicebot::followup::followup::{CachedRecording, FollowUp, RecordingCache};
+    use voicebot::speech_to_text::speech_to_text::{Segment, Transcript};
+    use voicebot::summary::summary::{split_sentences, summarize};
+
+    fn recording(samples: usize, text: &str) -> CachedRecording {
+        CachedRecording {
+            file_unique_id: text.to_string(),
+            duration: samples as f64 / 16000.0,
+            offset: 0.0,
+            samples: vec![0.0; samples].into(),
+            preprocessing: Vec::new(),
+            speakers: Speakers::Off,
+            transcript: Transcript {
+                segments: vec![Segment { start: 0.0, end: 1.0, text: text.to_string(), speaker: None }],
+                language: Some("en".to_string()),
+            },
+            model: "ggml-base.en".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_callback_data_round_trip() {
+        for action in FollowUp::ALL {
+            let data = action.callback_data(123456);
+            // Telegram limits callback data to 64 bytes
+            assert!(data.len() <= 64);
+            assert_eq!(FollowUp::parse(&data), Some((action, 123456)));
+        }
+        assert_eq!(FollowUp::parse("fu:nope:1"), None);
+        assert_eq!(FollowUp::parse("fu:srt:x"), None);
+        assert_eq!(FollowUp::parse("other:srt:1"), None);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        // Room for three recordings of 100 samples
+        let mut cache = RecordingCache::new(3 * 100 * 4);
+        cache.insert(1, 1, recording(100, "one"));
+        cache.insert(1, 2, recording(100, "two"));
+        cache.insert(1, 3, recording(100, "three"));
+        assert_eq!(cache.len(), 3);
+
+        // Using the first one makes the second the oldest
+        assert!(cache.get(1, 1).is_some());
+        cache.insert(1, 4, recording(100, "four"));
+        assert!(cache.get(1, 2).is_none());
+        assert!(cache.get(1, 1).is_some());
+        assert_eq!(cache.bytes(), 3 * 100 * 4);
+
+        // Too big to be cached at all
+        cache.insert(2, 1, recording(1000, "huge"));
+        assert!(cache.get(2, 1).is_none());
+        assert_eq!(cache.len(), 3);
+
+        let mut better = recording(0, "better").transcript;
+        better.language = None;
+        cache.update_transcript(1, 1, better, "ggml-medium");
+        let updated = cache.get(1, 1).unwrap();
+        assert_eq!(updated.transcript.text(), "better");
+        assert_eq!(updated.model, "ggml-medium");
+    }
+
+    #[test]
+    fn test_sentence_splitting() {
+        assert_eq!(
+            split_sentences("Hello there. It is 3.5 degrees! Is it? Yes"),
+            vec!["Hello there.", "It is 3.5 degrees!", "Is it?", "Yes"]
+        );
+        assert_eq!(split_sentences("Привет. Как дела?"), vec!["Привет.", "Как дела?"]);
+    }
+
+    #[test]
+    fn test_summary_keeps_central_sentences_in_order() {
+        let text = "The budget meeting moved to Friday. Lunch was good. \
+                    The budget needs approval before the meeting. It rained.";
+        assert_eq!(
+            summarize(text, 2),
+            "The budget meeting moved to Friday. The budget needs approval before the meeting."
+        );
+        // Short texts are returned as they are
+        assert_eq!(summarize("Just one sentence.", 3), "Just one sentence.");
+    }
+}
@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::config::config::{HallucinationConfig, VadConfig};
+    use voicebot::hallucination::hallucination::{collapse_loops, no_speech_share, HallucinationFilter};
+    use voicebot::speech_to_text::speech_to_text::Segment;
+    use voicebot::vad::vad::SpeechRegion;
+
+    fn segment(start: f64, end: f64, text: &str) -> Segment {
+        Segment { start, end, text: text.to_string(), speaker: None }
+    }
+
+    fn texts(segments: &[Segment]) -> Vec<&str> {
+        segments.iter().map(|segment| segment.text.as_str()).collect()
+    }
+
+    fn filter() -> HallucinationFilter {
+        HallucinationFilter::new(&HallucinationConfig::default(), &VadConfig::default())
+    }
+
+    /// Speech for the whole of `seconds`.
+    fn all_speech(seconds: f64) -> Vec<SpeechRegion> {
+        vec![SpeechRegion { start: 0.0, end: seconds }]
+    }
+
+    #[test]
+    fn test_no_speech_share() {
+        let regions = [SpeechRegion { start: 1.0, end: 3.0 }, SpeechRegion { start: 4.0, end: 5.0 }];
+        assert_eq!(no_speech_share(&segment(1.0, 3.0, ""), &regions), 0.0);
+        assert_eq!(no_speech_share(&segment(5.0, 9.0, ""), &regions), 1.0);
+        assert!((no_speech_share(&segment(2.0, 6.0, ""), &regions) - 0.5).abs() < 1e-9);
+        assert_eq!(no_speech_share(&segment(2.0, 2.0, ""), &regions), 0.0);
+        assert_eq!(no_speech_share(&segment(3.5, 3.5, ""), &regions), 1.0);
+    }
+
+    #[test]
+    fn test_segments_without_speech_are_dropped() {
+        // Speech ends at 6 s, whisper goes on about the silence after it
+        let regions = [SpeechRegion { start: 0.0, end: 6.0 }];
+        let segments = vec![
+            segment(0.0, 3.0, " Hello there."),
+            segment(3.0, 6.5, " How are you?"),
+            segment(6.5, 10.0, " I'll see you next time."),
+        ];
+        let cleaned = filter().clean(segments, &regions);
+        assert_eq!(texts(&cleaned), vec![" Hello there.", " How are you?"]);
+    }
+
+    #[test]
+    fn test_silence_gives_nothing() {
+        let segments = vec![segment(0.0, 2.0, " Thank you."), segment(2.0, 4.0, " Bye.")];
+        let silence = vec![0.0; 4 * 16000];
+        assert!(filter().apply(segments, &silence, 16000).is_empty());
+    }
+
+    #[test]
+    fn test_blocklist() {
+        let segments = vec![
+            segment(0.0, 4.0, " So that's the plan."),
+            segment(4.0, 6.0, " Thanks for watching!"),
+            segment(6.0, 8.0, " Продолжение следует..."),
+            segment(8.0, 10.0, " Thanks for watching the kids yesterday."),
+        ];
+        let cleaned = filter().clean(segments, &all_speech(10.0));
+        assert_eq!(texts(&cleaned), vec![" So that's the plan.", " Thanks for watching the kids yesterday."]);
+
+        let config = HallucinationConfig { blocklist: Vec::new(), ..Default::default() };
+        let filter = HallucinationFilter::new(&config, &VadConfig::default());
+        let cleaned = filter.clean(vec![segment(0.0, 2.0, " Thanks for watching!")], &all_speech(2.0));
+        assert_eq!(cleaned.len(), 1);
+    }
+
+    #[test]
+    fn test_loop_within_a_segment() {
+        let segments = vec![segment(0.0, 30.0, " I don't know. I don't know. I don't know. I don't know. I don't know.")];
+        let collapsed = collapse_loops(segments, 3, 8);
+        assert_eq!(texts(&collapsed), vec![" I don't know."]);
+    }
+
+    #[test]
+    fn test_loop_across_segments() {
+        let segments = vec![
+            segment(0.0, 2.0, " We should go now."),
+            segment(2.0, 4.0, " Thank you."),
+            segment(4.0, 6.0, " Thank you."),
+            segment(6.0, 8.0, " Thank you."),
+            segment(8.0, 10.0, " Thank you. Okay, see you."),
+        ];
+        let collapsed = collapse_loops(segments, 3, 8);
+        assert_eq!(texts(&collapsed), vec![" We should go now.", " Thank you.", " Okay, see you."]);
+        assert_eq!(collapsed[2].start, 8.0);
+    }
+
+    #[test]
+    fn test_loops_are_left_for_the_whole_transcript() {
+        // Two chunks of a long recording, the loop runs from one into the other
+        let first = vec![segment(0.0, 2.0, " Let's start."), segment(2.0, 4.0, " Thank you. Thank you.")];
+        let second = vec![segment(0.0, 2.0, " Thank you. Thank you.")];
+        let speech = vec![0.5; 4 * 16000];
+        let first = filter().drop_made_up(first, &speech, 16000);
+        let second = filter().drop_made_up(second, &speech, 16000);
+        assert_eq!(texts(&first), vec![" Let's start.", " Thank you. Thank you."]);
+        assert_eq!(texts(&second), vec![" Thank you. Thank you."]);
+
+        let stitched: Vec<Segment> = first.into_iter().chain(second).collect();
+        assert_eq!(texts(&filter().collapse(stitched)), vec![" Let's start.", " Thank you."]);
+    }
+
+    #[test]
+    fn test_ordinary_repetition_is_kept() {
+        let text = " No, no, no. It was very, very good. Go, go!";
+        let collapsed = collapse_loops(vec![segment(0.0, 5.0, text)], 3, 8);
+        assert_eq!(texts(&collapsed), vec![text]);
+
+        // Twice is not a loop
+        let text = " I said it twice. I said it twice.";
+        let collapsed = collapse_loops(vec![segment(0.0, 5.0, text)], 3, 8);
+        assert_eq!(texts(&collapsed), vec![text]);
+
+        // A single word needs more repeats to count as a loop
+        let collapsed = collapse_loops(vec![segment(0.0, 5.0, " la la la la la la")], 3, 8);
+        assert_eq!(texts(&collapsed), vec![" la"]);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let config = HallucinationConfig { enabled: false, ..Default::default() };
+        let filter = HallucinationFilter::new(&config, &VadConfig::default());
+        let segments = vec![segment(0.0, 2.0, " Thank you for watching."), segment(2.0, 4.0, " Yes yes yes yes yes")];
+        assert_eq!(filter.clean(segments.clone(), &[]), segments);
+    }
+}
@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use voicebot::i18n::i18n::{keys, Language};
+
+    /// Names of the `{placeholders}` used in a template.
+    fn placeholders(template: &str) -> BTreeSet<&str> {
+        template
+            .split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+            .collect()
+    }
+
+    #[test]
+    fn test_catalogs_are_complete() {
+        let english = keys(Language::English);
+        assert!(!english.is_empty());
+
+        for lang in Language::ALL {
+            assert_eq!(keys(lang), english, "Keys of {} differ from English", lang.code());
+            for key in &english {
+                assert_eq!(
+                    placeholders(lang.tr(key)),
+                    placeholders(Language::English.tr(key)),
+                    "Placeholders of {:?} in {} differ from English",
+                    key,
+                    lang.code()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_language_resolution() {
+        assert_eq!(Language::from_code("ru"), Some(Language::Russian));
+        assert_eq!(Language::from_code("en-US"), Some(Language::English));
+        assert_eq!(Language::from_code("de"), None);
+
+        // The chat setting wins over the user's app language
+        assert_eq!(Language::resolve(Some("en"), Some("ru")), Language::English);
+        assert_eq!(Language::resolve(None, Some("ru")), Language::Russian);
+        assert_eq!(Language::resolve(None, Some("de")), Language::English);
+        assert_eq!(Language::resolve(None, None), Language::English);
+    }
+
+    #[test]
+    fn test_formatting() {
+        assert_eq!(
+            Language::English.format("history.no_page", &[("page", &3)]),
+            "There is no page 3."
+        );
+        assert_eq!(
+            Language::Russian.format("duration.minutes_seconds", &[("minutes", &1), ("seconds", &5)]),
+            "1 мин 5 с"
+        );
+        // Placeholders in the values are not filled in, whatever the order
+        assert_eq!(
+            Language::English.format("vocab.too_long", &[("term", &"{max} {count}"), ("max", &50)]),
+            "\"{max} {count}\" is too long, terms can have at most 50 characters."
+        );
+        assert_eq!(
+            Language::English.format("cache.purged", &[("count", &"{count}")]),
+            "Transcript cache purged, {count} entries removed."
+        );
+        // Unknown keys show up as themselves instead of failing
+        assert_eq!(Language::Russian.tr("no.such.key"), "no.such.key");
+    }
+}
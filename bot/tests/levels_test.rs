@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::audio_conversion::audio_conversion::AudioData;
+    use voicebot::levels::levels::{Levels, SILENCE_DB};
+
+    const RATE: u32 = 48000;
+
+    fn sine(freq: f64, amplitude: f64, secs: f64) -> Vec<f32> {
+        let n = (secs * RATE as f64) as usize;
+        (0..n).map(|i| (amplitude * (2.0 * std::f64::consts::PI * freq * i as f64 / RATE as f64).sin()) as f32).collect()
+    }
+
+    fn close(actual: f64, expected: f64, tolerance: f64) -> bool {
+        (actual - expected).abs() <= tolerance
+    }
+
+    #[test]
+    fn test_sine_levels() {
+        let levels = Levels::measure(&sine(1000.0, 0.5, 2.0), RATE, 1);
+        assert!(close(levels.peak_db, -6.02, 0.05), "peak {}", levels.peak_db);
+        // The RMS of a sine is 3 dB below its peak
+        assert!(close(levels.rms_db, -9.03, 0.05), "RMS {}", levels.rms_db);
+        assert_eq!(levels.clipping_ratio, 0.0);
+    }
+
+    #[test]
+    fn test_loudness_of_reference_tone() {
+        // BS.1770 is calibrated so that a 1 kHz sine at 0 dBFS in one channel
+        // measures -3.01 LUFS
+        let levels = Levels::measure(&sine(1000.0, 1.0, 5.0), RATE, 1);
+        assert!(close(levels.loudness_lufs, -3.01, 0.1), "loudness {}", levels.loudness_lufs);
+
+        // 20 dB quieter is 20 LU lower, at any sample rate
+        let quiet: Vec<f32> = sine(1000.0, 0.1, 5.0);
+        let levels = Levels::measure(&quiet, RATE, 1);
+        assert!(close(levels.loudness_lufs, -23.01, 0.1), "loudness {}", levels.loudness_lufs);
+    }
+
+    #[test]
+    fn test_loudness_gates_out_silence() {
+        // Pauses don't make speech quieter
+        let mut samples = sine(1000.0, 0.1, 3.0);
+        samples.extend(vec![0.0; RATE as usize * 10]);
+        let levels = Levels::measure(&samples, RATE, 1);
+        // Blocks overlapping the end of the tone still count, a little quieter
+        assert!(close(levels.loudness_lufs, -23.01, 0.5), "loudness {}", levels.loudness_lufs);
+        // Unlike the RMS level
+        assert!(levels.rms_db < -25.0);
+    }
+
+    #[test]
+    fn test_silence() {
+        for samples in [vec![], vec![0.0; RATE as usize]] {
+            let levels = Levels::measure(&samples, RATE, 1);
+            assert_eq!(levels.peak_db, SILENCE_DB);
+            assert_eq!(levels.rms_db, SILENCE_DB);
+            assert_eq!(levels.loudness_lufs, SILENCE_DB);
+            assert_eq!(levels.clipping_ratio, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_clipping_ratio() {
+        // A sine driven 6 dB into the limits and clamped
+        let samples: Vec<f32> = sine(440.0, 2.0, 1.0).into_iter().map(|s| s.clamp(-1.0, 1.0)).collect();
+        let levels = Levels::measure(&samples, RATE, 1);
+        assert_eq!(levels.peak_db, 0.0);
+        // |2 sin x| >= 1 for two thirds of the period
+        assert!(close(levels.clipping_ratio, 2.0 / 3.0, 0.01), "clipped {}", levels.clipping_ratio);
+    }
+
+    #[test]
+    fn test_audio_data_measures_interleaved_channels() {
+        let left = sine(1000.0, 0.5, 1.0);
+        let samples: Vec<f32> = left.iter().flat_map(|&s| [s, 0.0]).collect();
+        let audio = AudioData::new(samples, RATE, 2).with_source(Some("ogg".to_string()), Some("opus".to_string()));
+        assert_eq!(audio.duration, 1.0);
+        assert_eq!(audio.describe_format(), "ogg/opus, 48000 Hz, 2 ch");
+        assert!(close(audio.levels.peak_db, -6.02, 0.05));
+        // Channel powers are summed, so a silent channel adds nothing
+        let mono = Levels::measure(&left, RATE, 1);
+        assert!(close(audio.levels.loudness_lufs, mono.loudness_lufs, 0.1));
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::audio_conversion::audio_conversion::AudioData;
+    use voicebot::config::config::{PreprocessConfig, PreprocessStage};
+    use voicebot::levels::levels::Levels;
+    use voicebot::preprocess::preprocess::{
+        format_stages, parse_stages, Denoise, HighPass, Normalize, Pipeline, Processor,
+    };
+
+    const RATE: u32 = 16000;
+
+    fn sine(freq: f64, amplitude: f64, secs: f64) -> Vec<f32> {
+        let n = (secs * RATE as f64) as usize;
+        (0..n).map(|i| (amplitude * (2.0 * std::f64::consts::PI * freq * i as f64 / RATE as f64).sin()) as f32).collect()
+    }
+
+    /// Deterministic white-ish noise of the given amplitude.
+    fn noise(secs: f64, amplitude: f32) -> Vec<f32> {
+        let mut state: u32 = 12345;
+        (0..(secs * RATE as f64) as usize)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((state >> 16) as f32 / 32768.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn rms_db(samples: &[f32]) -> f64 {
+        Levels::measure(samples, RATE, 1).rms_db
+    }
+
+    #[test]
+    fn test_high_pass_removes_offset_and_rumble() {
+        let speech = sine(1000.0, 0.3, 2.0);
+        let rumble = sine(20.0, 0.3, 2.0);
+        let samples = speech.iter().zip(&rumble).map(|(s, r)| s + r + 0.2).collect();
+        let mut audio = AudioData::new(samples, RATE, 1);
+        HighPass { cutoff_hz: 80.0 }.process(&mut audio);
+
+        // Compared after the filter has settled
+        let settled = &audio.samples[RATE as usize / 2..];
+        let mean = settled.iter().map(|&s| s as f64).sum::<f64>() / settled.len() as f64;
+        assert!(mean.abs() < 0.005, "offset {}", mean);
+        // With the rumble left in, the level would be 3 dB higher
+        assert!((rms_db(settled) - rms_db(&speech)).abs() < 0.2, "{} dB", rms_db(settled));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalize = Normalize { target_lufs: -23.0, max_gain_db: 30.0, peak_limit_db: -1.0 };
+
+        let mut audio = AudioData::new(sine(1000.0, 0.01, 3.0), RATE, 1);
+        normalize.process(&mut audio);
+        let loudness = Levels::measure(&audio.samples, RATE, 1).loudness_lufs;
+        assert!((loudness - -23.0).abs() < 0.1, "loudness {}", loudness);
+
+        // Limited by the peaks
+        let mut spiky = sine(1000.0, 0.01, 3.0);
+        spiky[1000] = 0.5;
+        let mut audio = AudioData::new(spiky, RATE, 1);
+        normalize.process(&mut audio);
+        assert!((Levels::measure(&audio.samples, RATE, 1).peak_db - -1.0).abs() < 0.01);
+
+        // Limited by the maximum gain
+        let mut audio = AudioData::new(sine(1000.0, 0.001, 3.0), RATE, 1);
+        normalize.process(&mut audio);
+        let gain = audio.samples.iter().cloned().fold(0.0f32, f32::max) / 0.001;
+        assert!((20.0 * (gain as f64).log10() - 30.0).abs() < 0.1, "gain {}", gain);
+
+        // Silence stays silent
+        let mut audio = AudioData::new(vec![0.0; RATE as usize], RATE, 1);
+        normalize.process(&mut audio);
+        assert!(audio.samples.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_denoise_attenuates_noise_and_keeps_speech() {
+        let hiss = noise(4.0, 0.02);
+        let mut tone = vec![0.0; RATE as usize];
+        tone.extend(sine(440.0, 0.3, 2.0));
+        tone.resize(hiss.len(), 0.0);
+        let samples: Vec<f32> = hiss.iter().zip(&tone).map(|(n, t)| n + t).collect();
+
+        let mut audio = AudioData::new(samples, RATE, 1);
+        Denoise { reduction_db: 12.0, threshold: 1.5 }.process(&mut audio);
+
+        let pause = 3 * RATE as usize + RATE as usize / 4..4 * RATE as usize - RATE as usize / 4;
+        assert!(
+            rms_db(&audio.samples[pause.clone()]) < rms_db(&hiss[pause.clone()]) - 8.0,
+            "noise {} dB before, {} dB after",
+            rms_db(&hiss[pause.clone()]),
+            rms_db(&audio.samples[pause])
+        );
+        let speech = RATE as usize + RATE as usize / 4..3 * RATE as usize - RATE as usize / 4;
+        assert!((rms_db(&audio.samples[speech.clone()]) - rms_db(&tone[speech])).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_pipeline_runs_stages_in_order() {
+        let config = PreprocessConfig::default();
+        let stages = [PreprocessStage::Normalize, PreprocessStage::HighPass, PreprocessStage::Normalize];
+        let pipeline = Pipeline::from_stages(&stages, &config);
+        assert_eq!(pipeline.names(), vec!["high_pass", "normalize"]);
+        assert!(Pipeline::from_stages(&[], &config).is_empty());
+
+        let audio = pipeline.apply(AudioData::new(sine(1000.0, 0.01, 3.0), RATE, 1));
+        assert!((audio.levels.loudness_lufs - config.target_lufs).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_stage_settings() {
+        let stages = [PreprocessStage::Normalize, PreprocessStage::Denoise];
+        assert_eq!(format_stages(&stages), "denoise,normalize");
+        assert_eq!(parse_stages("denoise,normalize"), Some(vec![PreprocessStage::Denoise, PreprocessStage::Normalize]));
+        assert_eq!(
+            parse_stages(" normalize  high_pass "),
+            Some(vec![PreprocessStage::HighPass, PreprocessStage::Normalize])
+        );
+        assert_eq!(format_stages(&[]), "none");
+        assert_eq!(parse_stages("none"), Some(Vec::new()));
+        assert_eq!(parse_stages("louder"), None);
+        assert_eq!(parse_stages(""), None);
+    }
+}
@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::speech_to_text::speech_to_text::{RecognitionOptions, Segment, Transcript};
+    use voicebot::storage::storage::{CacheKey, NewTranscript, TranscriptStore, MATCH_END, MATCH_START};
+
+    fn transcript(chat_id: i64, message_id: i32, created_at: i64, text: &str) -> NewTranscript<'_> {
+        NewTranscript {
+            chat_id,
+            chat_username: None,
+            sender_id: Some(1),
+            sender_name: Some("Alice"),
+            message_id,
+            created_at,
+            duration: 12.5,
+            language: Some("en"),
+            model: "ggml-base.en",
+            text,
+            segments: None,
+        }
+    }
+
+    #[test]
+    fn test_insert_and_page_recent() {
+        let store = TranscriptStore::open_in_memory().unwrap();
+        for i in 0..7 {
+            store
+                .insert(&transcript(100, i, 1_700_000_000 + i as i64, &format!("note number {}", i)))
+                .unwrap();
+        }
+        store.insert(&transcript(200, 1, 1_700_000_000, "other chat")).unwrap();
+
+        assert_eq!(store.count(100).unwrap(), 7);
+
+        let first_page = store.recent(100, 5, 0).unwrap();
+        assert_eq!(first_page.len(), 5);
+        // Newest first
+        assert_eq!(first_page[0].message_id, 6);
+        assert_eq!(first_page[0].sender_name.as_deref(), Some("Alice"));
+        assert_eq!(first_page[0].language.as_deref(), Some("en"));
+
+        let second_page = store.recent(100, 5, 5).unwrap();
+        assert_eq!(second_page.len(), 2);
+        assert_eq!(second_page[1].message_id, 0);
+    }
+
+    #[test]
+    fn test_search_is_scoped_to_chat() {
+        let store = TranscriptStore::open_in_memory().unwrap();
+        store.insert(&transcript(100, 1, 1, "the release is planned for Friday")).unwrap();
+        store.insert(&transcript(100, 2, 2, "lunch at noon")).unwrap();
+        store.insert(&transcript(200, 3, 3, "release notes are ready")).unwrap();
+
+        let hits = store.search(100, "release", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].transcript.message_id, 1);
+        assert!(hits[0]
+            .snippet
+            .contains(&format!("{}release{}", MATCH_START, MATCH_END)));
+
+        // All words have to match
+        assert!(store.search(100, "release lunch", 10).unwrap().is_empty());
+        assert_eq!(store.search(100, "planned friday", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_search_treats_input_as_plain_words() {
+        let store = TranscriptStore::open_in_memory().unwrap();
+        store.insert(&transcript(100, 1, 1, "Проверка связи, раз два три")).unwrap();
+
+        // FTS syntax characters must not cause query errors
+        assert!(store.search(100, "\"unbalanced OR NEAR(", 10).unwrap().is_empty());
+        assert!(store.search(100, "   ", 10).unwrap().is_empty());
+        assert_eq!(store.search(100, "связи", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_segments_round_trip() {
+        let store = TranscriptStore::open_in_memory().unwrap();
+        let segments = vec![
+            Segment { start: 0.0, end: 1.5, text: "Hello".to_string(), speaker: None },
+            Segment { start: 1.5, end: 3.0, text: "world".to_string(), speaker: None },
+        ];
+        let mut new = transcript(100, 1, 1, "Hello world");
+        new.segments = Some(&segments);
+        let id = store.insert(&new).unwrap();
+
+        let stored = store.get(id).unwrap().expect("Transcript not found");
+        assert_eq!(stored.segments, Some(segments));
+        assert!(store.get(id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_chat_settings() {
+        let store = TranscriptStore::open_in_memory().unwrap();
+        assert_eq!(store.chat_setting(100, "language").unwrap(), None);
+
+        store.set_chat_setting(100, "language", Some("ru")).unwrap();
+        store.set_chat_setting(100, "language", Some("en")).unwrap();
+        assert_eq!(store.chat_setting(100, "language").unwrap().as_deref(), Some("en"));
+        assert_eq!(store.chat_setting(200, "language").unwrap(), None);
+
+        store.set_chat_setting(100, "language", None).unwrap();
+        assert_eq!(store.chat_setting(100, "language").unwrap(), None);
+    }
+
+    #[test]
+    fn test_transcript_cache() {
+        let store = TranscriptStore::open_in_memory().unwrap();
+        let plain = RecognitionOptions::default().cache_key();
+        let translated = RecognitionOptions { translate: true, ..Default::default() }.cache_key();
+        let with_vocabulary = RecognitionOptions { vocabulary: vec!["Grafana".to_string()], ..Default::default() };
+        let with_vocabulary = with_vocabulary.cache_key();
+        let key = CacheKey { file_unique_id: "AgADxyz", model: "ggml-base", options: &plain };
+
+        assert!(store.cached_transcript(&key).unwrap().is_none());
+
+        let transcript = Transcript {
+            segments: vec![Segment { start: 0.0, end: 2.0, text: "Привет".to_string(), speaker: None }],
+            language: Some("ru".to_string()),
+        };
+        store.cache_transcript(&key, 2.5, &transcript, 1_700_000_000).unwrap();
+
+        let cached = store.cached_transcript(&key).unwrap().expect("Transcript should be cached");
+        assert_eq!(cached.duration, 2.5);
+        assert_eq!(cached.transcript.segments, transcript.segments);
+        assert_eq!(cached.transcript.language.as_deref(), Some("ru"));
+
+        // Another model or other options are a miss
+        let other_model = CacheKey { model: "ggml-medium", ..key };
+        assert!(store.cached_transcript(&other_model).unwrap().is_none());
+        let other_options = CacheKey { options: &translated, ..key };
+        assert!(store.cached_transcript(&other_options).unwrap().is_none());
+        let other_vocabulary = CacheKey { options: &with_vocabulary, ..key };
+        assert!(store.cached_transcript(&other_vocabulary).unwrap().is_none());
+
+        assert_eq!(store.purge_cache().unwrap(), 1);
+        assert!(store.cached_transcript(&key).unwrap().is_none());
+    }
+}
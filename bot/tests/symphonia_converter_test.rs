@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use std::fs;
+    use voicebot::audio_conversion::audio_conversion::{convert_wav_to_samples, resample, AudioConverter, ConversionError};
+    use voicebot::converter_registry::converter_registry::ConverterRegistry;
+    use voicebot::speech_to_text::speech_to_text::WHISPER_INPUT;
+    use voicebot::symphonia_converter::symphonia_converter::SymphoniaConverter;
+
+    /// Signal to error ratio in dB of `decoded` against `reference`.
+    fn snr_db(reference: &[f32], decoded: &[f32]) -> f64 {
+        let (signal, error) = reference.iter().zip(decoded).fold((0.0, 0.0), |(signal, error), (&a, &b)| {
+            (signal + (a as f64).powi(2), error + (a as f64 - b as f64).powi(2))
+        });
+        10.0 * (signal / error).log10()
+    }
+
+    #[test]
+    fn test_mp3_matches_ffmpeg() {
+        let input = fs::read("test_assets/test.mp3").expect("Failed to read test MP3 file");
+        let audio = SymphoniaConverter.decode_audio(&input, &WHISPER_INPUT).expect("MP3 should decode");
+        assert_eq!(audio.describe_format(), "mp3/mp3, 16000 Hz, 1 ch");
+
+        // Another decoder and resampler than ffmpeg's, so the samples are
+        // close but not equal
+        let golden = convert_wav_to_samples(&fs::read("test_assets/golden_ffmpeg.wav").unwrap()).unwrap();
+        assert!(
+            audio.samples.len().abs_diff(golden.samples.len()) <= 16,
+            "{} samples instead of {}",
+            audio.samples.len(),
+            golden.samples.len()
+        );
+        let snr = snr_db(&golden.samples, &audio.samples);
+        assert!(snr > 35.0, "only {:.1} dB apart from ffmpeg's output", snr);
+    }
+
+    #[test]
+    fn test_registry_prefers_native_decoding() {
+        let input = fs::read("test_assets/test.mp3").unwrap();
+        let registry = ConverterRegistry::standard(&Default::default());
+        let audio = registry.decode(&input, Some("audio/mpeg"), &WHISPER_INPUT).expect("MP3 should decode without ffmpeg");
+        assert_eq!(audio.codec.as_deref(), Some("mp3"));
+
+        // Handed over without a copy, with the same result
+        let owned = registry.decode_bytes(input.clone().into(), Some("audio/mpeg"), &WHISPER_INPUT).unwrap();
+        assert_eq!(owned.samples, audio.samples);
+        assert_eq!(owned.container.as_deref(), Some("mp3"));
+    }
+
+    #[test]
+    fn test_decoding_stops_at_max_duration() {
+        let input = fs::read("test_assets/test.mp3").unwrap();
+        let audio = SymphoniaConverter.decode_audio(&input, &WHISPER_INPUT.with_max_duration(1)).unwrap();
+        assert_eq!(audio.duration, 1.0);
+    }
+
+    #[test]
+    fn test_broken_files() {
+        let full = SymphoniaConverter.decode_audio(&fs::read("test_assets/test.mp3").unwrap(), &WHISPER_INPUT).unwrap();
+
+        // Decodes up to where the file ends
+        let truncated = fs::read("test_assets/broken/truncated.mp3").unwrap();
+        let audio = SymphoniaConverter.decode_audio(&truncated, &WHISPER_INPUT).expect("Truncated file not decoded");
+        assert!(audio.duration > 0.0 && audio.duration < full.duration);
+
+        let garbage = fs::read("test_assets/broken/garbage.bin").unwrap();
+        assert!(matches!(
+            SymphoniaConverter.decode_audio(&garbage, &WHISPER_INPUT),
+            Err(ConversionError::UnsupportedFormat(_) | ConversionError::Decode { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resample() {
+        // A second of 440 Hz stereo at 44.1 kHz
+        let tone = |rate: u32| -> Vec<f32> {
+            (0..rate).flat_map(|i| {
+                let sample = 0.5 * (2.0 * PI * 440.0 * i as f32 / rate as f32).sin();
+                [sample, -sample]
+            }).collect()
+        };
+        let resampled = resample(&tone(44100), 2, 44100, 16000).unwrap();
+        assert_eq!(resampled.len(), 2 * 16000);
+
+        // Away from the edges it is the same tone at the new rate
+        let expected = tone(16000);
+        let snr = snr_db(&expected[2000..30000], &resampled[2000..30000]);
+        assert!(snr > 40.0, "only {:.1} dB apart", snr);
+
+        assert_eq!(resample(&[0.1, 0.2], 1, 16000, 16000).unwrap(), vec![0.1, 0.2]);
+    }
+}
@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::text_split::text_split::{split_message, utf16_len};
+
+    fn check_parts(parts: &[String], text: &str, max_units: usize) {
+        for part in parts {
+            assert!(utf16_len(part) <= max_units, "Part too long: {:?}", part);
+            assert_eq!(part.trim(), part);
+        }
+        // Nothing is lost or reordered, only whitespace between parts
+        let joined: Vec<&str> = parts.iter().flat_map(|part| part.split_whitespace()).collect();
+        let original: Vec<&str> = text.split_whitespace().collect();
+        assert_eq!(joined, original);
+    }
+
+    #[test]
+    fn test_short_text_is_one_part() {
+        assert_eq!(split_message("  Hello world.  ", 100), vec!["Hello world."]);
+        assert!(split_message("   ", 100).is_empty());
+    }
+
+    #[test]
+    fn test_english_splits_at_sentences() {
+        let text = "The first sentence is here. The second one follows it. And a third one ends the text.";
+        let parts = split_message(text, 60);
+        check_parts(&parts, text, 60);
+        assert_eq!(
+            parts,
+            vec!["The first sentence is here. The second one follows it.", "And a third one ends the text."]
+        );
+    }
+
+    #[test]
+    fn test_cyrillic_splits_at_sentences() {
+        let text = "Привет, это первое предложение. Второе предложение чуть длиннее первого! А третье? Конец.";
+        let parts = split_message(text, 50);
+        check_parts(&parts, text, 50);
+        assert_eq!(parts[0], "Привет, это первое предложение.");
+        // Every part ends at a sentence end
+        assert!(parts.iter().all(|part| part.ends_with(['.', '!', '?'])), "{:?}", parts);
+    }
+
+    #[test]
+    fn test_falls_back_to_word_boundaries() {
+        // No sentence ends at all, as whisper sometimes produces
+        let text = "слово ".repeat(100);
+        let parts = split_message(&text, 64);
+        check_parts(&parts, &text, 64);
+        assert!(parts.iter().all(|part| part.split(' ').all(|word| word == "слово")));
+
+        // A sentence end too early in the part is not worth a short message
+        let text = format!("Ok. {}", "word ".repeat(30));
+        let parts = split_message(&text, 40);
+        check_parts(&parts, &text, 40);
+        assert!(parts[0].len() > 10);
+    }
+
+    #[test]
+    fn test_long_words_are_cut_at_characters() {
+        let text = "ж".repeat(25);
+        let parts = split_message(&text, 10);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts.concat(), text);
+
+        // Emoji take two UTF-16 units and are never split in half
+        let text = "😀".repeat(7);
+        let parts = split_message(&text, 4);
+        assert_eq!(parts, vec!["😀😀", "😀😀", "😀😀", "😀"]);
+    }
+
+    #[test]
+    fn test_line_breaks_are_boundaries() {
+        let text = "first paragraph without a full stop\nsecond paragraph goes on here";
+        let parts = split_message(text, 50);
+        assert_eq!(parts, vec!["first paragraph without a full stop", "second paragraph goes on here"]);
+    }
+}
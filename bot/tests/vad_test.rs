@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::config::config::VadConfig;
+    use voicebot::vad::vad::{speech_duration, Vad};
+
+    const RATE: u32 = 16000;
+
+    /// A 200 Hz tone, loud enough to stand for voiced speech.
+    fn tone(secs: f64) -> Vec<f32> {
+        let n = (secs * RATE as f64) as usize;
+        (0..n).map(|i| 0.3 * (2.0 * std::f32::consts::PI * 200.0 * i as f32 / RATE as f32).sin()).collect()
+    }
+
+    /// Deterministic white-ish noise of the given amplitude.
+    fn noise(secs: f64, amplitude: f32) -> Vec<f32> {
+        let mut state: u32 = 12345;
+        (0..(secs * RATE as f64) as usize)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((state >> 16) as f32 / 32768.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn concat(parts: &[Vec<f32>]) -> Vec<f32> {
+        parts.concat()
+    }
+
+    #[test]
+    fn test_silence_has_no_speech() {
+        let vad = Vad::new(&VadConfig::default(), RATE);
+        assert!(vad.detect(&vec![0.0; RATE as usize * 3]).is_empty());
+        assert!(vad.detect(&noise(3.0, 0.001)).is_empty());
+        assert!(vad.detect(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_speech_region_between_silence() {
+        let vad = Vad::new(&VadConfig::default(), RATE);
+        let samples = concat(&[noise(1.0, 0.001), tone(2.0), noise(1.5, 0.001)]);
+
+        let regions = vad.detect(&samples);
+        assert_eq!(regions.len(), 1, "{:?}", regions);
+        assert!((regions[0].start - 1.0).abs() < 0.05, "{:?}", regions);
+        // The end includes the hangover
+        assert!(regions[0].end > 3.0 && regions[0].end < 3.4, "{:?}", regions);
+
+        let (trimmed, offset) = vad.trim(&samples, &regions);
+        assert!((offset - 0.8).abs() < 0.05);
+        assert!(trimmed.len() < samples.len());
+        assert!((trimmed.len() as f64 / RATE as f64 - speech_duration(&regions) - 0.4).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_hangover_joins_short_pauses() {
+        let vad = Vad::new(&VadConfig::default(), RATE);
+        let silence = |secs| vec![0.0; (secs * RATE as f64) as usize];
+
+        // A 150 ms pause is bridged, a one second pause is not
+        let samples =
+            concat(&[silence(0.5), tone(1.0), silence(0.15), tone(1.0), silence(1.0), tone(1.0), silence(0.5)]);
+        let regions = vad.detect(&samples);
+        assert_eq!(regions.len(), 2, "{:?}", regions);
+
+        // Clicks shorter than the minimum are ignored
+        let samples = concat(&[silence(1.0), tone(0.03), silence(1.0)]);
+        assert!(vad.detect(&samples).is_empty());
+    }
+
+    #[test]
+    fn test_loud_noise_is_not_speech() {
+        let vad = Vad::new(&VadConfig::default(), RATE);
+        // White noise crosses zero far more often than speech does
+        let samples = concat(&[vec![0.0; RATE as usize], noise(2.0, 0.3), vec![0.0; RATE as usize]]);
+        assert!(vad.detect(&samples).is_empty());
+    }
+
+    #[test]
+    fn test_continuous_speech_and_disabled_detector() {
+        let vad = Vad::new(&VadConfig::default(), RATE);
+        let regions = vad.detect(&tone(3.0));
+        assert_eq!(regions.len(), 1);
+        assert!((speech_duration(&regions) - 3.0).abs() < 0.05);
+
+        let config = VadConfig { enabled: false, ..VadConfig::default() };
+        let vad = Vad::new(&config, RATE);
+        let regions = vad.detect(&vec![0.0; RATE as usize]);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].end, 1.0);
+    }
+}
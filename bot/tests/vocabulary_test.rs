@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::speech_to_text::speech_to_text::Segment;
+    use voicebot::vocabulary::vocabulary::{correct, correct_segments, parse_terms, prompt};
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_terms() {
+        assert_eq!(
+            parse_terms(" Grafana,  Open   Telemetry ,,grafana\nЯндекс"),
+            terms(&["Grafana", "Open Telemetry", "Яндекс"])
+        );
+        assert!(parse_terms(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_prompt() {
+        assert_eq!(prompt(&terms(&["Grafana", "OpenTelemetry"])).as_deref(), Some("Grafana, OpenTelemetry."));
+        assert_eq!(prompt(&[]), None);
+    }
+
+    #[test]
+    fn test_near_misses_are_corrected() {
+        let vocabulary = terms(&["Kubernetes", "OpenTelemetry", "Grafana Loki", "Jira"]);
+        assert_eq!(
+            correct(" We moved to Kubernetis, with Open Telemetry.", &vocabulary).as_deref(),
+            Some(" We moved to Kubernetes, with OpenTelemetry.")
+        );
+        assert_eq!(
+            correct("Logs go to (grafana loky) now", &vocabulary).as_deref(),
+            Some("Logs go to (Grafana Loki) now")
+        );
+        // Short terms only get their case fixed
+        assert_eq!(correct("File it in jira.", &vocabulary).as_deref(), Some("File it in Jira."));
+        assert_eq!(correct("File it in Jara.", &vocabulary), None);
+    }
+
+    #[test]
+    fn test_other_words_are_kept() {
+        let vocabulary = terms(&["Grafana", "Kubernetes"]);
+        assert_eq!(correct(" We flew to Granada and back.", &vocabulary), None);
+        assert_eq!(correct(" Kubernetes is fine.", &vocabulary), None);
+        assert_eq!(correct("", &vocabulary), None);
+        assert_eq!(correct(" Anything", &[]), None);
+    }
+
+    #[test]
+    fn test_neighbouring_words_are_kept() {
+        let vocabulary = terms(&["Kubernetes", "Grafana Loki"]);
+        assert_eq!(
+            correct(" We run a kubernetes cluster, it kubernetes.", &vocabulary).as_deref(),
+            Some(" We run a Kubernetes cluster, it Kubernetes.")
+        );
+        assert_eq!(correct(" Ship to grafana loky now.", &vocabulary).as_deref(), Some(" Ship to Grafana Loki now."));
+    }
+
+    #[test]
+    fn test_cyrillic_terms() {
+        let vocabulary = terms(&["Тинькофф"]);
+        assert_eq!(correct("Перевёл в тинькоф.", &vocabulary).as_deref(), Some("Перевёл в Тинькофф."));
+    }
+
+    #[test]
+    fn test_correct_segments() {
+        let segment = |text: &str| Segment { start: 0.0, end: 1.0, text: text.to_string(), speaker: None };
+        let mut segments = vec![segment(" Deploy to Kubernetis."), segment(" Nothing here.")];
+        correct_segments(&mut segments, &terms(&["Kubernetes"]));
+        assert_eq!(segments[0].text, " Deploy to Kubernetes.");
+        assert_eq!(segments[1].text, " Nothing here.");
+    }
+}
@@ -0,0 +1,274 @@
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use std::io::{self, Read};
+    use voicebot::audio_conversion::audio_conversion::ConversionError;
+    use voicebot::wav::wav::read_wav;
+
+    #[derive(Debug, Clone, Copy)]
+    enum Encoding {
+        U8,
+        I16,
+        I24,
+        I32,
+        F32,
+        F64,
+    }
+
+    impl Encoding {
+        fn tag_and_bits(self) -> (u16, u16) {
+            match self {
+                Encoding::U8 => (1, 8),
+                Encoding::I16 => (1, 16),
+                Encoding::I24 => (1, 24),
+                Encoding::I32 => (1, 32),
+                Encoding::F32 => (3, 32),
+                Encoding::F64 => (3, 64),
+            }
+        }
+
+        /// The stored bytes of `sample` and the value a reader should return for them.
+        fn encode(self, sample: f64) -> (Vec<u8>, f32) {
+            match self {
+                Encoding::U8 => {
+                    let stored = (sample * 127.0).round() as i32 + 128;
+                    (vec![stored as u8], (stored - 128) as f32 / 128.0)
+                }
+                Encoding::I16 => {
+                    let stored = (sample * 32767.0).round() as i16;
+                    (stored.to_le_bytes().to_vec(), stored as f32 / 32768.0)
+                }
+                Encoding::I24 => {
+                    let stored = (sample * 8388607.0).round() as i32;
+                    (stored.to_le_bytes()[..3].to_vec(), stored as f32 / 8388608.0)
+                }
+                Encoding::I32 => {
+                    let stored = (sample * 2147483647.0).round() as i32;
+                    (stored.to_le_bytes().to_vec(), stored as f32 / 2147483648.0)
+                }
+                Encoding::F32 => ((sample as f32).to_le_bytes().to_vec(), sample as f32),
+                Encoding::F64 => (sample.to_le_bytes().to_vec(), sample as f32),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Wav {
+        encoding: Encoding,
+        channels: u16,
+        sample_rate: u32,
+        extensible: bool,
+        /// A chunk the reader has to skip, with an odd length to check the padding
+        extra_chunk: bool,
+        /// Data chunk size left as written by streaming encoders
+        unknown_size: bool,
+        samples: Vec<f64>,
+    }
+
+    impl Wav {
+        /// The file and the samples a reader should return.
+        fn build(&self) -> (Vec<u8>, Vec<f32>) {
+            let (tag, bits) = self.encoding.tag_and_bits();
+            let block_align = self.channels * bits / 8;
+
+            let mut fmt = Vec::new();
+            fmt.extend(if self.extensible { 0xFFFEu16 } else { tag }.to_le_bytes());
+            fmt.extend(self.channels.to_le_bytes());
+            fmt.extend(self.sample_rate.to_le_bytes());
+            fmt.extend((self.sample_rate * block_align as u32).to_le_bytes());
+            fmt.extend(block_align.to_le_bytes());
+            fmt.extend(bits.to_le_bytes());
+            if self.extensible {
+                fmt.extend(22u16.to_le_bytes());
+                fmt.extend(bits.to_le_bytes());
+                fmt.extend(0u32.to_le_bytes());
+                fmt.extend(tag.to_le_bytes());
+                fmt.extend([0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71]);
+            }
+
+            let mut data = Vec::new();
+            let mut expected = Vec::new();
+            for &sample in &self.samples {
+                let (bytes, value) = self.encoding.encode(sample);
+                data.extend(bytes);
+                expected.push(value);
+            }
+
+            let mut body = b"WAVE".to_vec();
+            chunk(&mut body, b"fmt ", &fmt);
+            if self.extra_chunk {
+                chunk(&mut body, b"LIST", b"INFOISFT\x03\x00\x00\x00ab\x00");
+            }
+            let data_size = if self.unknown_size { u32::MAX } else { data.len() as u32 };
+            body.extend(b"data");
+            body.extend(data_size.to_le_bytes());
+            body.extend(&data);
+
+            let mut file = b"RIFF".to_vec();
+            file.extend((body.len() as u32).to_le_bytes());
+            file.extend(body);
+            (file, expected)
+        }
+    }
+
+    fn chunk(out: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+        out.extend(id);
+        out.extend((body.len() as u32).to_le_bytes());
+        out.extend(body);
+        if body.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+
+    /// A reader returning at most `step` bytes at a time, like a pipe.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    fn wavs() -> impl Strategy<Value = Wav> {
+        let encoding = prop_oneof![
+            Just(Encoding::U8),
+            Just(Encoding::I16),
+            Just(Encoding::I24),
+            Just(Encoding::I32),
+            Just(Encoding::F32),
+            Just(Encoding::F64),
+        ];
+        (
+            encoding,
+            1u16..=4,
+            1u32..=192_000,
+            any::<bool>(),
+            any::<bool>(),
+            any::<bool>(),
+            prop::collection::vec(-1.0f64..=1.0, 0..600),
+        )
+            .prop_map(|(encoding, channels, sample_rate, extensible, extra_chunk, unknown_size, mut samples)| {
+                samples.truncate(samples.len() / channels as usize * channels as usize);
+                Wav { encoding, channels, sample_rate, extensible, extra_chunk, unknown_size, samples }
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn test_reads_every_format(wav in wavs(), step in 1usize..64) {
+            let (file, expected) = wav.build();
+            let frames = wav.samples.len() / wav.channels as usize;
+
+            let audio = read_wav(file.as_slice()).unwrap();
+            prop_assert_eq!(&audio.samples, &expected);
+            prop_assert_eq!(audio.duration, frames as f64 / wav.sample_rate as f64);
+            prop_assert_eq!(audio.sample_rate, wav.sample_rate);
+            prop_assert_eq!(audio.channels, wav.channels);
+
+            // The same from a reader that returns a few bytes at a time
+            let audio = read_wav(Trickle { data: &file, step }).unwrap();
+            prop_assert_eq!(&audio.samples, &expected);
+        }
+
+        #[test]
+        fn test_truncated_files_are_errors(wav in wavs(), cut in any::<prop::sample::Index>()) {
+            let wav = Wav { unknown_size: false, ..wav };
+            let (file, _) = wav.build();
+            let truncated = &file[..cut.index(file.len())];
+            prop_assert!(matches!(read_wav(truncated), Err(ConversionError::Wav(_))));
+        }
+
+        #[test]
+        fn test_garbage_does_not_panic(bytes in prop::collection::vec(any::<u8>(), 0..256), header in any::<bool>()) {
+            let mut file = if header { b"RIFF\x00\x00\x00\x00WAVE".to_vec() } else { Vec::new() };
+            file.extend(bytes);
+            let _ = read_wav(file.as_slice());
+        }
+    }
+
+    fn wav(encoding: Encoding, samples: &[f64]) -> Wav {
+        Wav {
+            encoding,
+            channels: 1,
+            sample_rate: 8000,
+            extensible: false,
+            extra_chunk: false,
+            unknown_size: false,
+            samples: samples.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_unsigned_8_bit() {
+        let (mut file, _) = wav(Encoding::U8, &[0.0, 0.0, 0.0]).build();
+        let data = file.len() - 3;
+        file[data..].copy_from_slice(&[0, 128, 255]);
+        let audio = read_wav(file.as_slice()).unwrap();
+        assert_eq!(audio.samples, vec![-1.0, 0.0, 127.0 / 128.0]);
+    }
+
+    #[test]
+    fn test_matches_hound() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut file = io::Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut file, spec).unwrap();
+        for sample in [0, 1, -1, 8388607, -8388608, 4096] {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let audio = read_wav(file.get_ref().as_slice()).unwrap();
+        let expected: Vec<f32> = [0, 1, -1, 8388607, -8388608, 4096].iter().map(|&s| s as f32 / 8388608.0).collect();
+        assert_eq!(audio.samples, expected);
+        assert_eq!(audio.duration, 3.0 / 44100.0);
+        assert_eq!(audio.container.as_deref(), Some("wav"));
+        assert_eq!(audio.codec.as_deref(), Some("pcm_s24le"));
+    }
+
+    #[test]
+    fn test_malformed_files() {
+        let (file, _) = wav(Encoding::I16, &[0.5, -0.5]).build();
+        let fmt = 12 + 8;
+
+        // 12-bit integer samples
+        let mut unsupported = file.clone();
+        unsupported[fmt + 14] = 12;
+        assert!(matches!(read_wav(unsupported.as_slice()), Err(ConversionError::UnsupportedFormat(_))));
+
+        // Compressed formats, e.g. MP3 in a WAV container
+        let mut compressed = file.clone();
+        compressed[fmt] = 0x55;
+        assert!(matches!(read_wav(compressed.as_slice()), Err(ConversionError::UnsupportedFormat(_))));
+
+        let mut bad_align = file.clone();
+        bad_align[fmt + 12] = 3;
+        assert!(matches!(read_wav(bad_align.as_slice()), Err(ConversionError::Wav(_))));
+
+        let mut no_channels = file.clone();
+        no_channels[fmt + 2] = 0;
+        assert!(matches!(read_wav(no_channels.as_slice()), Err(ConversionError::Wav(_))));
+
+        // A data chunk with half a sample frame
+        let mut odd_data = file.clone();
+        odd_data.push(0);
+        let data_size = odd_data.len() - 8 - 1;
+        odd_data[data_size..data_size + 4].copy_from_slice(&5u32.to_le_bytes());
+        assert!(matches!(read_wav(odd_data.as_slice()), Err(ConversionError::Wav(_))));
+
+        // No fmt chunk before the data
+        let mut no_fmt = file[..12].to_vec();
+        no_fmt.extend(&file[12 + 8 + 16..]);
+        assert!(matches!(read_wav(no_fmt.as_slice()), Err(ConversionError::Wav(_))));
+    }
+}
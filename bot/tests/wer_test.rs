@@ -0,0 +1,15 @@
+#[cfg(test)]
+mod tests {
+    use voicebot::wer::wer::word_error_rate;
+
+    #[test]
+    fn test_word_error_rate() {
+        assert_eq!(word_error_rate("Hello, world!", "hello world"), 0.0);
+        // One substitution, one deletion
+        assert_eq!(word_error_rate("the quick brown fox", "the quack fox"), 0.5);
+        // Insertions count as well
+        assert_eq!(word_error_rate("one two", "one two three four"), 1.0);
+        assert_eq!(word_error_rate("", ""), 0.0);
+        assert_eq!(word_error_rate("", "something"), 1.0);
+    }
+}
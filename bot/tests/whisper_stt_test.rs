@@ -1,8 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use hound::{SampleFormat, WavReader};
+    use hound::WavReader;
     use std::fs::File;
     use std::io::BufReader;
+    use voicebot::config::config::Config;
     use voicebot::speech_to_text::speech_to_text::WhisperSTT;
 
     fn to_lowercase_and_remove_punctuation(input: &str) -> String {
@@ -45,8 +46,9 @@ mod tests {
         };
 
 
-        // Create an instance of WhisperSTT
-        let whisper_stt = WhisperSTT::new(Option::None).unwrap();
+        // Create an instance of WhisperSTT, the model comes from the config file or GGML
+        let config = Config::load().expect("Failed to load configuration");
+        let whisper_stt = WhisperSTT::new(&config.whisper).unwrap();
 
         // Perform the speech-to-text recognition
         let result = to_lowercase_and_remove_punctuation(&whisper_stt.wav_to_text(&wav_data).expect("STT failed"));